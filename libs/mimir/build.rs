@@ -0,0 +1,5 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The messages and the service of the gRPC API of bragi.
+    tonic_build::compile_protos("proto/bragi.proto")?;
+    Ok(())
+}
@@ -1,11 +1,18 @@
 use crate::{ensure, utils::deserialize::deserialize_opt_duration};
 use cosmogony::ZoneType;
 use geojson::{GeoJson, Geometry};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use std::time::Duration;
 
-use crate::adapters::primary::common::{coord::Coord, filters::Filters};
+use crate::adapters::primary::common::{
+    coord::Coord,
+    explain::{ScoreBreakdown, ScoreComponent},
+    fields::is_known_field,
+    filters::{Filters, Fuzziness},
+    formats::Format,
+};
 use common::document::ContainerDocument;
 use places::{addr::Addr, admin::Admin, poi::Poi, stop::Stop, street::Street, PlaceDocType};
 
@@ -14,6 +21,8 @@ use super::routes::{is_valid_zone_type, Validate};
 pub const DEFAULT_LIMIT_RESULT_ES: i64 = 10;
 pub const DEFAULT_LIMIT_RESULT_REVERSE_API: i64 = 1;
 pub const DEFAULT_LANG: &str = "fr";
+// Each result of the autocomplete-explain endpoint is explained with its own request.
+pub const MAX_LIMIT_RESULT_EXPLAIN: i64 = 50;
 
 fn default_result_limit() -> i64 {
     DEFAULT_LIMIT_RESULT_ES
@@ -23,11 +32,7 @@ fn default_result_limit_reverse() -> i64 {
     DEFAULT_LIMIT_RESULT_REVERSE_API
 }
 
-fn default_lang() -> String {
-    DEFAULT_LANG.to_string()
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ForwardGeocoderExplainQuery {
     pub doc_id: String,
@@ -43,17 +48,32 @@ pub struct ForwardGeocoderExplainQuery {
     #[serde(default, rename = "type")]
     pub types: Option<Vec<Type>>,
     #[serde(default, rename = "zone_type")]
+    #[schemars(with = "Option<Vec<String>>")]
     pub zone_types: Option<Vec<ZoneType>>,
     pub poi_types: Option<Vec<String>>,
+    /// Number of results explained, at most 50.
     #[serde(default = "default_result_limit")]
     pub limit: i64,
-    #[serde(default = "default_lang")]
-    pub lang: String,
+    /// If absent, the language is negotiated with the Accept-Language header,
+    /// and defaults to 'fr'.
+    pub lang: Option<String>,
+    /// The names and labels in each of these languages are added to the response.
+    pub include_langs: Option<Vec<String>>,
+    /// Timeout in milliseconds.
     #[serde(deserialize_with = "deserialize_opt_duration", default)]
+    #[schemars(with = "Option<u64>")]
     pub timeout: Option<Duration>,
     pub pt_dataset: Option<Vec<String>>,
     pub poi_dataset: Option<Vec<String>>,
     pub request_id: Option<String>,
+    /// The ranking profile, defined in the query settings.
+    pub profile: Option<String>,
+    /// The number of typos tolerated in each word ('auto', '0', '1' or '2').
+    #[schemars(with = "Option<String>")]
+    pub fuzziness: Option<Fuzziness>,
+    #[serde(default, with = "serde_with::rust::display_fromstr")]
+    #[schemars(with = "bool")]
+    pub phonetic: bool,
     #[serde(flatten)]
     pub proximity: Option<Proximity>,
 }
@@ -70,10 +90,14 @@ impl From<ForwardGeocoderExplainQuery> for ForwardGeocoderQuery {
             poi_types,
             limit,
             lang,
+            include_langs,
             timeout,
             pt_dataset,
             poi_dataset,
             request_id,
+            profile,
+            fuzziness,
+            phonetic,
             proximity,
             ..
         } = val;
@@ -88,10 +112,19 @@ impl From<ForwardGeocoderExplainQuery> for ForwardGeocoderQuery {
             poi_types,
             limit,
             lang,
+            include_langs,
             timeout,
             pt_dataset,
             poi_dataset,
             request_id,
+            profile,
+            fuzziness,
+            phonetic,
+            format: None,
+            fields: None,
+            exclude_fields: None,
+            with_geometry: None,
+            tolerance: None,
             proximity,
         }
     }
@@ -110,6 +143,9 @@ impl Validate for ForwardGeocoderExplainQuery {
 
             self.lon.map(|lon| (-180f32..=180f32).contains(&lon)).unwrap_or(true),
                 "lon must be in [-180, 180]";
+
+            (1..=MAX_LIMIT_RESULT_EXPLAIN).contains(&self.limit),
+                "limit must be in [1, 50]";
         }
     }
 }
@@ -118,7 +154,7 @@ impl Validate for ForwardGeocoderExplainQuery {
 /// can be submitted for the autocomplete endpoint.
 ///
 /// Only the `q` parameter is mandatory.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ForwardGeocoderQuery {
     #[serde(default)]
@@ -129,17 +165,42 @@ pub struct ForwardGeocoderQuery {
     #[serde(default, rename = "type")]
     pub types: Option<Vec<Type>>,
     #[serde(default, rename = "zone_type")]
+    #[schemars(with = "Option<Vec<String>>")]
     pub zone_types: Option<Vec<ZoneType>>,
     pub poi_types: Option<Vec<String>>,
     #[serde(default = "default_result_limit")]
     pub limit: i64,
-    #[serde(default = "default_lang")]
-    pub lang: String,
+    /// If absent, the language is negotiated with the Accept-Language header,
+    /// and defaults to 'fr'.
+    pub lang: Option<String>,
+    /// The names and labels in each of these languages are added to the response.
+    pub include_langs: Option<Vec<String>>,
+    /// Timeout in milliseconds.
     #[serde(deserialize_with = "deserialize_opt_duration", default)]
+    #[schemars(with = "Option<u64>")]
     pub timeout: Option<Duration>,
     pub pt_dataset: Option<Vec<String>>,
     pub poi_dataset: Option<Vec<String>>,
     pub request_id: Option<String>,
+    /// The ranking profile, defined in the query settings.
+    pub profile: Option<String>,
+    /// The number of typos tolerated in each word ('auto', '0', '1' or '2').
+    #[schemars(with = "Option<String>")]
+    pub fuzziness: Option<Fuzziness>,
+    #[serde(default, with = "serde_with::rust::display_fromstr")]
+    #[schemars(with = "bool")]
+    pub phonetic: bool,
+    /// Format of the response, which takes precedence over the Accept header.
+    pub format: Option<Format>,
+    /// Only these properties of the places are returned, along with their id and type.
+    pub fields: Option<Vec<String>>,
+    /// These properties of the places are not returned.
+    pub exclude_fields: Option<Vec<String>>,
+    /// With 'boundary', the admins have their boundary as geometry, instead of their location
+    /// (search only).
+    pub with_geometry: Option<WithGeometry>,
+    /// Tolerance, in degrees, with which the boundaries are simplified.
+    pub tolerance: Option<f64>,
     #[serde(flatten)]
     pub proximity: Option<Proximity>,
 }
@@ -184,6 +245,8 @@ impl From<(ForwardGeocoderQuery, Option<Geometry>)> for Filters {
             limit: query.limit,
             timeout: query.timeout,
             proximity: query.proximity,
+            fuzziness: query.fuzziness,
+            phonetic: query.phonetic,
         }
     }
 }
@@ -204,63 +267,191 @@ impl Validate for ForwardGeocoderQuery {
 
             is_valid_zone_type(self),
                 "'zone_type' must be specified when you query with 'type' parameter 'zone'";
+
+            are_known_fields(&self.fields, &self.exclude_fields),
+                "'fields' and 'exclude_fields' must be properties of the places";
+
+            is_valid_tolerance(self.tolerance),
+                "tolerance must be a positive number";
         }
     }
 }
 
 /// This structure contains all the query parameters that
 /// can be submitted for the reverse endpoint.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ReverseGeocoderQuery {
     pub lat: f64,
     pub lon: f64,
     #[serde(default = "default_result_limit_reverse")]
     pub limit: i64,
+    /// Timeout in milliseconds.
+    #[serde(deserialize_with = "deserialize_opt_duration", default)]
+    #[schemars(with = "Option<u64>")]
+    pub timeout: Option<Duration>,
+    /// The ranking profile, defined in the query settings.
+    pub profile: Option<String>,
+    /// Format of the response, which takes precedence over the Accept header.
+    pub format: Option<Format>,
+    /// Only these properties of the places are returned, along with their id and type.
+    pub fields: Option<Vec<String>>,
+    /// These properties of the places are not returned.
+    pub exclude_fields: Option<Vec<String>>,
+}
+
+impl Validate for ReverseGeocoderQuery {
+    fn filter(&self) -> Result<(), warp::Rejection> {
+        ensure! {
+            are_known_fields(&self.fields, &self.exclude_fields),
+                "'fields' and 'exclude_fields' must be properties of the places";
+        }
+    }
+}
+
+/// This structure contains all the query parameters that
+/// can be submitted for the features endpoint.
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct FeaturesQuery {
+    /// If absent, the language is negotiated with the Accept-Language header,
+    /// and defaults to 'fr'.
+    pub lang: Option<String>,
+    /// Timeout in milliseconds.
     #[serde(deserialize_with = "deserialize_opt_duration", default)]
+    #[schemars(with = "Option<u64>")]
     pub timeout: Option<Duration>,
+    pub pt_dataset: Option<Vec<String>>,
+    pub poi_dataset: Option<Vec<String>>,
+    pub request_id: Option<String>,
+    /// Format of the response, which takes precedence over the Accept header.
+    pub format: Option<Format>,
+    /// With 'boundary', an admin has its boundary as geometry, instead of its location.
+    pub with_geometry: Option<WithGeometry>,
+    /// Tolerance, in degrees, with which the boundary is simplified.
+    pub tolerance: Option<f64>,
+    /// Only these properties of the places are returned, along with their id and type.
+    pub fields: Option<Vec<String>>,
+    /// These properties of the places are not returned.
+    pub exclude_fields: Option<Vec<String>>,
+}
+
+impl Validate for FeaturesQuery {
+    fn filter(&self) -> Result<(), warp::Rejection> {
+        ensure! {
+            is_valid_tolerance(self.tolerance),
+                "tolerance must be a positive number";
+
+            are_known_fields(&self.fields, &self.exclude_fields),
+                "'fields' and 'exclude_fields' must be properties of the places";
+        }
+    }
 }
 
-impl Validate for ReverseGeocoderQuery {}
+/// Geometry of the places, instead of their location.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum WithGeometry {
+    /// The boundary of the admins, the other places keep their location.
+    Boundary,
+}
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+fn are_known_fields(fields: &Option<Vec<String>>, exclude_fields: &Option<Vec<String>>) -> bool {
+    fields
+        .iter()
+        .chain(exclude_fields.iter())
+        .flatten()
+        .all(|field| is_known_field(field))
+}
+
+fn is_valid_tolerance(tolerance: Option<f64>) -> bool {
+    tolerance.map_or(true, |tolerance| tolerance.is_finite() && tolerance > 0.0)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct ForwardGeocoderBody {
+    /// A GeoJSON feature, whose geometry restricts the search.
+    #[schemars(with = "JsonValue")]
     pub shape: GeoJson,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ExplainResponseBody {
+    /// The explanation of Elasticsearch for the score of the document.
     pub explanation: JsonValue,
+    /// The labels given to the words of the query.
+    pub parsed_query: JsonValue,
+    /// The score of the document, by clause of the query.
+    pub breakdown: ScoreBreakdown,
+    /// Rank of the document among the results of the query, starting at 1, if it is one of
+    /// them.
+    pub rank: Option<usize>,
+    /// The results of the query, in their order, with their score by clause.
+    pub results: Vec<ExplainedResult>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub warnings: Vec<String>,
 }
 
-impl From<JsonValue> for ExplainResponseBody {
-    fn from(explanation: JsonValue) -> Self {
-        ExplainResponseBody { explanation }
-    }
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ExplainedResult {
+    pub id: String,
+    pub doc_type: String,
+    pub breakdown: ScoreBreakdown,
+    /// For the results ranked before the document, the clauses adding more to their score, by
+    /// decreasing difference.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub lead: Vec<ScoreComponent>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct BragiStatus {
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct MimirStatus {
     pub version: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct ElasticsearchStatus {
     pub version: String,
     pub health: String,
     pub url: String,
+    pub nodes: Vec<ElasticsearchNodeStatus>,
+    pub indices: Vec<ElasticsearchIndexStatus>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ElasticsearchNodeStatus {
+    pub url: String,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ElasticsearchIndexStatus {
+    pub index: String,
+    pub doc_type: String,
+    pub dataset: String,
+    pub docs_count: u64,
+    pub indexed_at: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ProfilesResponseBody {
+    pub profiles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct StatusResponseBody {
     pub bragi: BragiStatus,
@@ -268,7 +459,28 @@ pub struct StatusResponseBody {
     pub elasticsearch: ElasticsearchStatus,
 }
 
-#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct LivenessResponseBody {
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReadinessCheck {
+    pub name: String,
+    pub ok: bool,
+    pub details: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct ReadinessResponseBody {
+    pub ready: bool,
+    pub checks: Vec<ReadinessCheck>,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub enum Type {
     #[serde(rename = "house")]
     House,
@@ -308,15 +520,18 @@ impl Type {
     }
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
 pub struct Proximity {
     #[serde(with = "serde_with::rust::display_fromstr")]
+    #[schemars(with = "f64")]
     #[serde(rename = "proximity_scale")]
     pub scale: f64,
     #[serde(with = "serde_with::rust::display_fromstr")]
+    #[schemars(with = "f64")]
     #[serde(rename = "proximity_offset")]
     pub offset: f64,
     #[serde(with = "serde_with::rust::display_fromstr")]
+    #[schemars(with = "f64")]
     #[serde(rename = "proximity_decay")]
     pub decay: f64,
 }
@@ -0,0 +1,339 @@
+//! API keys identifying the clients of bragi, each with its own rate limit and daily quota.
+//!
+//! The rate limit is a token bucket: a client may send `burst` requests at once, and then
+//! `rate` requests per second. The daily quota is reset at midnight (UTC).
+
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::{info, warn};
+
+use crate::utils::deserialize::deserialize_duration;
+
+/// Client of the requests without a key, when keys are not required.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Client of the requests with an unknown key.
+pub const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read API keys file {}: {}", path.display(), source))]
+    ReadKeys {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Could not parse API keys file {}: {}", path.display(), source))]
+    ParseKeys {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiKey {
+    #[serde(skip_serializing)]
+    pub key: String,
+    /// Name of the client, in the logs and the metrics.
+    pub client: String,
+    /// Requests per second, unlimited if not given.
+    pub rate: Option<f64>,
+    /// Requests which can be sent at once, which defaults to the rate.
+    pub burst: Option<f64>,
+    /// Requests per day, unlimited if not given.
+    pub daily_quota: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeysSettings {
+    /// Rejects the requests without a key. Otherwise they are served without limits.
+    pub required: bool,
+    /// Header carrying the key.
+    pub header: String,
+    /// Query parameter carrying the key, when it is not in the header.
+    pub query_param: String,
+    pub keys: Vec<ApiKey>,
+    /// TOML file with more keys, as a list 'keys', which is read again when it changes.
+    pub keys_file: Option<PathBuf>,
+    /// Interval between two checks of the keys file.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub reload_interval: Duration,
+}
+
+impl Default for ApiKeysSettings {
+    fn default() -> Self {
+        ApiKeysSettings {
+            required: false,
+            header: String::from("x-api-key"),
+            query_param: String::from("api_key"),
+            keys: Vec::new(),
+            keys_file: None,
+            reload_interval: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct KeysFile {
+    #[serde(default)]
+    keys: Vec<ApiKey>,
+}
+
+/// Why a request is refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Refusal {
+    MissingKey,
+    InvalidKey,
+    RateLimited { retry_after: Duration },
+    QuotaExceeded { retry_after: Duration },
+}
+
+// The usage of a key: the tokens of its bucket, and its requests of the day.
+#[derive(Debug)]
+struct Usage {
+    tokens: f64,
+    updated: Instant,
+    day: NaiveDate,
+    requests: u64,
+}
+
+/// The keys, along with their usage, shared by all the requests.
+#[derive(Debug)]
+pub struct ApiKeys {
+    pub settings: ApiKeysSettings,
+    keys: RwLock<HashMap<String, ApiKey>>,
+    usage: Mutex<HashMap<String, Usage>>,
+}
+
+impl ApiKeys {
+    /// Reads the keys of the settings and of the keys file.
+    pub fn new(settings: ApiKeysSettings) -> Result<Self, Error> {
+        let keys = load_keys(&settings)?;
+        Ok(ApiKeys {
+            settings,
+            keys: RwLock::new(keys),
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Reads the keys file again each time it changes, which must be done from within a tokio
+    /// runtime. An invalid file is logged, and the previous keys are kept.
+    pub fn watch(self: &Arc<Self>) {
+        let path = match &self.settings.keys_file {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let keys = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut modified = modified(&path);
+            loop {
+                tokio::time::sleep(keys.settings.reload_interval).await;
+                let current = modified(&path);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+                match load_keys(&keys.settings) {
+                    Ok(loaded) => {
+                        info!("Reloaded {} API keys from {}", loaded.len(), path.display());
+                        *keys.keys.write().expect("API keys lock poisoned") = loaded;
+                    }
+                    Err(err) => warn!("Keeping the previous API keys: {}", err),
+                }
+            }
+        });
+    }
+
+    /// The key carried by the request, in the header or else in the query.
+    pub fn key_of(&self, header: Option<&str>, query: &str) -> Option<String> {
+        header.map(String::from).or_else(|| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .find(|(name, _)| name == self.settings.query_param.as_str())
+                .map(|(_, value)| value.into_owned())
+        })
+    }
+
+    /// The name of the client of the key, for the metrics.
+    pub fn client(&self, key: Option<&str>) -> String {
+        match key {
+            None => String::from(ANONYMOUS),
+            Some(key) => self
+                .keys
+                .read()
+                .expect("API keys lock poisoned")
+                .get(key)
+                .map_or_else(|| String::from(UNKNOWN), |key| key.client.clone()),
+        }
+    }
+
+    /// Counts a request of the key, and returns its client if the request can be served.
+    pub fn admit(&self, key: Option<&str>) -> Result<String, Refusal> {
+        self.admit_at(key, Instant::now(), Utc::now().naive_utc())
+    }
+
+    fn admit_at(
+        &self,
+        key: Option<&str>,
+        now: Instant,
+        utc: NaiveDateTime,
+    ) -> Result<String, Refusal> {
+        let key = match key {
+            Some(key) => key,
+            None if self.settings.required => return Err(Refusal::MissingKey),
+            None => return Ok(String::from(ANONYMOUS)),
+        };
+        let api_key = self
+            .keys
+            .read()
+            .expect("API keys lock poisoned")
+            .get(key)
+            .cloned()
+            .ok_or(Refusal::InvalidKey)?;
+
+        let capacity = api_key.burst.or(api_key.rate).unwrap_or(1.0).max(1.0);
+        let today = utc.date();
+        let mut usage = self.usage.lock().expect("API keys usage lock poisoned");
+        let usage = usage.entry(key.to_string()).or_insert(Usage {
+            tokens: capacity,
+            updated: now,
+            day: today,
+            requests: 0,
+        });
+
+        if usage.day != today {
+            usage.day = today;
+            usage.requests = 0;
+        }
+        if let Some(quota) = api_key.daily_quota {
+            if usage.requests >= quota {
+                let midnight = today.succ().and_hms(0, 0, 0);
+                let retry_after = (midnight - utc).to_std().unwrap_or_default();
+                return Err(Refusal::QuotaExceeded { retry_after });
+            }
+        }
+
+        if let Some(rate) = api_key.rate {
+            let elapsed = now.saturating_duration_since(usage.updated).as_secs_f64();
+            usage.tokens = (usage.tokens + elapsed * rate).min(capacity);
+            usage.updated = now;
+            if usage.tokens < 1.0 {
+                let retry_after = Duration::from_secs_f64((1.0 - usage.tokens) / rate);
+                return Err(Refusal::RateLimited { retry_after });
+            }
+            usage.tokens -= 1.0;
+        }
+
+        usage.requests += 1;
+        Ok(api_key.client)
+    }
+}
+
+// The keys of the file override the ones of the settings.
+fn load_keys(settings: &ApiKeysSettings) -> Result<HashMap<String, ApiKey>, Error> {
+    let mut keys: HashMap<_, _> = settings
+        .keys
+        .iter()
+        .map(|key| (key.key.clone(), key.clone()))
+        .collect();
+
+    if let Some(path) = &settings.keys_file {
+        let content = std::fs::read_to_string(path).context(ReadKeysSnafu { path })?;
+        let file: KeysFile = toml::from_str(&content).context(ParseKeysSnafu { path })?;
+        keys.extend(file.keys.into_iter().map(|key| (key.key.clone(), key)));
+    }
+
+    Ok(keys)
+}
+
+fn modified(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_keys(required: bool) -> ApiKeys {
+        ApiKeys::new(ApiKeysSettings {
+            required,
+            keys: vec![ApiKey {
+                key: String::from("secret"),
+                client: String::from("navitia"),
+                rate: Some(2.0),
+                burst: Some(3.0),
+                daily_quota: Some(5),
+            }],
+            ..ApiKeysSettings::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn should_identify_the_client_of_the_key() {
+        let keys = api_keys(false);
+
+        assert_eq!(
+            keys.key_of(None, "q=paris&api_key=secret"),
+            Some(String::from("secret"))
+        );
+        assert_eq!(
+            keys.key_of(Some("other"), "api_key=secret"),
+            Some(String::from("other"))
+        );
+        assert_eq!(keys.admit(Some("secret")), Ok(String::from("navitia")));
+        assert_eq!(keys.admit(None), Ok(String::from(ANONYMOUS)));
+        assert_eq!(keys.admit(Some("other")), Err(Refusal::InvalidKey));
+        assert_eq!(api_keys(true).admit(None), Err(Refusal::MissingKey));
+    }
+
+    #[test]
+    fn should_limit_the_rate_after_the_burst() {
+        let keys = api_keys(false);
+        let start = Instant::now();
+        let utc = NaiveDate::from_ymd(2022, 1, 1).and_hms(12, 0, 0);
+
+        for _ in 0..3 {
+            assert!(keys.admit_at(Some("secret"), start, utc).is_ok());
+        }
+        assert_eq!(
+            keys.admit_at(Some("secret"), start, utc),
+            Err(Refusal::RateLimited {
+                retry_after: Duration::from_millis(500)
+            })
+        );
+        // A token is back after half a second.
+        let later = start + Duration::from_millis(500);
+        assert!(keys.admit_at(Some("secret"), later, utc).is_ok());
+    }
+
+    #[test]
+    fn should_reset_the_quota_at_midnight() {
+        let keys = api_keys(false);
+        let mut now = Instant::now();
+        let utc = NaiveDate::from_ymd(2022, 1, 1).and_hms(23, 0, 0);
+
+        for _ in 0..5 {
+            now += Duration::from_secs(1);
+            assert!(keys.admit_at(Some("secret"), now, utc).is_ok());
+        }
+        now += Duration::from_secs(1);
+        assert_eq!(
+            keys.admit_at(Some("secret"), now, utc),
+            Err(Refusal::QuotaExceeded {
+                retry_after: Duration::from_secs(3600)
+            })
+        );
+        let tomorrow = NaiveDate::from_ymd(2022, 1, 2).and_hms(0, 0, 1);
+        assert!(keys.admit_at(Some("secret"), now, tomorrow).is_ok());
+    }
+}
@@ -0,0 +1,418 @@
+use futures::future::join_all;
+use geojson::{Geometry, Position};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use snafu::Snafu;
+use std::{cmp::Ordering, time::Duration};
+use url::Url;
+
+use crate::{
+    adapters::secondary::elasticsearch::{
+        ElasticsearchStorageAuthConfig, ElasticsearchStorageConfig,
+        ElasticsearchStorageNodesConfig, ElasticsearchStorageTlsConfig,
+    },
+    domain::{
+        model::{configuration::INDEX_ROOT, query::Query},
+        ports::primary::search_documents::SearchDocuments,
+    },
+    utils::deserialize::deserialize_opt_duration,
+};
+
+/// Name of the cluster described by the 'elasticsearch' section.
+pub const DEFAULT_CLUSTER: &str = "default";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("cluster '{}' failed: {}", name, details))]
+    ClusterSearch { name: String, details: String },
+
+    #[snafu(display("cluster '{}' did not answer within {:?}", name, timeout))]
+    ClusterTimeout { name: String, timeout: Duration },
+}
+
+/// Rules telling which requests are sent to a cluster. An empty rule matches every request.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClusterRouting {
+    /// Datasets of pois and stops held by the cluster.
+    pub datasets: Vec<String>,
+    /// Types of documents held by the cluster ('addr', 'street', 'admin', 'poi', 'stop').
+    pub doc_types: Vec<String>,
+    /// Area covered by the cluster, as [min_lon, min_lat, max_lon, max_lat]. The requests about
+    /// another area (reverse requests, forward requests with a shape or coordinates) skip the
+    /// cluster.
+    pub bbox: Option<[f64; 4]>,
+}
+
+impl ClusterRouting {
+    /// Keeps the indices held by the cluster.
+    pub fn indices(&self, indices: &[String]) -> Vec<String> {
+        indices
+            .iter()
+            .filter(|index| self.holds(index))
+            .cloned()
+            .collect()
+    }
+
+    // Index names are either '<root>_<doc_type>' or '<root>_<doc_type>_<dataset>'. The
+    // indices without dataset are held by every cluster with the right doc type.
+    fn holds(&self, index: &str) -> bool {
+        let name = match index
+            .strip_prefix(INDEX_ROOT)
+            .and_then(|name| name.strip_prefix('_'))
+        {
+            Some(name) => name,
+            None => return true,
+        };
+        let (doc_type, dataset) = match name.split_once('_') {
+            Some((doc_type, dataset)) => (doc_type, Some(dataset)),
+            None => (name, None),
+        };
+
+        let doc_type_held =
+            self.doc_types.is_empty() || self.doc_types.iter().any(|t| t == doc_type);
+        let dataset_held = self.datasets.is_empty()
+            || dataset.map_or(true, |dataset| self.datasets.iter().any(|d| d == dataset));
+        doc_type_held && dataset_held
+    }
+
+    /// Whether the point lies in the area covered by the cluster.
+    pub fn covers(&self, lon: f64, lat: f64) -> bool {
+        self.bbox
+            .map_or(true, |[min_lon, min_lat, max_lon, max_lat]| {
+                (min_lon..=max_lon).contains(&lon) && (min_lat..=max_lat).contains(&lat)
+            })
+    }
+
+    /// Whether the area overlaps the one covered by the cluster.
+    pub fn covers_area(&self, area: &Area) -> bool {
+        match *area {
+            Area::Point(lon, lat) => self.covers(lon, lat),
+            Area::BBox([min_lon, min_lat, max_lon, max_lat]) => self.bbox.map_or(true, |bbox| {
+                min_lon <= bbox[2] && bbox[0] <= max_lon && min_lat <= bbox[3] && bbox[1] <= max_lat
+            }),
+        }
+    }
+}
+
+/// The part of the world a request is about, used to skip the clusters which do not cover it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Area {
+    /// The point of a reverse request, or the coordinates of a forward request, as (lon, lat).
+    Point(f64, f64),
+    /// The bounding box of the shape of a forward request, as
+    /// [min_lon, min_lat, max_lon, max_lat].
+    BBox([f64; 4]),
+}
+
+impl Area {
+    /// The bounding box of a shape, if it has any position.
+    pub fn of_shape(shape: &Geometry) -> Option<Self> {
+        positions(&shape.value)
+            .into_iter()
+            .filter(|position| position.len() >= 2)
+            .fold(None, |bbox, position| {
+                let (lon, lat) = (position[0], position[1]);
+                Some(match bbox {
+                    None => [lon, lat, lon, lat],
+                    Some([min_lon, min_lat, max_lon, max_lat]) => [
+                        f64::min(min_lon, lon),
+                        f64::min(min_lat, lat),
+                        f64::max(max_lon, lon),
+                        f64::max(max_lat, lat),
+                    ],
+                })
+            })
+            .map(Area::BBox)
+    }
+}
+
+fn positions(value: &geojson::Value) -> Vec<&Position> {
+    match value {
+        geojson::Value::Point(position) => vec![position],
+        geojson::Value::MultiPoint(positions) | geojson::Value::LineString(positions) => {
+            positions.iter().collect()
+        }
+        geojson::Value::MultiLineString(lines) | geojson::Value::Polygon(lines) => {
+            lines.iter().flatten().collect()
+        }
+        geojson::Value::MultiPolygon(polygons) => polygons.iter().flatten().flatten().collect(),
+        geojson::Value::GeometryCollection(geometries) => geometries
+            .iter()
+            .flat_map(|geometry| positions(&geometry.value))
+            .collect(),
+    }
+}
+
+/// An additional cluster, queried along with the one of the 'elasticsearch' section. The
+/// settings which are not given here are taken from the 'elasticsearch' section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterSettings {
+    pub name: String,
+    pub url: Url,
+    #[serde(default)]
+    pub auth: Option<ElasticsearchStorageAuthConfig>,
+    #[serde(default)]
+    pub tls: ElasticsearchStorageTlsConfig,
+    #[serde(default)]
+    pub nodes: ElasticsearchStorageNodesConfig,
+    #[serde(default)]
+    pub routing: ClusterRouting,
+    /// Maximum time to wait for the cluster. Past this delay, the results are returned without
+    /// the ones of this cluster.
+    #[serde(default, deserialize_with = "deserialize_opt_duration")]
+    pub timeout: Option<Duration>,
+    /// Factor by which the scores of the cluster are multiplied before being merged with the
+    /// scores of the other clusters, which depend on the statistics of the indices of each
+    /// cluster. The scores of the cluster of the 'elasticsearch' section are kept as is.
+    #[serde(default = "default_score_factor")]
+    pub score_factor: f64,
+}
+
+/// The scores of a cluster are kept as is by default.
+pub fn default_score_factor() -> f64 {
+    1.0
+}
+
+impl ClusterSettings {
+    pub fn elasticsearch(&self, base: &ElasticsearchStorageConfig) -> ElasticsearchStorageConfig {
+        ElasticsearchStorageConfig {
+            url: self.url.clone(),
+            auth: self.auth.clone(),
+            tls: self.tls.clone(),
+            nodes: self.nodes.clone(),
+            ..base.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cluster<C> {
+    pub name: String,
+    pub client: C,
+    pub routing: ClusterRouting,
+    pub timeout: Option<Duration>,
+    pub score_factor: f64,
+}
+
+/// The documents found in all the clusters, along with a warning for each cluster which
+/// failed to answer.
+#[derive(Debug)]
+pub struct FanOut<D> {
+    pub documents: Vec<(f64, D)>,
+    pub warnings: Vec<String>,
+}
+
+/// Sends the query to every cluster holding some of the indices (and covering the area, if
+/// any). The scores are multiplied by the score factor of each cluster, so that the documents of
+/// the different clusters can be merged by score. This fails only if all of these clusters fail.
+pub async fn search<C, D>(
+    clusters: &[Cluster<C>],
+    indices: &[String],
+    area: Option<Area>,
+    query: &Value,
+    limit: i64,
+    timeout: Duration,
+) -> Result<FanOut<D>, Error>
+where
+    C: SearchDocuments + Send + Sync,
+    D: serde::de::DeserializeOwned + Send + Sync + 'static,
+{
+    let searches = clusters
+        .iter()
+        .filter(|cluster| {
+            area.as_ref()
+                .map_or(true, |area| cluster.routing.covers_area(area))
+        })
+        .filter_map(|cluster| {
+            let indices = cluster.routing.indices(indices);
+            if indices.is_empty() {
+                None
+            } else {
+                Some(search_cluster(cluster, indices, query, limit, timeout))
+            }
+        });
+
+    let mut documents = Vec::new();
+    let mut errors = Vec::new();
+    let mut answered = false;
+    for res in join_all(searches).await {
+        match res {
+            Ok(docs) => {
+                answered = true;
+                documents.extend(docs);
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+
+    if !answered && !errors.is_empty() {
+        return Err(errors.remove(0));
+    }
+
+    let warnings = errors.iter().map(ToString::to_string).collect();
+    Ok(FanOut {
+        documents,
+        warnings,
+    })
+}
+
+// Errors are turned into strings right away, so that the future stays Send while the other
+// clusters are awaited.
+async fn search_cluster<C, D>(
+    cluster: &Cluster<C>,
+    indices: Vec<String>,
+    query: &Value,
+    limit: i64,
+    timeout: Duration,
+) -> Result<Vec<(f64, D)>, Error>
+where
+    C: SearchDocuments + Send + Sync,
+    D: serde::de::DeserializeOwned + Send + Sync + 'static,
+{
+    let search = cluster.client.search_scored_documents(
+        indices,
+        Query::QueryDSL(query.clone()),
+        limit,
+        Some(timeout),
+    );
+
+    let res = match cluster.timeout {
+        Some(cluster_timeout) => match tokio::time::timeout(cluster_timeout, search).await {
+            Ok(res) => res,
+            Err(_) => {
+                return Err(Error::ClusterTimeout {
+                    name: cluster.name.clone(),
+                    timeout: cluster_timeout,
+                })
+            }
+        },
+        None => search.await,
+    };
+
+    res.map(|docs| calibrate(docs, cluster.score_factor))
+        .map_err(|err| Error::ClusterSearch {
+            name: cluster.name.clone(),
+            details: err.to_string(),
+        })
+}
+
+// The raw scores are kept, so that a weak match stays weak next to the results of the other
+// clusters, and are only scaled by the factor calibrating the cluster.
+fn calibrate<D>(mut documents: Vec<(f64, D)>, score_factor: f64) -> Vec<(f64, D)> {
+    for (score, _) in documents.iter_mut() {
+        *score *= score_factor;
+    }
+    documents
+}
+
+/// Merges the documents of several clusters, best scores first. The documents with the same
+/// score keep their order.
+pub fn by_score<D>(mut documents: Vec<(f64, D)>, limit: usize) -> Vec<D> {
+    documents.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(Ordering::Equal));
+    documents
+        .into_iter()
+        .take(limit)
+        .map(|(_, doc)| doc)
+        .collect()
+}
+
+/// Merges the documents of several clusters, smallest distances first.
+pub fn by_distance<D, F>(documents: Vec<(f64, D)>, limit: usize, distance: F) -> Vec<D>
+where
+    F: Fn(&D) -> f64,
+{
+    let mut documents: Vec<(f64, D)> = documents
+        .into_iter()
+        .map(|(_, doc)| (distance(&doc), doc))
+        .collect();
+    documents.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    documents
+        .into_iter()
+        .take(limit)
+        .map(|(_, doc)| doc)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_route_indices_by_doc_type_and_dataset() {
+        let routing = ClusterRouting {
+            datasets: vec!["de".to_string()],
+            doc_types: vec!["poi".to_string(), "stop".to_string()],
+            bbox: None,
+        };
+        let indices = vec![
+            "munin_addr".to_string(),
+            "munin_poi".to_string(),
+            "munin_poi_fr".to_string(),
+            "munin_stop_de".to_string(),
+        ];
+
+        assert_eq!(
+            routing.indices(&indices),
+            vec!["munin_poi".to_string(), "munin_stop_de".to_string()]
+        );
+        assert_eq!(ClusterRouting::default().indices(&indices), indices);
+    }
+
+    #[test]
+    fn should_route_points_by_bbox() {
+        let routing = ClusterRouting {
+            bbox: Some([5.8, 47.2, 15.1, 55.1]),
+            ..ClusterRouting::default()
+        };
+
+        assert!(routing.covers(13.4, 52.5));
+        assert!(!routing.covers(2.35, 48.85));
+    }
+
+    #[test]
+    fn should_route_shapes_by_bbox() {
+        let routing = ClusterRouting {
+            bbox: Some([5.8, 47.2, 15.1, 55.1]),
+            ..ClusterRouting::default()
+        };
+        let shape = |positions: Vec<Position>| {
+            Area::of_shape(&Geometry::new(geojson::Value::Polygon(vec![positions]))).unwrap()
+        };
+
+        // Around Basel, across the border.
+        let basel = shape(vec![
+            vec![7.5, 47.5],
+            vec![7.7, 47.5],
+            vec![7.7, 47.7],
+            vec![7.5, 47.7],
+            vec![7.5, 47.5],
+        ]);
+        assert_eq!(basel, Area::BBox([7.5, 47.5, 7.7, 47.7]));
+        assert!(routing.covers_area(&basel));
+
+        let paris = shape(vec![vec![2.2, 48.8], vec![2.5, 48.8], vec![2.5, 48.9]]);
+        assert!(!routing.covers_area(&paris));
+        assert!(ClusterRouting::default().covers_area(&paris));
+    }
+
+    #[test]
+    fn should_merge_the_calibrated_scores_of_the_clusters() {
+        let merge = |score_factor| {
+            let first = calibrate(vec![(10.0, "a1"), (3.0, "a2")], 1.0);
+            // The best hit of the second cluster is a weak match.
+            let second = calibrate(vec![(2.0, "b1"), (0.5, "b2")], score_factor);
+            by_score(first.into_iter().chain(second).collect(), 4)
+        };
+
+        assert_eq!(merge(1.0), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(merge(2.0), vec!["a1", "b1", "a2", "b2"]);
+    }
+
+    #[test]
+    fn should_merge_by_score() {
+        let documents = vec![(1.0, "a"), (3.0, "b"), (2.0, "c"), (3.0, "d")];
+
+        assert_eq!(by_score(documents, 3), vec!["b", "d", "c"]);
+    }
+}
@@ -0,0 +1,695 @@
+//! gRPC API of bragi, served on its own port along with the REST API. The requests are answered
+//! by the same handlers as the REST endpoints, and the places are converted from their
+//! GeocodeJSON properties to the messages of 'proto/bragi.proto'.
+
+use geojson::Geometry;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use snafu::{ResultExt, Snafu};
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+use tonic::{Code, Request, Response, Status};
+use warp::Rejection;
+
+use crate::{
+    adapters::primary::{
+        bragi::{
+            api::{
+                FeaturesQuery, ForwardGeocoderQuery, Proximity, ReverseGeocoderQuery,
+                StatusResponseBody, DEFAULT_LIMIT_RESULT_ES, DEFAULT_LIMIT_RESULT_REVERSE_API,
+            },
+            api_keys::{ApiKeys, Refusal},
+            handlers::{self, Context, InternalError, InternalErrorReason},
+            prometheus_handler,
+            routes::{InvalidRequest, Validate, ValidationError},
+        },
+        common::{
+            formats::GeocodeResult,
+            geocoding::{AssociatedAdmin, Feature, GeocodeJsonProperty, GeocodeJsonResponse},
+        },
+    },
+    domain::ports::primary::{
+        get_documents::GetDocuments, search_documents::SearchDocuments, status::Status as GetStatus,
+    },
+};
+
+pub mod proto {
+    tonic::include_proto!("bragi");
+}
+
+use proto::bragi_server::{Bragi, BragiServer};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not serve the gRPC API on {}: {}", addr, source))]
+    Serve {
+        addr: SocketAddr,
+        source: tonic::transport::Error,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GrpcSettings {
+    /// Serves the gRPC API, on the host of the 'service' section.
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for GrpcSettings {
+    fn default() -> Self {
+        GrpcSettings {
+            enabled: false,
+            port: 5001,
+        }
+    }
+}
+
+/// Serves the gRPC API, with the API keys of the REST API.
+pub async fn serve<C>(
+    ctx: Context<C>,
+    api_keys: Arc<ApiKeys>,
+    addr: SocketAddr,
+) -> Result<(), Error>
+where
+    C: SearchDocuments + GetDocuments<Document = Value> + GetStatus + Send + Sync + 'static,
+{
+    tonic::transport::Server::builder()
+        .add_service(BragiServer::new(BragiService { ctx, api_keys }))
+        .serve(addr)
+        .await
+        .context(ServeSnafu { addr })
+}
+
+pub struct BragiService<C> {
+    ctx: Context<C>,
+    api_keys: Arc<ApiKeys>,
+}
+
+impl<C> BragiService<C> {
+    // The API key is carried by the metadata, under the name of the header of the REST API. The
+    // request is handled if the key is admitted, as in the REST API.
+    async fn call<R, T, F>(
+        &self,
+        method: &str,
+        request: Request<R>,
+        handle: impl FnOnce(R) -> F,
+    ) -> Result<Response<T>, Status>
+    where
+        F: Future<Output = Result<T, Status>>,
+    {
+        let key = request
+            .metadata()
+            .get(self.api_keys.settings.header.as_str())
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let res = match self.api_keys.admit(key.as_deref()) {
+            Ok(_) => handle(request.into_inner()).await,
+            Err(refusal) => Err(refused(refusal)),
+        };
+        self.count(method, key.as_deref(), res)
+    }
+
+    // Each call is counted in 'bragi_grpc_requests_total', refused ones included.
+    fn count<T>(
+        &self,
+        method: &str,
+        key: Option<&str>,
+        res: Result<T, Status>,
+    ) -> Result<Response<T>, Status> {
+        let code = res.as_ref().map_or_else(Status::code, |_| Code::Ok);
+        prometheus_handler::count_grpc_request(
+            method,
+            &format!("{:?}", code),
+            &self.api_keys.client(key),
+        );
+        res.map(Response::new)
+    }
+}
+
+fn refused(refusal: Refusal) -> Status {
+    match refusal {
+        Refusal::MissingKey => Status::unauthenticated("an API key is required"),
+        Refusal::InvalidKey => Status::unauthenticated("invalid API key"),
+        Refusal::RateLimited { retry_after } => Status::resource_exhausted(format!(
+            "rate limit exceeded, retry after {}s",
+            seconds(retry_after)
+        )),
+        Refusal::QuotaExceeded { retry_after } => Status::resource_exhausted(format!(
+            "daily quota exceeded, retry after {}s",
+            seconds(retry_after)
+        )),
+    }
+}
+
+#[tonic::async_trait]
+impl<C> Bragi for BragiService<C>
+where
+    C: SearchDocuments + GetDocuments<Document = Value> + GetStatus + Send + Sync + 'static,
+{
+    async fn autocomplete(
+        &self,
+        request: Request<proto::ForwardRequest>,
+    ) -> Result<Response<proto::GeocodeResponse>, Status> {
+        self.call("Autocomplete", request, |request| async move {
+            let (params, geometry) = forward_query(request)?;
+            handlers::autocomplete_response(&self.ctx, params, geometry, Vec::new())
+                .await
+                .map(proto::GeocodeResponse::from)
+                .map_err(status_of)
+        })
+        .await
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::ForwardRequest>,
+    ) -> Result<Response<proto::GeocodeResponse>, Status> {
+        self.call("Search", request, |request| async move {
+            let (params, geometry) = forward_query(request)?;
+            handlers::search_response(&self.ctx, params, geometry, Vec::new())
+                .await
+                .map(proto::GeocodeResponse::from)
+                .map_err(status_of)
+        })
+        .await
+    }
+
+    async fn reverse(
+        &self,
+        request: Request<proto::ReverseRequest>,
+    ) -> Result<Response<proto::GeocodeResponse>, Status> {
+        self.call("Reverse", request, |request| async move {
+            let params = reverse_query(request)?;
+            handlers::reverse_response(&self.ctx, params)
+                .await
+                .map(proto::GeocodeResponse::from)
+                .map_err(status_of)
+        })
+        .await
+    }
+
+    async fn features(
+        &self,
+        request: Request<proto::FeaturesRequest>,
+    ) -> Result<Response<proto::GeocodeResponse>, Status> {
+        self.call("Features", request, |request| async move {
+            if request.id.is_empty() {
+                return Err(Status::invalid_argument("the id is required"));
+            }
+            let id = request.id.clone();
+            handlers::features_response(&self.ctx, id, features_query(request), Vec::new())
+                .await
+                .map(proto::GeocodeResponse::from)
+                .map_err(status_of)
+        })
+        .await
+    }
+
+    // As in the REST API, the status does not require an API key.
+    async fn status(
+        &self,
+        request: Request<proto::StatusRequest>,
+    ) -> Result<Response<proto::StatusResponse>, Status> {
+        let res = handlers::status_response(&self.ctx)
+            .await
+            .map(proto::StatusResponse::from)
+            .map_err(status_of);
+        let key = request
+            .metadata()
+            .get(self.api_keys.settings.header.as_str())
+            .and_then(|value| value.to_str().ok());
+        self.count("Status", key, res)
+    }
+}
+
+/// The status of the errors of the handlers, which are the ones of the REST API.
+pub fn status_of(rejection: Rejection) -> Status {
+    if let Some(err) = rejection.find::<ValidationError>() {
+        Status::invalid_argument(err.0)
+    } else if let Some(err) = rejection.find::<InvalidRequest>() {
+        Status::invalid_argument(err.info.clone())
+    } else if let Some(err) = rejection.find::<InternalError>() {
+        match err.reason {
+            InternalErrorReason::ObjectNotFoundError => Status::not_found(err.info.clone()),
+            InternalErrorReason::ElasticSearchError => Status::unavailable(err.info.clone()),
+            _ => Status::internal(err.info.clone()),
+        }
+    } else {
+        Status::internal(format!("{:?}", rejection))
+    }
+}
+
+/// The query of the REST API with the parameters of the request, validated the same way.
+pub fn forward_query(
+    request: proto::ForwardRequest,
+) -> Result<(ForwardGeocoderQuery, Option<Geometry>), Status> {
+    let geometry = non_empty(request.shape)
+        .map(|shape| serde_json::from_str::<Geometry>(&shape))
+        .transpose()
+        .map_err(|err| Status::invalid_argument(format!("invalid shape: {}", err)))?;
+
+    let query = ForwardGeocoderQuery {
+        q: request.q,
+        lat: request.coord.as_ref().map(|coord| coord.lat as f32),
+        lon: request.coord.as_ref().map(|coord| coord.lon as f32),
+        shape_scope: parse_all("shape_scope", request.shape_scope)?,
+        types: parse_all("types", request.types)?,
+        zone_types: parse_all("zone_types", request.zone_types)?,
+        poi_types: non_empty_vec(request.poi_types),
+        limit: limit(request.limit, DEFAULT_LIMIT_RESULT_ES),
+        lang: non_empty(request.lang),
+        include_langs: non_empty_vec(request.include_langs),
+        timeout: timeout(request.timeout),
+        pt_dataset: non_empty_vec(request.pt_datasets),
+        poi_dataset: non_empty_vec(request.poi_datasets),
+        request_id: non_empty(request.request_id),
+        profile: non_empty(request.profile),
+        fuzziness: non_empty(request.fuzziness)
+            .map(|fuzziness| fuzziness.parse())
+            .transpose()
+            .map_err(Status::invalid_argument)?,
+        phonetic: request.phonetic,
+        format: None,
+        fields: None,
+        exclude_fields: None,
+        with_geometry: None,
+        tolerance: None,
+        proximity: request.proximity.map(|proximity| Proximity {
+            scale: proximity.scale,
+            offset: proximity.offset,
+            decay: proximity.decay,
+        }),
+    };
+
+    query.filter().map_err(status_of)?;
+    Ok((query, geometry))
+}
+
+fn reverse_query(request: proto::ReverseRequest) -> Result<ReverseGeocoderQuery, Status> {
+    let coord = request
+        .coord
+        .ok_or_else(|| Status::invalid_argument("the coord is required"))?;
+    let query = ReverseGeocoderQuery {
+        lat: coord.lat,
+        lon: coord.lon,
+        limit: limit(request.limit, DEFAULT_LIMIT_RESULT_REVERSE_API),
+        timeout: timeout(request.timeout),
+        profile: non_empty(request.profile),
+        format: None,
+        fields: None,
+        exclude_fields: None,
+    };
+    query.filter().map_err(status_of)?;
+    Ok(query)
+}
+
+fn features_query(request: proto::FeaturesRequest) -> FeaturesQuery {
+    FeaturesQuery {
+        lang: non_empty(request.lang),
+        timeout: timeout(request.timeout),
+        pt_dataset: non_empty_vec(request.pt_datasets),
+        poi_dataset: non_empty_vec(request.poi_datasets),
+        request_id: non_empty(request.request_id),
+        format: None,
+        with_geometry: None,
+        tolerance: None,
+        fields: None,
+        exclude_fields: None,
+    }
+}
+
+fn non_empty(value: String) -> Option<String> {
+    Some(value).filter(|value| !value.is_empty())
+}
+
+fn non_empty_vec(values: Vec<String>) -> Option<Vec<String>> {
+    Some(values).filter(|values| !values.is_empty())
+}
+
+fn limit(limit: u32, default: i64) -> i64 {
+    if limit == 0 {
+        default
+    } else {
+        i64::from(limit)
+    }
+}
+
+fn timeout(timeout: u32) -> Option<Duration> {
+    Some(timeout)
+        .filter(|timeout| *timeout > 0)
+        .map(|timeout| Duration::from_millis(u64::from(timeout)))
+}
+
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+// The values are parsed as in the REST API, where they are strings.
+fn parse_all<T: DeserializeOwned>(
+    name: &str,
+    values: Vec<String>,
+) -> Result<Option<Vec<T>>, Status> {
+    non_empty_vec(values)
+        .map(|values| {
+            values
+                .into_iter()
+                .map(|value| {
+                    serde_json::from_value(Value::String(value.clone())).map_err(|_| {
+                        Status::invalid_argument(format!("invalid value '{}' in {}", value, name))
+                    })
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+// The enums are given as they are serialized in the GeocodeJSON responses.
+fn serialized<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(value)) => value,
+        _ => String::new(),
+    }
+}
+
+fn coord(lon: f64, lat: f64) -> Option<proto::Coord> {
+    Some(proto::Coord { lon, lat })
+}
+
+fn bbox(rect: &Option<geo_types::Rect<f64>>) -> Option<proto::BoundingBox> {
+    rect.map(|rect| proto::BoundingBox {
+        min_lon: rect.min().x,
+        min_lat: rect.min().y,
+        max_lon: rect.max().x,
+        max_lat: rect.max().y,
+    })
+}
+
+impl From<GeocodeResult> for proto::GeocodeResponse {
+    fn from(result: GeocodeResult) -> Self {
+        proto::GeocodeResponse::from(GeocodeJsonResponse::from(result))
+    }
+}
+
+impl From<GeocodeJsonResponse> for proto::GeocodeResponse {
+    fn from(response: GeocodeJsonResponse) -> Self {
+        proto::GeocodeResponse {
+            query: response.geocoding.query.unwrap_or_default(),
+            places: response
+                .features
+                .into_iter()
+                .map(proto::Place::from)
+                .collect(),
+            warnings: response.warnings,
+        }
+    }
+}
+
+impl From<Feature> for proto::Place {
+    fn from(feature: Feature) -> Self {
+        let mut place = proto::Place::from(feature.properties.geocoding);
+        if let geojson::Value::Point(point) = &feature.geometry.value {
+            place.coord = coord(point[0], point[1]);
+        }
+        place.distance = feature.distance.unwrap_or_default();
+        place
+    }
+}
+
+impl From<GeocodeJsonProperty> for proto::Place {
+    fn from(property: GeocodeJsonProperty) -> Self {
+        proto::Place {
+            id: property.id,
+            r#type: property.place_type.as_str().to_string(),
+            zone_type: property.zone_type.unwrap_or_default(),
+            label: property.label.unwrap_or_default(),
+            name: property.name.unwrap_or_default(),
+            names: property.names.into_iter().collect(),
+            labels: property.labels.into_iter().collect(),
+            housenumber: property.housenumber.unwrap_or_default(),
+            street: property.street.unwrap_or_default(),
+            postcode: property.postcode.unwrap_or_default(),
+            city: property.city.unwrap_or_default(),
+            citycode: property.citycode.unwrap_or_default(),
+            level: property.level.unwrap_or_default(),
+            coord: None,
+            distance: 0,
+            administrative_regions: property
+                .administrative_regions
+                .into_iter()
+                .map(proto::AssociatedAdmin::from)
+                .collect(),
+            poi_types: property
+                .poi_types
+                .into_iter()
+                .map(|poi_type| proto::PoiType {
+                    id: poi_type.id,
+                    name: poi_type.name,
+                })
+                .collect(),
+            properties: property
+                .properties
+                .into_iter()
+                .map(|property| (property.key, property.value))
+                .collect(),
+            address: property
+                .address
+                .map(|address| Box::new(proto::Place::from(*address))),
+            commercial_modes: property
+                .commercial_modes
+                .into_iter()
+                .map(|mode| proto::CommercialMode {
+                    id: mode.id,
+                    name: mode.name,
+                })
+                .collect(),
+            comments: property
+                .comments
+                .into_iter()
+                .map(|comment| comment.name)
+                .collect(),
+            physical_modes: property
+                .physical_modes
+                .into_iter()
+                .map(|mode| proto::PhysicalMode {
+                    id: mode.id,
+                    name: mode.name,
+                })
+                .collect(),
+            lines: property
+                .lines
+                .into_iter()
+                .map(|line| proto::Line {
+                    id: line.id,
+                    name: line.name,
+                    code: line.code.unwrap_or_default(),
+                    color: line.color.as_ref().map(serialized).unwrap_or_default(),
+                    text_color: line.text_color.as_ref().map(serialized).unwrap_or_default(),
+                    commercial_mode: line.commercial_mode.map(|mode| proto::CommercialMode {
+                        id: mode.id,
+                        name: mode.name,
+                    }),
+                    network: line.network.map(|network| proto::Network {
+                        id: network.id,
+                        name: network.name,
+                    }),
+                    physical_modes: line
+                        .physical_modes
+                        .into_iter()
+                        .map(|mode| proto::PhysicalMode {
+                            id: mode.id,
+                            name: mode.name,
+                        })
+                        .collect(),
+                })
+                .collect(),
+            timezone: property.timezone.unwrap_or_default(),
+            codes: property
+                .codes
+                .into_iter()
+                .map(|code| (code.name, code.value))
+                .collect(),
+            feed_publishers: property
+                .feed_publishers
+                .into_iter()
+                .map(|publisher| proto::FeedPublisher {
+                    id: publisher.id,
+                    license: publisher.license,
+                    name: publisher.name,
+                    url: publisher.url,
+                })
+                .collect(),
+            bbox: bbox(&property.bbox),
+            country_codes: property.country_codes,
+        }
+    }
+}
+
+impl From<AssociatedAdmin> for proto::AssociatedAdmin {
+    fn from(admin: AssociatedAdmin) -> Self {
+        proto::AssociatedAdmin {
+            id: admin.id,
+            insee: admin.insee,
+            level: admin.level,
+            label: admin.label,
+            name: admin.name,
+            zip_codes: admin.zip_codes,
+            coord: coord(admin.coord.lon(), admin.coord.lat()),
+            bbox: bbox(&admin.bbox),
+            zone_type: admin.zone_type.as_ref().map(serialized).unwrap_or_default(),
+            parent_id: admin.parent_id.unwrap_or_default(),
+            codes: admin
+                .codes
+                .into_iter()
+                .map(|code| (code.name, code.value))
+                .collect(),
+        }
+    }
+}
+
+impl From<StatusResponseBody> for proto::StatusResponse {
+    fn from(status: StatusResponseBody) -> Self {
+        let elasticsearch = status.elasticsearch;
+        proto::StatusResponse {
+            bragi_version: status.bragi.version,
+            mimir_version: status.mimir.version,
+            elasticsearch: Some(proto::ElasticsearchStatus {
+                version: elasticsearch.version,
+                health: elasticsearch.health,
+                url: elasticsearch.url,
+                nodes: elasticsearch
+                    .nodes
+                    .into_iter()
+                    .map(|node| proto::ElasticsearchNode {
+                        url: node.url,
+                        healthy: node.healthy,
+                    })
+                    .collect(),
+                indices: elasticsearch
+                    .indices
+                    .into_iter()
+                    .map(|index| proto::ElasticsearchIndex {
+                        index: index.index,
+                        doc_type: index.doc_type,
+                        dataset: index.dataset,
+                        docs_count: index.docs_count,
+                        indexed_at: index.indexed_at.unwrap_or_default(),
+                        aliases: index.aliases,
+                    })
+                    .collect(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::primary::bragi::api::Type;
+    use cosmogony::ZoneType;
+    use places::{admin::Admin, coord::Coord, street::Street, Place};
+
+    #[test]
+    fn should_build_the_query_of_the_rest_api() {
+        let (query, geometry) = forward_query(proto::ForwardRequest {
+            q: String::from("rue hugo"),
+            coord: Some(proto::Coord {
+                lon: 2.35,
+                lat: 48.85,
+            }),
+            types: vec![String::from("street"), String::from("zone")],
+            zone_types: vec![String::from("city")],
+            fuzziness: String::from("1"),
+            ..proto::ForwardRequest::default()
+        })
+        .unwrap();
+
+        assert_eq!(query.types, Some(vec![Type::Street, Type::Zone]));
+        assert_eq!(query.zone_types, Some(vec![ZoneType::City]));
+        assert_eq!(query.limit, DEFAULT_LIMIT_RESULT_ES);
+        assert_eq!(query.lat, Some(48.85));
+        assert!(query.lang.is_none() && query.timeout.is_none());
+        assert!(geometry.is_none());
+    }
+
+    #[test]
+    fn should_reject_invalid_requests() {
+        let invalid = |request: proto::ForwardRequest| forward_query(request).unwrap_err().code();
+
+        assert_eq!(
+            invalid(proto::ForwardRequest::default()),
+            Code::InvalidArgument
+        );
+        assert_eq!(
+            invalid(proto::ForwardRequest {
+                q: String::from("paris"),
+                types: vec![String::from("castle")],
+                ..proto::ForwardRequest::default()
+            }),
+            Code::InvalidArgument
+        );
+        // A zone must come with its zone types, as in the REST API.
+        assert_eq!(
+            invalid(proto::ForwardRequest {
+                q: String::from("paris"),
+                types: vec![String::from("zone")],
+                ..proto::ForwardRequest::default()
+            }),
+            Code::InvalidArgument
+        );
+    }
+
+    #[test]
+    fn should_mirror_the_geocoding_properties() {
+        let city = Admin {
+            id: String::from("admin:fr:75056"),
+            insee: String::from("75056"),
+            level: 8,
+            name: String::from("Paris"),
+            label: String::from("Paris (75000-75116)"),
+            zip_codes: vec![String::from("75000")],
+            coord: Coord::new(2.35, 48.85).unwrap(),
+            zone_type: Some(ZoneType::City),
+            ..Admin::default()
+        };
+        let street = Street {
+            id: String::from("street:1"),
+            name: String::from("Rue Victor Hugo"),
+            label: String::from("Rue Victor Hugo (Paris)"),
+            coord: Coord::new(2.36, 48.86).unwrap(),
+            administrative_regions: vec![Arc::new(city)],
+            zip_codes: vec![String::from("75016")],
+            ..Street::default()
+        };
+        let response = proto::GeocodeResponse::from(GeocodeResult {
+            query: String::from("hugo"),
+            places: vec![Place::Street(street)],
+            lang: Some(String::from("fr")),
+            warnings: vec![String::from("cluster 'de' failed")],
+            ..GeocodeResult::default()
+        });
+
+        assert_eq!(response.query, "hugo");
+        assert_eq!(response.warnings, vec!["cluster 'de' failed"]);
+        let place = &response.places[0];
+        assert_eq!(place.r#type, "street");
+        assert_eq!(place.city, "Paris");
+        assert_eq!(place.citycode, "75056");
+        assert_eq!(place.postcode, "75016");
+        assert_eq!(
+            place.coord,
+            Some(proto::Coord {
+                lon: 2.36,
+                lat: 48.86
+            })
+        );
+        let admin = &place.administrative_regions[0];
+        assert_eq!(admin.zone_type, "city");
+        assert_eq!(
+            admin.coord,
+            Some(proto::Coord {
+                lon: 2.35,
+                lat: 48.85
+            })
+        );
+    }
+}
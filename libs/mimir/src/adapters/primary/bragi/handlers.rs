@@ -1,38 +1,56 @@
-use std::time::Duration;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
 
+use futures::stream::StreamExt;
 use geo::algorithm::haversine_distance::HaversineDistance;
 use geojson::Geometry;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tracing::instrument;
+use tracing::{info_span, instrument, Instrument};
 use warp::{
-    http::StatusCode,
+    http::{
+        header::{CONTENT_TYPE, VARY},
+        HeaderValue, StatusCode,
+    },
     reject::Reject,
     reply::{json, with_status},
-    Rejection,
+    Rejection, Reply,
 };
 
 use common::document::ContainerDocument;
 use places::{addr::Addr, admin::Admin, poi::Poi, stop::Stop, street::Street, Place};
 
-use crate::adapters::primary::common::filters::Filters;
+use crate::adapters::primary::common::filters::{Filters, Fuzziness};
 use crate::{
     adapters::{
         primary::{
             bragi::{
                 api::{
-                    BragiStatus, ElasticsearchStatus, ForwardGeocoderExplainQuery,
-                    ForwardGeocoderQuery, MimirStatus, ReverseGeocoderQuery, StatusResponseBody,
-                    Type,
+                    BragiStatus, ElasticsearchIndexStatus, ElasticsearchNodeStatus,
+                    ElasticsearchStatus, ExplainResponseBody, ExplainedResult, FeaturesQuery,
+                    ForwardGeocoderExplainQuery, ForwardGeocoderQuery, LivenessResponseBody,
+                    MimirStatus, ProfilesResponseBody, ReadinessResponseBody, ReverseGeocoderQuery,
+                    StatusResponseBody, Type, WithGeometry, DEFAULT_LANG,
                 },
-                prometheus_handler,
+                api_keys::ApiKeysSettings,
+                clusters::{self, Area, Cluster, ClusterRouting, ClusterSettings},
+                grpc::GrpcSettings,
+                health::{self, ReadinessSettings},
+                openapi, prometheus_handler,
+                routes::{CompressionSettings, CorsSettings, InvalidRequest, InvalidRequestReason},
             },
             common::{
                 coord, dsl,
                 dsl::QueryType,
+                explain::ScoreBreakdown,
+                fields::FieldSelection,
                 filters,
-                geocoding::{Feature, FromWithLang, GeocodeJsonResponse},
-                settings::QuerySettings,
+                formats::{Format, GeocodeResult},
+                query_parser,
+                settings::{QueryProfiles, QueryProfilesSource, QuerySettings},
             },
         },
         secondary::elasticsearch::ElasticsearchStorageConfig,
@@ -43,14 +61,18 @@ use crate::{
             query::Query,
         },
         ports::primary::{
-            explain_query::ExplainDocument, search_documents::SearchDocuments, status::Status,
+            explain_query::ExplainDocument, get_documents::GetDocuments,
+            search_documents::SearchDocuments, status::Status,
         },
     },
-    utils::deserialize::deserialize_duration,
+    utils::{deserialize::deserialize_duration, telemetry::TracingSettings},
 };
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+// Number of explain requests sent at the same time for the results of autocomplete-explain.
+const EXPLAIN_CONCURRENT_REQUESTS: usize = 4;
+
 #[cfg(feature = "metrics")]
 lazy_static::lazy_static! {
     static ref ES_REQ_HISTOGRAM: prometheus::HistogramVec = prometheus::register_histogram_vec!(
@@ -80,18 +102,84 @@ pub struct Settings {
     pub service: Service,
     pub nb_threads: Option<usize>,
     pub http_cache_duration: usize,
+    /// Adds ETags to the responses, and answers 304 to requests with a matching If-None-Match.
+    #[serde(default)]
+    pub etag: bool,
+    /// Languages of the names and labels in the indices, which the Accept-Language header can
+    /// select.
+    #[serde(default = "default_langs")]
+    pub langs: Vec<String>,
+    #[serde(default)]
+    pub cors: CorsSettings,
+    #[serde(default)]
+    pub compression: CompressionSettings,
     #[serde(deserialize_with = "deserialize_duration")]
     pub autocomplete_timeout: Duration,
     #[serde(deserialize_with = "deserialize_duration")]
     pub reverse_timeout: Duration,
     #[serde(deserialize_with = "deserialize_duration")]
     pub features_timeout: Duration,
+    /// Routing rules of the cluster of the 'elasticsearch' section.
+    #[serde(default)]
+    pub routing: ClusterRouting,
+    /// Additional clusters, queried along with the one of the 'elasticsearch' section.
+    #[serde(default)]
+    pub clusters: Vec<ClusterSettings>,
+    /// Ranking profiles, selected with the 'profile' parameter. Each profile overrides some
+    /// values of the 'query' section.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Value>,
+    /// Token required by the admin endpoints. Without it, these endpoints are disabled.
+    #[serde(default, skip_serializing)]
+    pub admin_token: Option<String>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// What '/health/ready' checks in the clusters.
+    #[serde(default)]
+    pub readiness: ReadinessSettings,
+    /// API keys of the clients, with their rate limits and quotas.
+    #[serde(default)]
+    pub api_keys: ApiKeysSettings,
+    /// The gRPC API, served on its own port.
+    #[serde(default)]
+    pub grpc: GrpcSettings,
+}
+
+fn default_langs() -> Vec<String> {
+    vec![DEFAULT_LANG.to_string()]
 }
 
 #[derive(Clone)]
 pub struct Context<C> {
-    pub client: C,
+    /// The clusters to query, starting with the one of the 'elasticsearch' section.
+    pub clusters: Vec<Cluster<C>>,
     pub settings: Settings,
+    /// The ranking profiles, which can be reloaded from their source while running.
+    pub profiles: Arc<RwLock<QueryProfiles>>,
+    pub profiles_source: Option<QueryProfilesSource>,
+}
+
+impl<C> Context<C> {
+    /// The client of the cluster of the 'elasticsearch' section.
+    pub fn client(&self) -> &C {
+        &self.clusters[0].client
+    }
+
+    /// The query settings of the requested ranking profile.
+    pub fn query_settings(&self, profile: Option<&str>) -> Result<QuerySettings, Rejection> {
+        let profiles = self.profiles.read().expect("profiles lock poisoned");
+        profiles.get(profile).cloned().ok_or_else(|| {
+            warp::reject::custom(InvalidRequest {
+                reason: InvalidRequestReason::UnknownProfile,
+                info: format!(
+                    "unknown profile '{}', expected one of: {}",
+                    profile.unwrap_or_default(),
+                    profiles.names().join(", ")
+                ),
+            })
+        })
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -100,6 +188,7 @@ pub enum InternalErrorReason {
     SerializationError,
     ObjectNotFoundError,
     StatusError,
+    ProfilesError,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -110,11 +199,8 @@ pub struct InternalError {
 
 impl Reject for InternalError {}
 
-pub fn build_feature(
-    places: Vec<Place>,
-    query_coord: Option<&coord::Coord>,
-    lang: Option<&str>,
-) -> Vec<Feature> {
+/// Sets the distance of each place to the coordinates of the query, if any.
+pub fn with_distances(places: Vec<Place>, query_coord: Option<&coord::Coord>) -> Vec<Place> {
     places
         .into_iter()
         .map(|mut p| {
@@ -124,37 +210,89 @@ pub fn build_feature(
                 let distance = geo_point.haversine_distance(&pp) as u32;
                 p.set_distance(distance);
             }
-            Feature::from_with_lang(p, lang)
+            p
         })
         .collect()
 }
 
-#[instrument(skip(ctx))]
+// The body of the response in the requested format. As the format may be negotiated with the
+// Accept header, caches are told so.
+fn formatted(format: Format, result: GeocodeResult) -> Result<warp::reply::Response, Rejection> {
+    let formatter = format.formatter();
+    let body = formatter.format(result).map_err(|err| {
+        warp::reject::custom(InternalError {
+            reason: InternalErrorReason::SerializationError,
+            info: err.to_string(),
+        })
+    })?;
+    let mut response =
+        warp::reply::with_header(body, CONTENT_TYPE, formatter.content_type()).into_response();
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept"));
+    Ok(response)
+}
+
 pub async fn forward_autocomplete_geocoder<C>(
     ctx: Context<C>,
     params: ForwardGeocoderQuery,
     geometry: Option<Geometry>,
+    accept_language: Vec<String>,
+    accept_format: Option<Format>,
 ) -> Result<impl warp::Reply, Rejection>
 where
-    C: SearchDocuments,
+    C: SearchDocuments + Send + Sync,
 {
-    let (q, timeout, es_indices_to_search_in, lang, filters, excludes, query_settings) =
-        get_search_fields_from_params(ctx.settings.clone(), params, geometry);
-
-    for query_type in [QueryType::PREFIX, QueryType::FUZZY] {
-        let dsl_query = dsl::build_query(
-            &q,
-            &filters,
-            lang.as_str(),
-            &query_settings,
-            query_type,
-            Some(&excludes),
-        );
+    let format = params.format.or(accept_format).unwrap_or_default();
+    let result = autocomplete_response(&ctx, params, geometry, accept_language).await?;
+    formatted(format, result)
+}
+
+/// The places matching the beginning of the query, for the REST and the gRPC APIs.
+#[instrument(skip(ctx), fields(request_id = params.request_id.as_deref().unwrap_or_default()))]
+pub async fn autocomplete_response<C>(
+    ctx: &Context<C>,
+    params: ForwardGeocoderQuery,
+    geometry: Option<Geometry>,
+    accept_language: Vec<String>,
+) -> Result<GeocodeResult, Rejection>
+where
+    C: SearchDocuments + Send + Sync,
+{
+    reject_search_only_parameters(&params)?;
+    let include_langs = params.include_langs.clone().unwrap_or_default();
+    let request_id = params.request_id.clone().unwrap_or_default();
+    let fields = FieldSelection::new(params.fields.clone(), params.exclude_fields.clone());
+    let (q, timeout, es_indices_to_search_in, lang, filters, query_settings) =
+        get_search_fields_from_params(ctx, params, geometry, accept_language)?;
+    let source = fields.source(false);
 
-        let places = request_search_documents(
-            &ctx,
+    // Without typo tolerance, there is no fallback on the fuzzy query.
+    let query_types = if filters.fuzziness.map_or(true, Fuzziness::is_enabled) {
+        vec![QueryType::PREFIX, QueryType::FUZZY]
+    } else {
+        vec![QueryType::PREFIX]
+    };
+
+    let mut warnings = Vec::new();
+    for query_type in query_types {
+        let dsl_query = info_span!("build_query", %request_id, ?query_type).in_scope(|| {
+            dsl::build_query(
+                &q,
+                &filters,
+                lang.as_str(),
+                &query_settings,
+                query_type,
+                Some(&source),
+            )
+        });
+
+        let (places, query_warnings) = request_search_documents(
+            ctx,
+            &request_id,
             timeout,
             es_indices_to_search_in.clone(),
+            search_area(&filters),
             filters.limit,
             query_type,
             dsl_query,
@@ -162,71 +300,126 @@ where
         .await?;
 
         if !places.is_empty() {
-            let features = build_feature(places, filters.coord.as_ref(), Some(lang.as_str()));
-            let resp = GeocodeJsonResponse::new(q, features);
-            return Ok(with_status(json(&resp), StatusCode::OK));
+            return Ok(GeocodeResult {
+                query: q,
+                places: with_distances(places, filters.coord.as_ref()),
+                lang: Some(lang),
+                include_langs,
+                warnings: query_warnings,
+                fields,
+                ..GeocodeResult::default()
+            });
         }
+        warnings.extend(query_warnings);
     }
 
-    Ok(with_status(
-        json(&GeocodeJsonResponse::new(q, vec![])),
-        StatusCode::OK,
-    ))
+    warnings.dedup();
+    Ok(GeocodeResult {
+        query: q,
+        places: vec![],
+        lang: Some(lang),
+        include_langs,
+        warnings,
+        fields,
+        ..GeocodeResult::default()
+    })
+}
+
+// The boundaries of the admins are only returned by the search, the autocomplete refuses the
+// parameters asking for them rather than ignoring them.
+fn reject_search_only_parameters(params: &ForwardGeocoderQuery) -> Result<(), Rejection> {
+    if params.with_geometry.is_some() || params.tolerance.is_some() {
+        return Err(warp::reject::custom(InvalidRequest {
+            reason: InvalidRequestReason::SearchOnlyParameter,
+            info: String::from("'with_geometry' and 'tolerance' are only available for the search"),
+        }));
+    }
+    Ok(())
 }
 
-#[instrument(skip(ctx))]
 pub async fn forward_search_geocoder<C>(
     ctx: Context<C>,
     params: ForwardGeocoderQuery,
     geometry: Option<Geometry>,
+    accept_language: Vec<String>,
+    accept_format: Option<Format>,
 ) -> Result<impl warp::Reply, Rejection>
 where
-    C: SearchDocuments,
+    C: SearchDocuments + Send + Sync,
 {
-    let (q, timeout, es_indices_to_search_in, lang, filters, excludes, query_settings) =
-        get_search_fields_from_params(ctx.settings.clone(), params, geometry);
-
-    let dsl_query = dsl::build_query(
-        &q,
-        &filters,
-        lang.as_str(),
-        &query_settings,
-        QueryType::SEARCH,
-        Some(&excludes),
-    );
+    let format = params.format.or(accept_format).unwrap_or_default();
+    let result = search_response(&ctx, params, geometry, accept_language).await?;
+    formatted(format, result)
+}
 
-    let places = request_search_documents(
-        &ctx,
+/// The place matching the whole query, for the REST and the gRPC APIs.
+#[instrument(skip(ctx), fields(request_id = params.request_id.as_deref().unwrap_or_default()))]
+pub async fn search_response<C>(
+    ctx: &Context<C>,
+    params: ForwardGeocoderQuery,
+    geometry: Option<Geometry>,
+    accept_language: Vec<String>,
+) -> Result<GeocodeResult, Rejection>
+where
+    C: SearchDocuments + Send + Sync,
+{
+    let include_langs = params.include_langs.clone().unwrap_or_default();
+    let request_id = params.request_id.clone().unwrap_or_default();
+    let fields = FieldSelection::new(params.fields.clone(), params.exclude_fields.clone());
+    let boundaries = params.with_geometry == Some(WithGeometry::Boundary);
+    let tolerance = params.tolerance;
+    let (q, timeout, es_indices_to_search_in, lang, filters, query_settings) =
+        get_search_fields_from_params(ctx, params, geometry, accept_language)?;
+    let source = fields.source(boundaries);
+
+    let dsl_query = info_span!("build_query", %request_id, query_type = ?QueryType::SEARCH)
+        .in_scope(|| {
+            dsl::build_query(
+                &q,
+                &filters,
+                lang.as_str(),
+                &query_settings,
+                QueryType::SEARCH,
+                Some(&source),
+            )
+        });
+
+    let (places, warnings) = request_search_documents(
+        ctx,
+        &request_id,
         timeout,
         es_indices_to_search_in.clone(),
+        search_area(&filters),
         1,
         QueryType::SEARCH,
         dsl_query,
     )
     .await?;
 
-    if !places.is_empty() {
-        let features = build_feature(places, filters.coord.as_ref(), Some(lang.as_str()));
-        let resp = GeocodeJsonResponse::new(q, features);
-        Ok(with_status(json(&resp), StatusCode::OK))
-    } else {
-        Ok(with_status(
-            json(&GeocodeJsonResponse::new(q, vec![])),
-            StatusCode::OK,
-        ))
-    }
+    Ok(GeocodeResult {
+        query: q,
+        places: with_distances(places, filters.coord.as_ref()),
+        lang: Some(lang),
+        include_langs,
+        warnings,
+        fields,
+        boundaries,
+        tolerance,
+    })
 }
 
 async fn request_search_documents<C>(
     ctx: &Context<C>,
+    request_id: &str,
     timeout: Duration,
     es_indices_to_search_in: Vec<String>,
+    area: Option<Area>,
     results_limit: i64,
     query_type: QueryType,
     dsl_query: Value,
-) -> Result<Vec<Place>, Rejection>
+) -> Result<(Vec<Place>, Vec<String>), Rejection>
 where
-    C: SearchDocuments,
+    C: SearchDocuments + Send + Sync,
 {
     tracing::trace!(
         query_type = ?query_type,
@@ -247,110 +440,282 @@ where
         })
         .ok();
 
-    let res = ctx
-        .client
-        .search_documents(
-            es_indices_to_search_in.clone(),
-            Query::QueryDSL(dsl_query),
-            results_limit,
-            Some(timeout),
-        )
-        .await;
+    let res = clusters::search(
+        &ctx.clusters,
+        &es_indices_to_search_in,
+        area,
+        &dsl_query,
+        results_limit,
+        timeout,
+    )
+    .instrument(info_span!(
+        "search_documents",
+        %request_id,
+        ?query_type,
+        indices = ?es_indices_to_search_in,
+    ))
+    .await;
 
     #[cfg(feature = "metrics")]
     if let Some(timer) = timer {
         timer.observe_duration();
     }
 
-    let places: Result<Vec<Place>, Rejection> = res.map_err(|err| {
+    let res = res.map_err(|err| {
         warp::reject::custom(InternalError {
             reason: InternalErrorReason::ElasticSearchError,
             info: err.to_string(),
         })
-    });
-    places
+    })?;
+
+    let places = clusters::by_score(res.documents, results_limit.max(0) as usize);
+    Ok((places, res.warnings))
+}
+
+// The 'lang' parameter takes precedence over the Accept-Language header, whose languages are
+// given by decreasing quality: the first one with labels in the indices is taken.
+fn negotiate_lang(lang: Option<String>, accept_language: &[String], langs: &[String]) -> String {
+    lang.or_else(|| {
+        accept_language
+            .iter()
+            .find(|accepted| langs.contains(accepted) || accepted.as_str() == DEFAULT_LANG)
+            .cloned()
+    })
+    .unwrap_or_else(|| DEFAULT_LANG.to_string())
+}
+
+// Forward requests with a shape only need the clusters covering the shape, and the ones with
+// coordinates the clusters covering these coordinates.
+fn search_area(filters: &Filters) -> Option<Area> {
+    filters
+        .shape
+        .as_ref()
+        .and_then(|(shape, _)| Area::of_shape(shape))
+        .or_else(|| {
+            filters
+                .coord
+                .map(|coord| Area::Point(f64::from(coord.lon), f64::from(coord.lat)))
+        })
 }
 
-fn get_search_fields_from_params(
-    settings: Settings,
+#[allow(clippy::type_complexity)]
+fn get_search_fields_from_params<C>(
+    ctx: &Context<C>,
     params: ForwardGeocoderQuery,
     geometry: Option<Geometry>,
-) -> (
-    String,
-    Duration,
-    Vec<String>,
-    String,
-    Filters,
-    [String; 1],
-    QuerySettings,
-) {
+    accept_language: Vec<String>,
+) -> Result<
+    (
+        String,
+        Duration,
+        Vec<String>,
+        String,
+        Filters,
+        QuerySettings,
+    ),
+    Rejection,
+> {
+    let settings_query = ctx.query_settings(params.profile.as_deref())?;
     let q = params.q.clone();
-    let timeout = params.timeout.unwrap_or(settings.autocomplete_timeout);
+    let timeout = params.timeout.unwrap_or(ctx.settings.autocomplete_timeout);
     let es_indices_to_search_in =
         build_es_indices_to_search(&params.types, &params.pt_dataset, &params.poi_dataset);
-    let lang = params.lang.clone();
+    let lang = negotiate_lang(params.lang.clone(), &accept_language, &ctx.settings.langs);
     let filters = filters::Filters::from((params, geometry));
-    let excludes = ["boundary".to_string()];
-    let settings_query = settings.query;
-    (
+    Ok((
         q,
         timeout,
         es_indices_to_search_in,
         lang,
         filters,
-        excludes,
         settings_query,
-    )
+    ))
 }
 
-#[instrument(skip(ctx))]
+/// Explains the score of a document for the prefix query of the autocomplete, along with the
+/// scores of the results of this query, so that they can be compared.
+#[instrument(skip(ctx), fields(request_id = params.request_id.as_deref().unwrap_or_default()))]
 pub async fn forward_geocoder_explain<C>(
     ctx: Context<C>,
     params: ForwardGeocoderExplainQuery,
     geometry: Option<Geometry>,
+    accept_language: Vec<String>,
 ) -> Result<impl warp::Reply, Rejection>
 where
-    C: ExplainDocument,
+    C: ExplainDocument + SearchDocuments + Send + Sync,
     C::Document: Serialize + Into<Value>,
 {
     let doc_id = params.doc_id.clone();
     let doc_type = params.doc_type.clone();
     let q = params.q.clone();
-    let lang = params.lang.clone();
+    let request_id = params.request_id.clone().unwrap_or_default();
+    let lang = negotiate_lang(params.lang.clone(), &accept_language, &ctx.settings.langs);
+    let query_settings = ctx.query_settings(params.profile.as_deref())?;
+    let timeout = params.timeout.unwrap_or(ctx.settings.autocomplete_timeout);
+    let es_indices_to_search_in =
+        build_es_indices_to_search(&params.types, &params.pt_dataset, &params.poi_dataset);
 
     let filters = filters::Filters::from((params.into(), geometry));
-    let dsl = dsl::build_query(
-        &q,
-        &filters,
-        lang.as_str(),
-        &ctx.settings.query,
-        QueryType::PREFIX,
-        None,
-    );
+    let clauses = dsl::score_clauses(&query_settings, &filters, QueryType::PREFIX);
+    let build_query = |source: Option<&Value>| {
+        info_span!("build_query", %request_id, query_type = ?QueryType::PREFIX).in_scope(|| {
+            dsl::build_query(
+                &q,
+                &filters,
+                lang.as_str(),
+                &query_settings,
+                QueryType::PREFIX,
+                source,
+            )
+        })
+    };
+    let dsl = build_query(None);
 
-    match ctx
-        .client
-        .explain_document(Query::QueryDSL(dsl), doc_id, doc_type)
+    let explanation = explain_in_clusters(&ctx, &dsl, &doc_id, &doc_type, &request_id)
         .await
-    {
-        Ok(res) => Ok(with_status(json(&res), StatusCode::OK)),
-        Err(err) => Err(warp::reject::custom(InternalError {
-            reason: InternalErrorReason::ElasticSearchError,
-            info: err.to_string(),
-        })),
+        .map_err(|info| {
+            warp::reject::custom(InternalError {
+                reason: InternalErrorReason::ElasticSearchError,
+                info,
+            })
+        })?;
+    let breakdown = ScoreBreakdown::from_explanation(&explanation, &clauses);
+
+    // The results are explained with the same query, to tell why they rank before the document.
+    let (places, mut warnings) = request_search_documents(
+        &ctx,
+        &request_id,
+        timeout,
+        es_indices_to_search_in,
+        search_area(&filters),
+        filters.limit,
+        QueryType::PREFIX,
+        build_query(Some(&FieldSelection::default().source(false))),
+    )
+    .await?;
+    let rank = places
+        .iter()
+        .position(|place| place.id() == doc_id)
+        .map(|index| index + 1);
+
+    let explanations: Vec<_> = futures::stream::iter(places.iter().map(|place| {
+        explain_in_clusters(&ctx, &dsl, place.id(), place_doc_type(place), &request_id)
+    }))
+    .buffered(EXPLAIN_CONCURRENT_REQUESTS)
+    .collect()
+    .await;
+    let mut results = Vec::new();
+    for (index, (place, explanation)) in places.iter().zip(explanations).enumerate() {
+        match explanation {
+            Ok(explanation) => {
+                let result = ScoreBreakdown::from_explanation(&explanation, &clauses);
+                let lead = if rank.map_or(true, |rank| index + 1 < rank) {
+                    result.lead_over(&breakdown)
+                } else {
+                    Vec::new()
+                };
+                results.push(ExplainedResult {
+                    id: place.id().to_string(),
+                    doc_type: place_doc_type(place).to_string(),
+                    breakdown: result,
+                    lead,
+                });
+            }
+            Err(info) => warnings.push(info),
+        }
+    }
+
+    let response = ExplainResponseBody {
+        explanation,
+        parsed_query: serde_json::to_value(query_parser::parse(&q))
+            .expect("parsed query serialization"),
+        breakdown,
+        rank,
+        results,
+        warnings,
+    };
+    Ok(with_status(json(&response), StatusCode::OK))
+}
+
+// The explanation of the score of a document, by the first cluster holding its type which
+// answers.
+async fn explain_in_clusters<C>(
+    ctx: &Context<C>,
+    dsl: &Value,
+    doc_id: &str,
+    doc_type: &str,
+    request_id: &str,
+) -> Result<Value, String>
+where
+    C: ExplainDocument,
+    C::Document: Into<Value>,
+{
+    let index = root_doctype(doc_type);
+    let mut info = format!("no cluster holds documents of type {}", doc_type);
+    for cluster in &ctx.clusters {
+        if cluster.routing.indices(&[index.clone()]).is_empty() {
+            continue;
+        }
+
+        match cluster
+            .client
+            .explain_document(
+                Query::QueryDSL(dsl.clone()),
+                doc_id.to_string(),
+                doc_type.to_string(),
+            )
+            .instrument(info_span!(
+                "explain_document",
+                %request_id,
+                %doc_id,
+                cluster = %cluster.name,
+            ))
+            .await
+        {
+            Ok(explanation) => return Ok(explanation.into()),
+            Err(err) => info = format!("cluster '{}' failed: {}", cluster.name, err),
+        }
+    }
+    Err(info)
+}
+
+fn place_doc_type(place: &Place) -> &'static str {
+    match place {
+        Place::Admin(_) => Admin::static_doc_type(),
+        Place::Street(_) => Street::static_doc_type(),
+        Place::Addr(_) => Addr::static_doc_type(),
+        Place::Poi(_) => Poi::static_doc_type(),
+        Place::Stop(_) => Stop::static_doc_type(),
     }
 }
 
 pub async fn reverse_geocoder<C>(
     ctx: Context<C>,
     params: ReverseGeocoderQuery,
+    accept_format: Option<Format>,
 ) -> Result<impl warp::Reply, Rejection>
 where
-    C: SearchDocuments,
+    C: SearchDocuments + Send + Sync,
+{
+    let format = params.format.or(accept_format).unwrap_or_default();
+    let result = reverse_response(&ctx, params).await?;
+    formatted(format, result)
+}
+
+/// The places nearest to the location, for the REST and the gRPC APIs.
+pub async fn reverse_response<C>(
+    ctx: &Context<C>,
+    params: ReverseGeocoderQuery,
+) -> Result<GeocodeResult, Rejection>
+where
+    C: SearchDocuments + Send + Sync,
 {
     let timeout = params.timeout.unwrap_or(ctx.settings.autocomplete_timeout);
-    let distance = format!("{}m", ctx.settings.query.reverse_query.radius);
-    let dsl = dsl::build_reverse_query(&distance, params.lat, params.lon);
+    let query_settings = ctx.query_settings(params.profile.as_deref())?;
+    let fields = FieldSelection::new(params.fields.clone(), params.exclude_fields.clone());
+    let distance = format!("{}m", query_settings.reverse_query.radius);
+    let mut dsl = dsl::build_reverse_query(&distance, params.lat, params.lon);
+    dsl["_source"] = fields.source(false);
 
     let es_indices_to_search_in = vec![
         root_doctype(Street::static_doc_type()),
@@ -363,47 +728,172 @@ where
         serde_json::to_string_pretty(&dsl).unwrap()
     );
 
-    let places = ctx
-        .client
-        .search_documents(
-            es_indices_to_search_in,
-            Query::QueryDSL(dsl),
-            params.limit,
-            Some(timeout),
-        )
-        .await
-        .map_err(|err| {
-            warp::reject::custom(InternalError {
-                reason: InternalErrorReason::ElasticSearchError,
-                info: err.to_string(),
-            })
-        })?;
+    let res = clusters::search::<_, Place>(
+        &ctx.clusters,
+        &es_indices_to_search_in,
+        Some(Area::Point(params.lon, params.lat)),
+        &dsl,
+        params.limit,
+        timeout,
+    )
+    .await
+    .map_err(|err| {
+        warp::reject::custom(InternalError {
+            reason: InternalErrorReason::ElasticSearchError,
+            info: err.to_string(),
+        })
+    })?;
 
-    let resp = GeocodeJsonResponse::from_with_lang(places, None);
-    Ok(with_status(json(&resp), StatusCode::OK))
+    // The results of the different clusters are merged by distance to the requested point.
+    let point = geo::Point::new(params.lon, params.lat);
+    let places = clusters::by_distance(res.documents, params.limit.max(0) as usize, |place| {
+        point.haversine_distance(&geo::Point::new(place.coord().lon(), place.coord().lat()))
+    });
+
+    Ok(GeocodeResult {
+        places,
+        warnings: res.warnings,
+        fields,
+        ..GeocodeResult::default()
+    })
+}
+
+pub async fn features<C>(
+    doc_id: String,
+    ctx: Context<C>,
+    params: FeaturesQuery,
+    accept_language: Vec<String>,
+    accept_format: Option<Format>,
+) -> Result<impl warp::Reply, Rejection>
+where
+    C: GetDocuments<Document = Value> + Send + Sync,
+{
+    let format = params.format.or(accept_format).unwrap_or_default();
+    let result = features_response(&ctx, doc_id, params, accept_language).await?;
+    formatted(format, result)
+}
+
+/// The place with the given id, for the REST and the gRPC APIs.
+#[instrument(skip(ctx), fields(request_id = params.request_id.as_deref().unwrap_or_default()))]
+pub async fn features_response<C>(
+    ctx: &Context<C>,
+    doc_id: String,
+    params: FeaturesQuery,
+    accept_language: Vec<String>,
+) -> Result<GeocodeResult, Rejection>
+where
+    C: GetDocuments<Document = Value> + Send + Sync,
+{
+    let timeout = params.timeout.unwrap_or(ctx.settings.features_timeout);
+    let lang = negotiate_lang(params.lang.clone(), &accept_language, &ctx.settings.langs);
+    let indices = build_es_indices_to_search(&None, &params.pt_dataset, &params.poi_dataset);
+    let boundaries = params.with_geometry == Some(WithGeometry::Boundary);
+    let fields = FieldSelection::new(params.fields.clone(), params.exclude_fields.clone());
+    let source = fields.source(boundaries);
+
+    // The document is looked for in each cluster holding some of the indices, until it is
+    // found.
+    let mut warnings = Vec::new();
+    for cluster in &ctx.clusters {
+        let indices = cluster.routing.indices(&indices);
+        if indices.is_empty() {
+            continue;
+        }
+
+        let query = Query::QueryDSL(dsl::build_features_query(&indices, &doc_id, &source));
+        let docs = cluster
+            .client
+            .get_documents_by_id(query, Some(timeout))
+            .instrument(info_span!("get_documents_by_id", cluster = %cluster.name))
+            .await;
+
+        match docs {
+            Ok(docs) => {
+                let places = docs
+                    .into_iter()
+                    .map(serde_json::from_value::<Place>)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| {
+                        warp::reject::custom(InternalError {
+                            reason: InternalErrorReason::SerializationError,
+                            info: err.to_string(),
+                        })
+                    })?;
+                if !places.is_empty() {
+                    return Ok(GeocodeResult {
+                        places,
+                        lang: Some(lang),
+                        warnings,
+                        fields,
+                        boundaries,
+                        tolerance: params.tolerance,
+                        ..GeocodeResult::default()
+                    });
+                }
+            }
+            Err(err) => warnings.push(format!("cluster '{}' failed: {}", cluster.name, err)),
+        }
+    }
+
+    Err(warp::reject::custom(InternalError {
+        reason: InternalErrorReason::ObjectNotFoundError,
+        info: if warnings.is_empty() {
+            format!("no place with id '{}'", doc_id)
+        } else {
+            format!("no place with id '{}': {}", doc_id, warnings.join(", "))
+        },
+    }))
 }
 
 pub async fn status<C>(ctx: Context<C>) -> Result<impl warp::Reply, Rejection>
 where
     C: Status,
 {
-    match ctx.client.status().await {
-        Ok(res) => {
-            let resp = StatusResponseBody {
-                bragi: BragiStatus {
-                    version: VERSION.to_string(),
-                },
-                mimir: MimirStatus {
-                    version: res.version,
-                },
-                elasticsearch: ElasticsearchStatus {
-                    version: res.storage.version,
-                    health: res.storage.health.to_string(),
-                    url: ctx.settings.elasticsearch.url.to_string(),
-                },
-            };
-            Ok(with_status(json(&resp), StatusCode::OK))
-        }
+    let resp = status_response(&ctx).await?;
+    Ok(with_status(json(&resp), StatusCode::OK))
+}
+
+/// The versions and state of bragi and Elasticsearch, for the REST and the gRPC APIs.
+pub async fn status_response<C>(ctx: &Context<C>) -> Result<StatusResponseBody, Rejection>
+where
+    C: Status,
+{
+    match ctx.client().status().await {
+        Ok(res) => Ok(StatusResponseBody {
+            bragi: BragiStatus {
+                version: VERSION.to_string(),
+            },
+            mimir: MimirStatus {
+                version: res.version,
+            },
+            elasticsearch: ElasticsearchStatus {
+                version: res.storage.version,
+                health: res.storage.health.to_string(),
+                url: ctx.settings.elasticsearch.url.to_string(),
+                nodes: res
+                    .storage
+                    .nodes
+                    .into_iter()
+                    .map(|node| ElasticsearchNodeStatus {
+                        url: node.url,
+                        healthy: node.healthy,
+                    })
+                    .collect(),
+                indices: res
+                    .storage
+                    .indices
+                    .into_iter()
+                    .map(|index| ElasticsearchIndexStatus {
+                        index: index.name,
+                        doc_type: index.doc_type,
+                        dataset: index.dataset,
+                        docs_count: index.docs_count,
+                        indexed_at: index.indexed_at,
+                        aliases: index.aliases,
+                    })
+                    .collect(),
+            },
+        }),
         Err(err) => Err(warp::reject::custom(InternalError {
             reason: InternalErrorReason::StatusError,
             info: err.to_string(),
@@ -411,6 +901,85 @@ where
     }
 }
 
+/// Answers as long as bragi runs, without querying Elasticsearch.
+pub async fn health_live() -> Result<impl warp::Reply, Rejection> {
+    Ok(with_status(
+        json(&LivenessResponseBody {
+            status: String::from("alive"),
+        }),
+        StatusCode::OK,
+    ))
+}
+
+/// Answers 503 (Service Unavailable) until all the clusters are healthy, with the required
+/// aliases holding documents and up to date index templates, see 'ReadinessSettings'.
+pub async fn health_ready<C>(ctx: Context<C>) -> Result<impl warp::Reply, Rejection>
+where
+    C: Status + Send + Sync,
+{
+    let statuses = futures::future::join_all(ctx.clusters.iter().map(|cluster| async move {
+        let status = cluster
+            .client
+            .status()
+            .await
+            .map(|status| status.storage)
+            .map_err(|err| err.to_string());
+        (cluster.name.clone(), status)
+    }))
+    .await;
+
+    let checks = health::readiness_checks(&ctx.settings.readiness, &statuses);
+    let ready = checks.iter().all(|check| check.ok);
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok(with_status(
+        json(&ReadinessResponseBody { ready, checks }),
+        code,
+    ))
+}
+
+/// Reads the ranking profiles again from their source, and returns their names.
+pub async fn reload_profiles<C>(ctx: Context<C>) -> Result<impl warp::Reply, Rejection> {
+    let source = ctx.profiles_source.as_ref().ok_or_else(|| {
+        warp::reject::custom(InternalError {
+            reason: InternalErrorReason::ProfilesError,
+            info: "the ranking profiles have no source to be reloaded from".to_string(),
+        })
+    })?;
+
+    let profiles = source.load().map_err(|err| {
+        warp::reject::custom(InternalError {
+            reason: InternalErrorReason::ProfilesError,
+            info: err.to_string(),
+        })
+    })?;
+
+    let names = profiles.names();
+    *ctx.profiles.write().expect("profiles lock poisoned") = profiles;
+    tracing::info!("Reloaded ranking profiles: {}", names.join(", "));
+
+    Ok(with_status(
+        json(&ProfilesResponseBody { profiles: names }),
+        StatusCode::OK,
+    ))
+}
+
+/// The OpenAPI specification of the API.
+pub async fn openapi<C>(ctx: Context<C>) -> Result<impl warp::Reply, Rejection> {
+    Ok(with_status(
+        json(&openapi::spec(&ctx.settings.api_keys)),
+        StatusCode::OK,
+    ))
+}
+
+/// The OpenAPI specification, displayed with Swagger UI.
+pub async fn swagger_ui() -> Result<impl warp::Reply, Rejection> {
+    Ok(warp::reply::html(openapi::SWAGGER_UI))
+}
+
 pub async fn metrics() -> Result<impl warp::Reply, Rejection> {
     let reply = warp::reply::with_header(
         prometheus_handler::metrics(),
@@ -488,3 +1057,48 @@ pub fn build_es_indices_to_search(
         indices
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_negotiate_an_indexed_language() {
+        let langs = vec![String::from("fr"), String::from("en")];
+        let accepted = |langs: &[&str]| {
+            langs
+                .iter()
+                .map(|lang| lang.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(negotiate_lang(None, &accepted(&["de", "fr"]), &langs), "fr");
+        assert_eq!(negotiate_lang(None, &accepted(&["en", "fr"]), &langs), "en");
+        assert_eq!(
+            negotiate_lang(None, &accepted(&["de"]), &langs),
+            DEFAULT_LANG
+        );
+        assert_eq!(
+            negotiate_lang(Some(String::from("de")), &accepted(&["en"]), &langs),
+            "de"
+        );
+    }
+
+    #[test]
+    fn should_reject_the_geometry_on_the_autocomplete() {
+        assert!(reject_search_only_parameters(&ForwardGeocoderQuery::default()).is_ok());
+
+        let params = ForwardGeocoderQuery {
+            with_geometry: Some(WithGeometry::Boundary),
+            ..ForwardGeocoderQuery::default()
+        };
+        let rejection = reject_search_only_parameters(&params).unwrap_err();
+        assert!(rejection.find::<InvalidRequest>().is_some());
+
+        let params = ForwardGeocoderQuery {
+            tolerance: Some(0.001),
+            ..ForwardGeocoderQuery::default()
+        };
+        assert!(reject_search_only_parameters(&params).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! Readiness of bragi: it is ready to serve requests when all its clusters are healthy, hold the
+//! documents of the required aliases, and use the current index templates.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapters::primary::bragi::api::ReadinessCheck,
+    domain::model::status::{StorageHealth, StorageStatus},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadinessSettings {
+    /// Aliases which must exist and hold documents, in any of the clusters.
+    pub aliases: Vec<String>,
+    /// Minimal version of the index templates of mimir found in each cluster.
+    pub template_version: u64,
+}
+
+impl Default for ReadinessSettings {
+    fn default() -> Self {
+        ReadinessSettings {
+            aliases: ["munin_admin", "munin_street", "munin_addr"]
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            template_version: 3,
+        }
+    }
+}
+
+/// The checks of readiness, given the status of each cluster or the error retrieving it.
+pub fn readiness_checks(
+    settings: &ReadinessSettings,
+    clusters: &[(String, Result<StorageStatus, String>)],
+) -> Vec<ReadinessCheck> {
+    let mut checks = Vec::new();
+
+    for (name, status) in clusters {
+        match status {
+            Ok(status) => {
+                checks.push(ReadinessCheck {
+                    name: format!("{} health", name),
+                    ok: matches!(status.health, StorageHealth::OK),
+                    details: status.health.to_string(),
+                });
+                checks.push(templates_check(settings, name, status));
+            }
+            Err(err) => checks.push(ReadinessCheck {
+                name: format!("{} health", name),
+                ok: false,
+                details: err.clone(),
+            }),
+        }
+    }
+
+    for alias in &settings.aliases {
+        let indices: Vec<_> = clusters
+            .iter()
+            .filter_map(|(_, status)| status.as_ref().ok())
+            .flat_map(|status| status.indices.iter())
+            .filter(|index| index.aliases.contains(alias))
+            .collect();
+        let docs_count: u64 = indices.iter().map(|index| index.docs_count).sum();
+        checks.push(ReadinessCheck {
+            name: format!("alias {}", alias),
+            ok: docs_count > 0,
+            details: if indices.is_empty() {
+                String::from("missing")
+            } else {
+                format!("{} documents", docs_count)
+            },
+        });
+    }
+
+    checks
+}
+
+fn templates_check(
+    settings: &ReadinessSettings,
+    cluster: &str,
+    status: &StorageStatus,
+) -> ReadinessCheck {
+    let outdated: Vec<_> = status
+        .templates
+        .iter()
+        .filter(|template| {
+            template
+                .version
+                .map_or(true, |version| version < settings.template_version)
+        })
+        .map(|template| match template.version {
+            Some(version) => format!("{} (version {})", template.name, version),
+            None => format!("{} (no version)", template.name),
+        })
+        .collect();
+
+    let details = if status.templates.is_empty() {
+        String::from("no index template")
+    } else if outdated.is_empty() {
+        format!("version {} or later", settings.template_version)
+    } else {
+        format!("outdated: {}", outdated.join(", "))
+    };
+
+    ReadinessCheck {
+        name: format!("{} templates", cluster),
+        ok: !status.templates.is_empty() && outdated.is_empty(),
+        details,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::model::status::{StorageIndex, StorageTemplate};
+
+    fn storage_status(indices: &[(&str, u64)], template_version: u64) -> StorageStatus {
+        StorageStatus {
+            health: StorageHealth::OK,
+            version: String::from("7.13.0"),
+            nodes: Vec::new(),
+            indices: indices
+                .iter()
+                .map(|(doc_type, docs_count)| StorageIndex {
+                    name: format!("munin_{}_fr_20220101_000000_000000000", doc_type),
+                    doc_type: doc_type.to_string(),
+                    dataset: String::from("fr"),
+                    docs_count: *docs_count,
+                    indexed_at: None,
+                    aliases: vec![format!("munin_{}", doc_type), String::from("munin")],
+                })
+                .collect(),
+            templates: vec![StorageTemplate {
+                name: String::from("mimir-addr"),
+                version: Some(template_version),
+            }],
+        }
+    }
+
+    fn failed(checks: &[ReadinessCheck]) -> Vec<&str> {
+        checks
+            .iter()
+            .filter(|check| !check.ok)
+            .map(|check| check.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn should_be_ready_with_required_aliases_across_clusters() {
+        let clusters = vec![
+            (
+                String::from("default"),
+                Ok(storage_status(&[("admin", 10), ("street", 20)], 3)),
+            ),
+            (String::from("de"), Ok(storage_status(&[("addr", 30)], 3))),
+        ];
+
+        let checks = readiness_checks(&ReadinessSettings::default(), &clusters);
+
+        assert!(failed(&checks).is_empty(), "{:?}", checks);
+    }
+
+    #[test]
+    fn should_not_be_ready_with_missing_or_empty_aliases() {
+        let clusters = vec![(
+            String::from("default"),
+            Ok(storage_status(&[("admin", 10), ("street", 0)], 3)),
+        )];
+
+        let checks = readiness_checks(&ReadinessSettings::default(), &clusters);
+
+        assert_eq!(
+            failed(&checks),
+            vec!["alias munin_street", "alias munin_addr"]
+        );
+        assert_eq!(checks.last().unwrap().details, "missing");
+    }
+
+    #[test]
+    fn should_not_be_ready_with_outdated_templates_or_unreachable_cluster() {
+        let clusters = vec![
+            (
+                String::from("default"),
+                Ok(storage_status(
+                    &[("admin", 1), ("street", 1), ("addr", 1)],
+                    2,
+                )),
+            ),
+            (String::from("de"), Err(String::from("connection refused"))),
+        ];
+
+        let checks = readiness_checks(&ReadinessSettings::default(), &clusters);
+
+        assert_eq!(failed(&checks), vec!["default templates", "de health"]);
+        assert_eq!(checks[1].details, "outdated: mimir-addr (version 2)");
+    }
+}
@@ -1,4 +1,9 @@
 pub mod api;
+pub mod api_keys;
+pub mod clusters;
+pub mod grpc;
 pub mod handlers;
+pub mod health;
+pub mod openapi;
 pub mod prometheus_handler;
 pub mod routes;
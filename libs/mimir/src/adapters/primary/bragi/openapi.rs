@@ -0,0 +1,361 @@
+//! OpenAPI 3 specification of the REST API of bragi, generated from the types of its requests
+//! and responses, so that it follows them.
+
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde_json::{json, Map, Value};
+
+use crate::adapters::primary::{
+    bragi::{
+        api::{
+            ExplainResponseBody, FeaturesQuery, ForwardGeocoderBody, ForwardGeocoderExplainQuery,
+            ForwardGeocoderQuery, LivenessResponseBody, ProfilesResponseBody,
+            ReadinessResponseBody, ReverseGeocoderQuery, StatusResponseBody,
+        },
+        api_keys::ApiKeysSettings,
+        routes::ApiError,
+    },
+    common::geocoding::GeocodeJsonResponse,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Static page displaying the specification with Swagger UI.
+pub const SWAGGER_UI: &str = include_str!("swagger-ui.html");
+
+/// The specification, with the API keys carried as configured.
+pub fn spec(api_keys: &ApiKeysSettings) -> Value {
+    let mut gen = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+
+    let forward = query_parameters::<ForwardGeocoderQuery>(&mut gen);
+    let geocode_json = response_schema::<GeocodeJsonResponse>(&mut gen);
+    let api_error = response_schema::<ApiError>(&mut gen);
+    let api_key_security = json!([{ "api_key_header": [] }, { "api_key_query": [] }, {}]);
+
+    let forward_responses = json!({
+        "200": places_response("Places matching the query", &geocode_json),
+        "400": json_response("Invalid query", &api_error),
+        "401": json_response("Invalid API key", &api_error),
+        "429": json_response("Rate limit or daily quota exceeded, see Retry-After", &api_error),
+    });
+
+    add(
+        &mut paths,
+        "/api/v1/autocomplete",
+        "get",
+        json!({
+            "summary": "Searches places matching the beginning of the query",
+            "parameters": forward,
+            "security": api_key_security,
+            "responses": forward_responses,
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/autocomplete",
+        "post",
+        json!({
+            "summary": "Searches places matching the beginning of the query within a shape",
+            "parameters": forward,
+            "requestBody": {
+                "required": true,
+                "content": {
+                    "application/json": { "schema": gen.subschema_for::<ForwardGeocoderBody>() }
+                }
+            },
+            "security": api_key_security,
+            "responses": forward_responses,
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/search",
+        "get",
+        json!({
+            "summary": "Searches places matching the whole query",
+            "parameters": forward,
+            "security": api_key_security,
+            "responses": forward_responses,
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/reverse",
+        "get",
+        json!({
+            "summary": "Searches the places nearest to a location",
+            "parameters": query_parameters::<ReverseGeocoderQuery>(&mut gen),
+            "security": api_key_security,
+            "responses": forward_responses,
+        }),
+    );
+    let mut features = query_parameters::<FeaturesQuery>(&mut gen);
+    features.insert(
+        0,
+        json!({
+            "name": "id",
+            "in": "path",
+            "required": true,
+            "description": "Id of the place, eg. 'admin:osm:relation:7444'.",
+            "schema": { "type": "string" },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/features/{id}",
+        "get",
+        json!({
+            "summary": "Returns the place with the given id",
+            "parameters": features,
+            "security": api_key_security,
+            "responses": {
+                "200": places_response("The place", &geocode_json),
+                "400": json_response("Invalid query, or unknown place", &api_error),
+                "401": json_response("Invalid API key", &api_error),
+                "429": json_response("Rate limit or daily quota exceeded, see Retry-After", &api_error),
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/autocomplete-explain",
+        "get",
+        json!({
+            "summary": "Explains the score of a document for a query",
+            "parameters": query_parameters::<ForwardGeocoderExplainQuery>(&mut gen),
+            "security": api_key_security,
+            "responses": {
+                "200": json_response(
+                    "Details of the score",
+                    &response_schema::<ExplainResponseBody>(&mut gen)
+                ),
+                "400": json_response("Invalid query", &api_error),
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/status",
+        "get",
+        json!({
+            "summary": "Versions and state of bragi and Elasticsearch, with the indices",
+            "responses": {
+                "200": json_response("Status", &response_schema::<StatusResponseBody>(&mut gen)),
+                "400": json_response("Elasticsearch could not be reached", &api_error),
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/admin/profiles/reload",
+        "post",
+        json!({
+            "summary": "Reads the ranking profiles again",
+            "security": [{ "admin_token": [] }],
+            "responses": {
+                "200": json_response(
+                    "Names of the profiles",
+                    &response_schema::<ProfilesResponseBody>(&mut gen)
+                ),
+                "401": json_response("Invalid admin token", &api_error),
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/metrics",
+        "get",
+        json!({
+            "summary": "Metrics in the Prometheus format",
+            "responses": {
+                "200": { "description": "Metrics", "content": { "text/plain": {} } },
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/openapi.json",
+        "get",
+        json!({
+            "summary": "This specification",
+            "responses": {
+                "200": { "description": "OpenAPI specification", "content": { "application/json": {} } },
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/api/v1/docs",
+        "get",
+        json!({
+            "summary": "This specification, displayed with Swagger UI",
+            "responses": {
+                "200": { "description": "Swagger UI", "content": { "text/html": {} } },
+            },
+        }),
+    );
+    add(
+        &mut paths,
+        "/health/live",
+        "get",
+        json!({
+            "summary": "Answers as long as bragi runs",
+            "responses": {
+                "200": json_response("Alive", &response_schema::<LivenessResponseBody>(&mut gen)),
+            },
+        }),
+    );
+    let readiness = response_schema::<ReadinessResponseBody>(&mut gen);
+    add(
+        &mut paths,
+        "/health/ready",
+        "get",
+        json!({
+            "summary": "Checks that bragi can serve requests",
+            "responses": {
+                "200": json_response("Ready", &readiness),
+                "503": json_response("Not ready, see the failed checks", &readiness),
+            },
+        }),
+    );
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Bragi",
+            "description": "REST API of the geocoder mimirsbrunn",
+            "version": VERSION,
+        },
+        "paths": paths,
+        "components": {
+            "schemas": gen.definitions(),
+            "securitySchemes": {
+                "api_key_header": { "type": "apiKey", "in": "header", "name": api_keys.header },
+                "api_key_query": { "type": "apiKey", "in": "query", "name": api_keys.query_param },
+                "admin_token": { "type": "http", "scheme": "bearer" },
+            },
+        },
+    })
+}
+
+fn add(paths: &mut Map<String, Value>, path: &str, method: &str, operation: Value) {
+    paths
+        .entry(path)
+        .or_insert_with(|| json!({}))
+        .as_object_mut()
+        .expect("path item")
+        .insert(method.to_string(), operation);
+}
+
+fn response_schema<T: JsonSchema>(gen: &mut schemars::gen::SchemaGenerator) -> Value {
+    serde_json::to_value(gen.subschema_for::<T>()).expect("JSON schema")
+}
+
+fn json_response(description: &str, schema: &Value) -> Value {
+    json!({
+        "description": description,
+        "content": { "application/json": { "schema": schema } },
+    })
+}
+
+// GeocodeJSON, or one of the other formats given by the 'format' parameter or the Accept header.
+fn places_response(description: &str, schema: &Value) -> Value {
+    json!({
+        "description": description,
+        "content": {
+            "application/json": { "schema": schema },
+            "application/geo+json": {},
+            "text/csv": {},
+        },
+    })
+}
+
+// Each field of the query is a parameter. Lists are given by repeating the parameter with
+// brackets, eg. 'type[]=poi&type[]=street', as parsed by serde_qs.
+fn query_parameters<T: JsonSchema>(gen: &mut schemars::gen::SchemaGenerator) -> Vec<Value> {
+    let schema = serde_json::to_value(T::json_schema(gen)).expect("JSON schema");
+    let required: Vec<&str> = schema["required"]
+        .as_array()
+        .map(|required| required.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    schema["properties"]
+        .as_object()
+        .map(|properties| {
+            properties
+                .iter()
+                .map(|(name, property)| {
+                    let mut property = property.clone();
+                    let description = property
+                        .as_object_mut()
+                        .and_then(|property| property.remove("description"));
+                    let name = if property["type"] == "array" {
+                        format!("{}[]", name)
+                    } else {
+                        name.clone()
+                    };
+                    let mut parameter = json!({
+                        "name": name,
+                        "in": "query",
+                        "required": required.contains(&name.as_str()),
+                        "schema": property,
+                    });
+                    if let Some(description) = description {
+                        parameter["description"] = description;
+                    }
+                    parameter
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_describe_query_parameters() {
+        let spec = spec(&ApiKeysSettings::default());
+        let parameters = spec["paths"]["/api/v1/autocomplete"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        let parameter = |name: &str| {
+            parameters
+                .iter()
+                .find(|parameter| parameter["name"] == name)
+                .unwrap_or_else(|| panic!("missing parameter {}", name))
+        };
+
+        assert_eq!(parameter("q")["schema"]["type"], "string");
+        assert_eq!(
+            parameter("type[]")["schema"]["items"]["$ref"],
+            "#/components/schemas/Type"
+        );
+        assert_eq!(parameter("proximity_scale")["schema"]["type"], "number");
+        assert!(parameter("timeout")["description"]
+            .as_str()
+            .unwrap()
+            .contains("milliseconds"));
+
+        let reverse = spec["paths"]["/api/v1/reverse"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(reverse
+            .iter()
+            .any(|parameter| parameter["name"] == "lat" && parameter["required"] == true));
+    }
+
+    #[test]
+    fn should_define_all_referenced_schemas() {
+        let spec = spec(&ApiKeysSettings::default());
+        let text = spec.to_string();
+        let schemas = spec["components"]["schemas"].as_object().unwrap();
+
+        for reference in text.split("\"#/components/schemas/").skip(1) {
+            let name = reference.split('"').next().unwrap();
+            assert!(schemas.contains_key(name), "undefined schema {}", name);
+        }
+        assert!(schemas.contains_key("GeocodeJsonProperty"));
+    }
+}
@@ -8,6 +8,8 @@ lazy_static::lazy_static! {
         map.insert("/api/v1/", "/");
         map.insert("/api/v1/metrics", "metrics");
         map.insert("/api/v1/status", "status");
+        map.insert("/health/live", "health-live");
+        map.insert("/health/ready", "health-ready");
         map.insert("/api/v1/reverse", "reverse");
         map.insert("/api/v1/autocomplete", "autocomplete");
         map.insert("/api/v1/autocomplete-explain", "autocomplete-explain");
@@ -39,7 +41,7 @@ lazy_static::lazy_static! {
     static ref HTTP_COUNTER: prometheus::CounterVec = prometheus::register_counter_vec!(
         "bragi_http_requests_total",
         "Total number of HTTP requests made.",
-        &["handler", "method", "status"]
+        &["handler", "method", "status", "client"]
     )
     .unwrap();
 
@@ -51,6 +53,13 @@ lazy_static::lazy_static! {
     )
     .unwrap();
 
+    static ref GRPC_COUNTER: prometheus::CounterVec = prometheus::register_counter_vec!(
+        "bragi_grpc_requests_total",
+        "Total number of gRPC requests made.",
+        &["method", "code", "client"]
+    )
+    .unwrap();
+
     static ref HTTP_IN_FLIGHT: prometheus::Gauge = prometheus::register_gauge!(
         "bragi_http_requests_in_flight",
         "current number of http request being served"
@@ -68,20 +77,53 @@ pub fn update_metrics(info: warp::log::Info) {
         info.elapsed()
     );
     let method = info.method().to_string();
-    let status = info.status().as_u16().to_string();
     let handler = get_ressource_name(info.path());
 
     HTTP_REQ_HISTOGRAM
         .with_label_values(&[&handler, &method])
         .observe(info.elapsed().as_secs_f64());
 
+    HTTP_IN_FLIGHT.dec();
+}
+
+/// Counts a request in 'bragi_http_requests_total'. The client is the one of the API key.
+#[cfg(feature = "metrics")]
+pub fn count_request(
+    path: &str,
+    method: &warp::http::Method,
+    status: warp::http::StatusCode,
+    client: &str,
+) {
     HTTP_COUNTER
-        .with_label_values(&[&handler, &method, &status])
+        .with_label_values(&[
+            &get_ressource_name(path),
+            method.as_str(),
+            status.as_str(),
+            client,
+        ])
         .inc();
+}
 
-    HTTP_IN_FLIGHT.dec();
+#[cfg(not(feature = "metrics"))]
+pub fn count_request(
+    _path: &str,
+    _method: &warp::http::Method,
+    _status: warp::http::StatusCode,
+    _client: &str,
+) {
+}
+
+/// Counts a call of the gRPC API in 'bragi_grpc_requests_total', by status code and client.
+#[cfg(feature = "metrics")]
+pub fn count_grpc_request(method: &str, code: &str, client: &str) {
+    GRPC_COUNTER
+        .with_label_values(&[method, code, client])
+        .inc();
 }
 
+#[cfg(not(feature = "metrics"))]
+pub fn count_grpc_request(_method: &str, _code: &str, _client: &str) {}
+
 #[cfg(not(feature = "prometheus"))]
 pub fn update_metrics(_info: warp::log::Info) {}
 
@@ -1,22 +1,42 @@
-use crate::adapters::primary::bragi::{
-    api::{ForwardGeocoderQuery, Type},
-    handlers::{InternalError, InternalErrorReason},
+use crate::{
+    adapters::primary::{
+        bragi::{
+            api::{ForwardGeocoderQuery, Type},
+            api_keys::{ApiKeys, Refusal},
+            handlers::{InternalError, InternalErrorReason},
+            prometheus_handler,
+        },
+        common::formats::Format,
+    },
+    utils::deserialize::deserialize_opt_duration,
 };
 use futures::future;
 use geojson::{GeoJson, Geometry};
+use schemars::JsonSchema;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_qs::Config;
-use std::convert::Infallible;
+use snafu::Snafu;
+use std::{
+    collections::hash_map::DefaultHasher,
+    convert::Infallible,
+    hash::{Hash, Hasher},
+    sync::Arc,
+    time::Duration,
+};
 use tracing::instrument;
 use warp::{
-    http::StatusCode,
+    http::{
+        header::{HeaderName, CONTENT_LENGTH, ETAG, RETRY_AFTER, VARY},
+        HeaderMap, HeaderValue, Method, StatusCode, Uri,
+    },
+    path::FullPath,
     reject::{MethodNotAllowed, Reject},
     Filter, Rejection, Reply,
 };
 
 use super::api::ForwardGeocoderBody;
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, JsonSchema)]
 pub struct ApiError {
     pub short: String,
     pub long: String,
@@ -30,6 +50,8 @@ pub enum InvalidRequestReason {
     InconsistentZoneRequest,
     InconsistentLatLonRequest,
     OutOfRangeLatLonRequest,
+    UnknownProfile,
+    SearchOnlyParameter,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -48,6 +70,23 @@ impl Reject for InvalidRequest {}
 struct InvalidPostBody;
 impl Reject for InvalidPostBody {}
 
+#[derive(Debug)]
+struct Unauthorized;
+impl Reject for Unauthorized {}
+
+#[derive(Debug)]
+struct InvalidApiKey {
+    info: String,
+}
+impl Reject for InvalidApiKey {}
+
+#[derive(Debug)]
+struct TooManyRequests {
+    info: String,
+    retry_after: Duration,
+}
+impl Reject for TooManyRequests {}
+
 pub trait Validate {
     fn filter(&self) -> Result<(), Rejection> {
         Ok(())
@@ -129,6 +168,145 @@ pub fn validate_geojson_body(
         })
 }
 
+/// This filter extracts the languages of the Accept-Language header, by decreasing quality.
+pub fn accept_language() -> impl Filter<Extract = (Vec<String>,), Error = Infallible> + Copy {
+    warp::header::optional::<String>("accept-language")
+        .map(|header: Option<String>| {
+            header
+                .as_deref()
+                .map(parse_accept_language)
+                .unwrap_or_default()
+        })
+        .or(warp::any().map(Vec::new))
+        .unify()
+}
+
+/// This filter extracts the format of the response negotiated with the Accept header, if any.
+pub fn accept_format() -> impl Filter<Extract = (Option<Format>,), Error = Infallible> + Copy {
+    warp::header::optional::<String>("accept")
+        .map(|header: Option<String>| header.as_deref().and_then(Format::from_accept))
+        .or(warp::any().map(|| None))
+        .unify()
+}
+
+/// This filter lets the request through only if it holds the admin token in an
+/// 'Authorization: Bearer <token>' header. Without admin token, admin requests are refused.
+pub fn admin_authorization(
+    admin_token: Option<String>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let authorized = match (&admin_token, header) {
+                (Some(token), Some(header)) => {
+                    header.strip_prefix("Bearer ").map(str::trim) == Some(token.as_str())
+                }
+                _ => false,
+            };
+            future::ready(if authorized {
+                Ok(())
+            } else {
+                Err(warp::reject::custom(Unauthorized))
+            })
+        })
+        .untuple_one()
+}
+
+// The API key of the request, in the header or else in the query, if any.
+fn request_api_key(
+    keys: Arc<ApiKeys>,
+) -> impl Filter<Extract = (Option<String>,), Error = Infallible> + Clone {
+    warp::header::headers_cloned()
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .map(move |headers: HeaderMap, query: String| {
+            let header = headers
+                .get(keys.settings.header.as_str())
+                .and_then(|value| value.to_str().ok());
+            keys.key_of(header, &query)
+        })
+}
+
+/// This filter lets the request through if its API key is valid and within the limits of its
+/// client, see 'ApiKeysSettings'.
+pub fn api_key(keys: Arc<ApiKeys>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    request_api_key(keys.clone())
+        .and_then(move |key: Option<String>| {
+            let admission = keys.admit(key.as_deref()).map_err(|refusal| match refusal {
+                Refusal::MissingKey => warp::reject::custom(InvalidApiKey {
+                    info: "an API key is required".to_string(),
+                }),
+                Refusal::InvalidKey => warp::reject::custom(InvalidApiKey {
+                    info: "invalid API key".to_string(),
+                }),
+                Refusal::RateLimited { retry_after } => warp::reject::custom(TooManyRequests {
+                    info: "rate limit exceeded".to_string(),
+                    retry_after,
+                }),
+                Refusal::QuotaExceeded { retry_after } => warp::reject::custom(TooManyRequests {
+                    info: "daily quota exceeded".to_string(),
+                    retry_after,
+                }),
+            });
+            future::ready(admission.map(|_client| ()))
+        })
+        .untuple_one()
+}
+
+/// Counts the replies of the filter in 'bragi_http_requests_total', by client.
+pub fn count_requests<F, T>(
+    keys: Arc<ApiKeys>,
+    filter: F,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync,
+    T: Reply,
+{
+    warp::method()
+        .and(warp::path::full())
+        .and(request_api_key(keys.clone()))
+        .and(filter)
+        .map(
+            move |method: Method, path: FullPath, key: Option<String>, reply: T| {
+                let response = reply.into_response();
+                prometheus_handler::count_request(
+                    path.as_str(),
+                    &method,
+                    response.status(),
+                    &keys.client(key.as_deref()),
+                );
+                response
+            },
+        )
+}
+
+/// Returns the primary subtags of the languages of an Accept-Language header, by decreasing
+/// quality (eg 'nl', 'fr' for 'nl-BE,nl;q=0.9,fr;q=0.8'). The wildcard is ignored.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut langs: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty() && *tag != "*")?;
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            Some((tag, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+
+    // The sort is stable, so languages with the same quality keep the order of the header.
+    langs.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut primary_tags: Vec<String> = Vec::new();
+    for (tag, _) in langs {
+        let primary_tag = tag.split('-').next().unwrap_or(tag).to_lowercase();
+        if !primary_tags.contains(&primary_tag) {
+            primary_tags.push(primary_tag);
+        }
+    }
+    primary_tags
+}
+
 pub async fn report_invalid(rejection: Rejection) -> Result<impl Reply, Infallible> {
     let reply = if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
         tracing::info!("Invalid query {:?}", err);
@@ -161,6 +339,33 @@ pub async fn report_invalid(rejection: Rejection) -> Result<impl Reply, Infallib
             }),
             StatusCode::BAD_REQUEST,
         )
+    } else if rejection.find::<Unauthorized>().is_some() {
+        tracing::info!("Unauthorized request");
+        warp::reply::with_status(
+            warp::reply::json(&ApiError {
+                short: "unauthorized".to_string(),
+                long: "a valid admin token is required".to_string(),
+            }),
+            StatusCode::UNAUTHORIZED,
+        )
+    } else if let Some(err) = rejection.find::<InvalidApiKey>() {
+        tracing::info!("Invalid API key {:?}", err);
+        warp::reply::with_status(
+            warp::reply::json(&ApiError {
+                short: "unauthorized".to_string(),
+                long: err.info.clone(),
+            }),
+            StatusCode::UNAUTHORIZED,
+        )
+    } else if let Some(err) = rejection.find::<TooManyRequests>() {
+        tracing::info!("Too many requests {:?}", err);
+        warp::reply::with_status(
+            warp::reply::json(&ApiError {
+                short: "too many requests".to_string(),
+                long: err.info.clone(),
+            }),
+            StatusCode::TOO_MANY_REQUESTS,
+        )
     } else if let Some(err) = rejection.find::<MethodNotAllowed>() {
         tracing::info!("MethodNotAllowed {:?}", err);
         warp::reply::with_status(
@@ -180,8 +385,14 @@ pub async fn report_invalid(rejection: Rejection) -> Result<impl Reply, Infallib
             StatusCode::INTERNAL_SERVER_ERROR,
         )
     };
-    let reply = warp::reply::with_header(reply, "content-type", "application/json");
-    Ok(reply)
+    let mut response =
+        warp::reply::with_header(reply, "content-type", "application/json").into_response();
+    if let Some(err) = rejection.find::<TooManyRequests>() {
+        // Retry-After is given in whole seconds, rounded up.
+        let seconds = err.retry_after.as_secs() + u64::from(err.retry_after.subsec_nanos() > 0);
+        response.headers_mut().insert(RETRY_AFTER, seconds.into());
+    }
+    Ok(response)
 }
 
 pub fn cache_filter<F, T>(
@@ -201,3 +412,399 @@ where
         )
     })
 }
+
+/// Cross-origin requests from browsers. CORS is disabled without allowed origins.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsSettings {
+    /// Origins allowed to call bragi, eg. 'https://example.com', or '*' for any origin.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may keep the answer to a preflight request.
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub max_age: Option<Duration>,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        CorsSettings {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec![String::from("GET"), String::from("POST")],
+            allowed_headers: vec![
+                String::from("accept-language"),
+                String::from("content-type"),
+                String::from("x-api-key"),
+            ],
+            max_age: None,
+        }
+    }
+}
+
+#[derive(Debug, Snafu)]
+pub enum CorsError {
+    #[snafu(display("Invalid CORS origin '{}': expected eg. 'https://example.com'", origin))]
+    InvalidOrigin { origin: String },
+
+    #[snafu(display("Invalid CORS method '{}'", method))]
+    InvalidMethod { method: String },
+
+    #[snafu(display("Invalid CORS header '{}'", header))]
+    InvalidHeader { header: String },
+}
+
+/// The CORS filter of the settings, if CORS is enabled. Invalid origins, methods or headers are
+/// reported before the filter is built, as it would panic on them.
+pub fn cors(settings: &CorsSettings) -> Result<Option<warp::cors::Cors>, CorsError> {
+    if settings.allowed_origins.is_empty() {
+        return Ok(None);
+    }
+    if let Some(origin) = settings
+        .allowed_origins
+        .iter()
+        .find(|origin| *origin != "*" && !is_valid_origin(origin))
+    {
+        return Err(CorsError::InvalidOrigin {
+            origin: origin.clone(),
+        });
+    }
+    if let Some(method) = settings
+        .allowed_methods
+        .iter()
+        .find(|method| Method::from_bytes(method.as_bytes()).is_err())
+    {
+        return Err(CorsError::InvalidMethod {
+            method: method.clone(),
+        });
+    }
+    if let Some(header) = settings
+        .allowed_headers
+        .iter()
+        .find(|header| HeaderName::from_bytes(header.as_bytes()).is_err())
+    {
+        return Err(CorsError::InvalidHeader {
+            header: header.clone(),
+        });
+    }
+
+    let builder = if settings.allowed_origins.iter().any(|origin| origin == "*") {
+        warp::cors().allow_any_origin()
+    } else {
+        warp::cors().allow_origins(settings.allowed_origins.iter().map(String::as_str))
+    };
+    let builder = builder
+        .allow_methods(settings.allowed_methods.iter().map(String::as_str))
+        .allow_headers(settings.allowed_headers.iter().map(String::as_str));
+    let builder = match settings.max_age {
+        Some(max_age) => builder.max_age(max_age),
+        None => builder,
+    };
+    Ok(Some(builder.build()))
+}
+
+// An origin is a scheme and a host, with an optional port: 'https://example.com:8080'.
+fn is_valid_origin(origin: &str) -> bool {
+    if !origin.contains("://") {
+        return false;
+    }
+    match origin.parse::<Uri>() {
+        Ok(uri) => {
+            uri.scheme().is_some()
+                && uri.authority().is_some()
+                && uri.path() == "/"
+                && uri.query().is_none()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Encodings of the responses, negotiated with the Accept-Encoding header of the request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionSettings {
+    pub gzip: bool,
+    pub brotli: bool,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        CompressionSettings {
+            gzip: true,
+            brotli: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+/// Returns the enabled encoding with the highest quality in an Accept-Encoding header (eg.
+/// 'gzip' for 'gzip, deflate, br;q=0.5'), brotli being preferred at equal quality. Without
+/// such an encoding, the response is not compressed.
+pub fn negotiate_encoding(header: &str, settings: &CompressionSettings) -> Option<Encoding> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';').map(str::trim);
+            let encoding = match parts.next()? {
+                "br" if settings.brotli => Encoding::Brotli,
+                "gzip" if settings.gzip => Encoding::Gzip,
+                _ => return None,
+            };
+            let quality = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            Some((encoding, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .fold(
+            None,
+            |best: Option<(Encoding, f32)>, (encoding, quality)| match best {
+                Some((best_encoding, best_quality))
+                    if best_quality > quality
+                        || (best_quality == quality && best_encoding == Encoding::Brotli) =>
+                {
+                    best
+                }
+                _ => Some((encoding, quality)),
+            },
+        )
+        .map(|(encoding, _)| encoding)
+}
+
+// Lets the request through only if its response is to be compressed with the encoding.
+fn accepts_encoding(
+    encoding: Encoding,
+    settings: CompressionSettings,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("accept-encoding")
+        .and_then(move |header: Option<String>| {
+            let negotiated = header
+                .as_deref()
+                .and_then(|header| negotiate_encoding(header, &settings));
+            future::ready(if negotiated == Some(encoding) {
+                Ok(())
+            } else {
+                Err(warp::reject::not_found())
+            })
+        })
+        .untuple_one()
+}
+
+/// Compresses the replies of the filter with the encoding negotiated with the request.
+pub fn compression<F, T>(
+    filter: F,
+    settings: CompressionSettings,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync + 'static,
+    T: Reply,
+{
+    let brotli = accepts_encoding(Encoding::Brotli, settings.clone())
+        .and(filter.clone())
+        .with(warp::compression::brotli())
+        .map(Reply::into_response);
+    let gzip = accepts_encoding(Encoding::Gzip, settings)
+        .and(filter.clone())
+        .with(warp::compression::gzip())
+        .map(Reply::into_response);
+
+    brotli
+        .or(gzip)
+        .unify()
+        .or(filter.map(Reply::into_response))
+        .unify()
+        .map(|mut response: warp::reply::Response| {
+            response
+                .headers_mut()
+                .append(VARY, HeaderValue::from_static("accept-encoding"));
+            response
+        })
+}
+
+/// Adds a strong ETag, a hash of the body, to the successful replies of the filter to GET
+/// requests, and answers 304 (Not Modified) when it matches the If-None-Match header. The ETag
+/// depends on the encoding negotiated with the request, as the body sent does.
+pub fn etag<F, T>(
+    filter: F,
+    enabled: bool,
+    compression: CompressionSettings,
+) -> impl Filter<Extract = (warp::reply::Response,), Error = Infallible> + Clone
+where
+    F: Filter<Extract = (T,), Error = Infallible> + Clone + Send + Sync,
+    T: Reply,
+{
+    warp::method()
+        .and(warp::header::optional::<String>("if-none-match"))
+        .and(warp::header::optional::<String>("accept-encoding"))
+        .and(filter)
+        .and_then(
+            move |method: Method,
+                  if_none_match: Option<String>,
+                  accept_encoding: Option<String>,
+                  reply: T| {
+                let response = reply.into_response();
+                let encoding = accept_encoding
+                    .as_deref()
+                    .and_then(|header| negotiate_encoding(header, &compression));
+                async move {
+                    if !enabled || method != Method::GET || response.status() != StatusCode::OK {
+                        return Ok::<_, Infallible>(response);
+                    }
+                    Ok(with_etag(response, if_none_match.as_deref(), encoding).await)
+                }
+            },
+        )
+}
+
+async fn with_etag(
+    response: warp::reply::Response,
+    if_none_match: Option<&str>,
+    encoding: Option<Encoding>,
+) -> warp::reply::Response {
+    let (mut parts, body) = response.into_parts();
+    // The bodies of the replies are in memory, so reading them does not fail.
+    let body = warp::hyper::body::to_bytes(body).await.unwrap_or_default();
+
+    let tag = etag_of(&body, encoding);
+    parts
+        .headers
+        .insert(ETAG, HeaderValue::from_str(&tag).expect("valid ETag"));
+
+    if if_none_match.map_or(false, |header| etag_matches(header, &tag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_LENGTH);
+        warp::reply::Response::from_parts(parts, warp::hyper::Body::empty())
+    } else {
+        warp::reply::Response::from_parts(parts, warp::hyper::Body::from(body))
+    }
+}
+
+fn etag_of(body: &[u8], encoding: Option<Encoding>) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let suffix = match encoding {
+        Some(Encoding::Brotli) => "-br",
+        Some(Encoding::Gzip) => "-gzip",
+        None => "",
+    };
+    format!("\"{:016x}{}\"", hasher.finish(), suffix)
+}
+
+// If-None-Match uses the weak comparison: 'W/' prefixes are ignored.
+fn etag_matches(header: &str, tag: &str) -> bool {
+    let tag = tag.trim_start_matches("W/");
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_pick_the_language_with_the_highest_quality() {
+        assert_eq!(
+            parse_accept_language("fr;q=0.8, nl-BE, nl;q=0.9"),
+            vec!["nl", "fr"]
+        );
+        assert_eq!(parse_accept_language("de-CH;q=0.5, *;q=0.9"), vec!["de"]);
+        assert!(parse_accept_language("*").is_empty());
+        assert!(parse_accept_language("en;q=0").is_empty());
+    }
+
+    #[test]
+    fn should_report_invalid_cors_settings() {
+        let settings = CorsSettings {
+            allowed_origins: vec![String::from("https://example.com")],
+            ..CorsSettings::default()
+        };
+        assert!(cors(&settings).unwrap().is_some());
+        assert!(cors(&CorsSettings::default()).unwrap().is_none());
+
+        for origin in [
+            "example.com",
+            "https://example.com/path",
+            "https://exa mple.com",
+        ] {
+            let settings = CorsSettings {
+                allowed_origins: vec![String::from(origin)],
+                ..CorsSettings::default()
+            };
+            assert!(matches!(
+                cors(&settings),
+                Err(CorsError::InvalidOrigin { .. })
+            ));
+        }
+
+        let settings = CorsSettings {
+            allowed_origins: vec![String::from("*")],
+            allowed_headers: vec![String::from("x api key")],
+            ..CorsSettings::default()
+        };
+        assert!(matches!(
+            cors(&settings),
+            Err(CorsError::InvalidHeader { .. })
+        ));
+    }
+
+    #[test]
+    fn should_negotiate_the_encoding() {
+        let settings = CompressionSettings::default();
+
+        assert_eq!(
+            negotiate_encoding("gzip, deflate, br", &settings),
+            Some(Encoding::Brotli)
+        );
+        assert_eq!(
+            negotiate_encoding("gzip, deflate, br;q=0.5", &settings),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(negotiate_encoding("deflate, gzip;q=0", &settings), None);
+        assert_eq!(
+            negotiate_encoding(
+                "br, gzip",
+                &CompressionSettings {
+                    brotli: false,
+                    ..settings
+                }
+            ),
+            Some(Encoding::Gzip)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_answer_not_modified_to_a_matching_etag() {
+        let filter = etag(
+            warp::any().map(|| warp::reply::json(&vec!["paris"])),
+            true,
+            CompressionSettings::default(),
+        );
+
+        let response = warp::test::request().reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().as_ref(), b"[\"paris\"]");
+        let tag = response.headers()[ETAG].to_str().unwrap().to_string();
+
+        let response = warp::test::request()
+            .header("if-none-match", format!("\"other\", W/{}", tag))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(response.body().is_empty());
+
+        // The compressed body has its own ETag.
+        let response = warp::test::request()
+            .header("if-none-match", tag.as_str())
+            .header("accept-encoding", "gzip")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(response.headers()[ETAG].to_str().unwrap(), tag);
+    }
+}
@@ -7,7 +7,13 @@ use geojson::Geometry;
 use places::addr::Addr;
 use serde_json::json;
 
-use super::{coord::Coord, filters, settings};
+use super::{
+    coord::Coord,
+    filters::{self, Fuzziness},
+    query_parser::{self, ParsedQuery},
+    settings,
+};
+use crate::adapters::primary::synonyms;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum QueryType {
@@ -32,13 +38,37 @@ pub fn build_query(
     lang: &str,
     settings: &settings::QuerySettings,
     query_type: QueryType,
-    excludes: Option<&[String]>,
+    source: Option<&serde_json::Value>,
 ) -> serde_json::Value {
+    // The unit of an address (apartment, floor) is not part of the labels, and its house number
+    // and postcode are matched with the corresponding fields.
+    let parsed = query_parser::parse(q);
+    let text = parsed.text();
+    let q = text.as_str();
+
     let type_query = build_place_type_boost(&settings.type_query);
-    let boosts = build_boosts(settings, filters, query_type);
+    let boosts: Vec<_> = build_boosts(settings, filters, query_type)
+        .into_iter()
+        .map(|(_, boost)| boost)
+        .collect();
 
-    let string_query =
-        build_string_query(q, lang, &settings.string_query, query_type, &filters.coord);
+    // The synonyms of the language are applied when matching the prefixes or the trigrams of the
+    // words.
+    let synonym_lang = settings
+        .synonym_langs
+        .iter()
+        .any(|synonyms_lang| synonyms_lang == lang)
+        .then(|| lang);
+
+    let string_query = build_string_query(
+        q,
+        &parsed,
+        lang,
+        &settings.string_query,
+        query_type,
+        filters,
+        synonym_lang,
+    );
 
     let filters = [
         build_filters(
@@ -47,8 +77,14 @@ pub fn build_query(
             filters.zone_types.as_deref(),
         ),
         vec![
-            build_matching_condition(q, query_type),
-            build_house_number_condition(q),
+            build_matching_condition(
+                q,
+                query_type,
+                filters.fuzziness,
+                filters.phonetic,
+                synonym_lang,
+            ),
+            build_house_number_condition(q, &parsed),
         ],
     ]
     .concat();
@@ -63,8 +99,8 @@ pub fn build_query(
         },
     });
 
-    if let Some(values) = excludes {
-        query["_source"] = json!({ "excludes": values });
+    if let Some(source) = source {
+        query["_source"] = source.clone();
     }
 
     query
@@ -72,58 +108,86 @@ pub fn build_query(
 
 fn build_string_query(
     q: &str,
+    parsed: &ParsedQuery,
     lang: &str,
     settings: &StringQuery,
     query_type: QueryType,
-    coord: &Option<Coord>,
+    filters: &filters::Filters,
+    synonym_lang: Option<&str>,
 ) -> serde_json::Value {
+    let fuzziness = filters.fuzziness;
     let mut string_should = vec![
-        build_multi_match_query(
+        build_fuzzy_multi_match_query(
             q,
             &["name", &format!("names.{}", lang)],
             settings.boosts.name,
+            fuzziness,
         ),
-        build_multi_match_query(
+        build_fuzzy_multi_match_query(
             q,
             &["alt_name", &format!("alt_names.{}", lang)],
             settings.boosts.alt_name,
+            fuzziness,
         ),
-        build_multi_match_query(
+        build_fuzzy_multi_match_query(
             q,
             &["loc_name", &format!("loc_names.{}", lang)],
             settings.boosts.loc_name,
+            fuzziness,
         ),
-        build_multi_match_query(
+        build_fuzzy_multi_match_query(
             q,
             &["label", &format!("labels.{}", lang)],
             settings.boosts.label,
+            fuzziness,
+        ),
+        build_match_query(
+            parsed.postcode.as_deref().unwrap_or(q),
+            "zip_codes",
+            settings.boosts.zip_codes,
+        ),
+        build_match_query(
+            parsed.house_number.as_deref().unwrap_or(q),
+            "house_number",
+            settings.boosts.house_number,
         ),
-        build_match_query(q, "zip_codes", settings.boosts.zip_codes),
-        build_match_query(q, "house_number", settings.boosts.house_number),
     ];
 
     if query_type != QueryType::SEARCH {
-        string_should.push(build_multi_match_query(
+        let mut label_prefix = build_multi_match_query(
             q,
             &["label.prefix", &format!("labels.{}.prefix", lang)],
             settings.boosts.label_prefix,
+        );
+        if let Some(lang) = synonym_lang {
+            label_prefix["multi_match"]["analyzer"] = json!(synonyms::analyzer(lang));
+        }
+        string_should.push(label_prefix);
+    }
+
+    if filters.phonetic {
+        string_should.push(build_multi_match_query(
+            q,
+            &["label.phonetic", &format!("labels.{}.phonetic", lang)],
+            settings.boosts.label_phonetic,
         ));
     }
 
     if query_type == QueryType::FUZZY {
-        if coord.is_some() {
-            string_should.push(build_multi_match_query(
-                q,
-                &["label.ngram", &format!("labels.{}.ngram", lang)],
-                settings.boosts.label_ngram_with_coord,
-            ));
+        let boost = if filters.coord.is_some() {
+            settings.boosts.label_ngram_with_coord
         } else {
-            string_should.push(build_multi_match_query(
-                q,
-                &["label.ngram", &format!("labels.{}.ngram", lang)],
-                settings.boosts.label_ngram,
-            ));
+            settings.boosts.label_ngram
+        };
+        let mut label_ngram = build_multi_match_query(
+            q,
+            &["label.ngram", &format!("labels.{}.ngram", lang)],
+            boost,
+        );
+        if let Some(lang) = synonym_lang {
+            label_ngram["multi_match"]["analyzer"] = json!(synonyms::ngram_analyzer(lang));
         }
+        string_should.push(label_ngram);
     }
 
     json!({
@@ -134,20 +198,71 @@ fn build_string_query(
     })
 }
 
+// Names of the clauses boosting the type of the place and matching the query.
+pub const TYPE_CLAUSE: &str = "type";
+pub const STRING_CLAUSE: &str = "string";
+
+/// Names of the clauses of the query built by `build_query` which add to the score, in their
+/// order: the boost of the type of the place, the string query, and then the boosts of the
+/// weight, of the admins and of the proximity.
+pub fn score_clauses(
+    settings: &settings::QuerySettings,
+    filters: &filters::Filters,
+    query_type: QueryType,
+) -> Vec<&'static str> {
+    [TYPE_CLAUSE, STRING_CLAUSE]
+        .into_iter()
+        .chain(
+            build_boosts(settings, filters, query_type)
+                .into_iter()
+                .map(|(name, _)| name),
+        )
+        .collect()
+}
+
+/// The boost of the string query matching a field, as named in the settings, eg. 'label_prefix'
+/// for 'labels.fr.prefix'.
+pub fn string_query_boost(field: &str) -> Option<&'static str> {
+    let (base, subfield) = field.split_once('.').unwrap_or((field, ""));
+    // The fields in the language of the query, eg. 'labels.fr', stand for the main ones.
+    let (base, subfield) = match base {
+        "names" | "alt_names" | "loc_names" | "labels" => (
+            base.trim_end_matches('s'),
+            subfield
+                .split_once('.')
+                .map_or("", |(_, subfield)| subfield),
+        ),
+        _ => (base, subfield),
+    };
+
+    match (base, subfield) {
+        ("name", "") => Some("name"),
+        ("alt_name", "") => Some("alt_name"),
+        ("loc_name", "") => Some("loc_name"),
+        ("label", "") => Some("label"),
+        ("label", "prefix") => Some("label_prefix"),
+        ("label", "phonetic") => Some("label_phonetic"),
+        ("label", "ngram") => Some("label_ngram"),
+        ("zip_codes", "") => Some("zip_codes"),
+        ("house_number", "") => Some("house_number"),
+        _ => None,
+    }
+}
+
 fn build_boosts(
     settings: &settings::QuerySettings,
     filters: &filters::Filters,
     query_type: QueryType,
-) -> Vec<serde_json::Value> {
+) -> Vec<(&'static str, serde_json::Value)> {
     let weights = build_weight_depending_on_radius(&settings.importance_query, &filters.coord);
 
-    let mut boosts = vec![build_with_weight(
-        &weights,
-        &settings.importance_query.weights.types,
+    let mut boosts = vec![(
+        "weight",
+        build_with_weight(&weights, &settings.importance_query.weights.types),
     )];
 
     if query_type != QueryType::FUZZY {
-        boosts.push(build_admin_weight_query(weights));
+        boosts.push(("admin_weight", build_admin_weight_query(weights)));
     }
 
     if let Some(coord) = filters.coord {
@@ -164,7 +279,10 @@ fn build_boosts(
             _ => settings.importance_query.proximity.weight_fuzzy,
         };
 
-        boosts.push(build_proximity_boost(coord, &decay, weight_boost));
+        boosts.push((
+            "proximity",
+            build_proximity_boost(coord, &decay, weight_boost),
+        ));
     }
 
     boosts
@@ -216,7 +334,11 @@ fn build_weight_depending_on_radius(
     }
 }
 
-fn build_house_number_condition(q: &str) -> serde_json::Value {
+fn build_house_number_condition(q: &str, parsed: &ParsedQuery) -> serde_json::Value {
+    // When the house number is found in the query, only this one is matched. Otherwise, any
+    // word of the query may be the house number.
+    let house_number = parsed.house_number.as_deref().unwrap_or(q);
+
     if q.split_whitespace().count() > 1 {
         // Filter to handle house number. We either want:
         // * to exactly match the document house_number
@@ -241,7 +363,7 @@ fn build_house_number_condition(q: &str) -> serde_json::Value {
                     {
                         "match": {
                             "house_number": {
-                                "query": q
+                                "query": house_number
                             }
                         }
                     }
@@ -262,25 +384,81 @@ fn build_house_number_condition(q: &str) -> serde_json::Value {
     }
 }
 
-fn build_matching_condition(q: &str, query_type: QueryType) -> serde_json::Value {
-    // Filter to handle house number.
-    // We either want:
-    // * to exactly match the document house_number
-    // * or that the document has no house_number
+fn build_matching_condition(
+    q: &str,
+    query_type: QueryType,
+    fuzziness: Option<Fuzziness>,
+    phonetic: bool,
+    synonym_lang: Option<&str>,
+) -> serde_json::Value {
+    let mut condition = build_text_matching_condition(q, query_type, fuzziness);
+    match (query_type, synonym_lang) {
+        (QueryType::PREFIX, Some(lang)) => {
+            condition["match"]["full_label.prefix"]["analyzer"] =
+                json!(synonyms::elision_analyzer(lang));
+        }
+        (QueryType::FUZZY, Some(lang)) => {
+            condition["match"]["full_label.ngram"]["analyzer"] =
+                json!(synonyms::ngram_analyzer(lang));
+        }
+        _ => {}
+    }
+
+    // The words of the query may also only sound like the ones of the label.
+    if phonetic {
+        json!({
+            "bool": {
+                "should": [
+                    condition,
+                    {
+                        "match": {
+                            "full_label.phonetic": {
+                                "query": q,
+                                "operator": "and"
+                            }
+                        }
+                    }
+                ]
+            }
+        })
+    } else {
+        condition
+    }
+}
+
+fn build_text_matching_condition(
+    q: &str,
+    query_type: QueryType,
+    fuzziness: Option<Fuzziness>,
+) -> serde_json::Value {
     match query_type {
         // When the match type is Prefix, we want to use every possible information even though
         // these are not present in label, for instance, the zip_code.
         // The field full_label contains all of them and will do the trick.
         // The query must at least match with elision activated, matching without elision will
         // provide extra score bellow.
-        QueryType::PREFIX => json!({
-            "match": {
-                "full_label.prefix": {
-                  "query": q,
-                  "operator": "and"
+        // The requested fuzziness allows typos in the words of the query, but never in their
+        // first letter.
+        QueryType::PREFIX => match fuzziness.filter(|fuzziness| fuzziness.is_enabled()) {
+            Some(fuzziness) => json!({
+                "match": {
+                    "full_label.prefix": {
+                      "query": q,
+                      "operator": "and",
+                      "fuzziness": fuzziness.to_string(),
+                      "prefix_length": 1
+                    }
                 }
-            }
-        }),
+            }),
+            None => json!({
+                "match": {
+                    "full_label.prefix": {
+                      "query": q,
+                      "operator": "and"
+                    }
+                }
+            }),
+        },
         // for fuzzy search we lower our expectation & we accept a certain percentage of token match
         // on full_label.ngram
         // The values defined here are empirical,
@@ -303,7 +481,8 @@ fn build_matching_condition(q: &str, query_type: QueryType) -> serde_json::Value
             "match": {
                 "full_label_extra" : {
                     "query": q,
-                    "fuzziness": "auto:4,8",
+                    "fuzziness": fuzziness
+                        .map_or_else(|| "auto:4,8".to_string(), |fuzziness| fuzziness.to_string()),
                     "minimum_should_match": "4<-1 7<-25%"
                 }
             }
@@ -565,16 +744,18 @@ pub fn build_zone_types_filter(zone_types: &[String]) -> serde_json::Value {
     })
 }
 
-pub fn build_features_query(indices: &[String], doc_id: &str) -> serde_json::Value {
+pub fn build_features_query(
+    indices: &[String],
+    doc_id: &str,
+    source: &serde_json::Value,
+) -> serde_json::Value {
     let vec: Vec<serde_json::Value> = indices
         .iter()
         .map(|index| {
             json!({
                 "_index": index,
                 "_id" : doc_id,
-                "_source" : {
-                    "exclude" : "boundary"
-                }
+                "_source" : source
             })
         })
         .collect();
@@ -591,6 +772,28 @@ fn build_multi_match_query(query: &str, fields: &[&str], boost: f64) -> serde_js
     })
 }
 
+// Same as build_multi_match_query, tolerating typos (except in the first letter of the words) if
+// a fuzziness is given.
+fn build_fuzzy_multi_match_query(
+    query: &str,
+    fields: &[&str],
+    boost: f64,
+    fuzziness: Option<Fuzziness>,
+) -> serde_json::Value {
+    match fuzziness.filter(|fuzziness| fuzziness.is_enabled()) {
+        Some(fuzziness) => json!({
+            "multi_match": {
+                "query": query,
+                "fields": fields,
+                "boost": boost,
+                "fuzziness": fuzziness.to_string(),
+                "prefix_length": 1
+            }
+        }),
+        None => build_multi_match_query(query, fields, boost),
+    }
+}
+
 fn build_match_query(query: &str, field: &str, boost: f64) -> serde_json::Value {
     json!({
         "match": {
@@ -0,0 +1,254 @@
+//! Attribution of the score of a document to the clauses of the autocomplete query, from the
+//! explanation given by Elasticsearch.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::dsl;
+
+/// The part of the score of a document due to a clause of the query.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ScoreComponent {
+    /// 'type', 'weight', 'admin_weight', 'proximity', or a boost of the string query, eg.
+    /// 'label_prefix'.
+    pub name: String,
+    pub score: f64,
+}
+
+/// The score of a document, by clause of the query.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ScoreBreakdown {
+    pub score: f64,
+    /// Whether the document matches the query.
+    pub matched: bool,
+    pub components: Vec<ScoreComponent>,
+    /// The required clauses which the document does not match.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub unmatched: Vec<String>,
+}
+
+impl ScoreBreakdown {
+    /// The clauses are named in their order in the query, as given by `dsl::score_clauses`.
+    pub fn from_explanation(explanation: &Value, clauses: &[&str]) -> Self {
+        let score = explanation["value"].as_f64().unwrap_or_default();
+
+        // The score of a matching document is the sum of the scores of the clauses, the
+        // filters adding nothing.
+        if description(explanation) != "sum of:" {
+            return ScoreBreakdown {
+                score,
+                matched: false,
+                components: Vec::new(),
+                unmatched: details(explanation)
+                    .map(description)
+                    .filter(|description| description.starts_with("no match on required clause"))
+                    .map(String::from)
+                    .collect(),
+            };
+        }
+
+        let scoring_clauses = details(explanation)
+            .filter(|detail| !description(detail).starts_with("match on required clause"));
+        let mut breakdown = ScoreBreakdown {
+            score,
+            matched: true,
+            ..ScoreBreakdown::default()
+        };
+        for (name, clause) in clauses.iter().zip(scoring_clauses) {
+            if *name == dsl::STRING_CLAUSE {
+                // Each field matched by the string query has its own boost.
+                let fields = first_sum(clause).map_or_else(Vec::new, |sum| details(sum).collect());
+                for field in fields {
+                    let boost = field_of(field)
+                        .and_then(dsl::string_query_boost)
+                        .unwrap_or(dsl::STRING_CLAUSE);
+                    breakdown.add(boost, field["value"].as_f64().unwrap_or_default());
+                }
+            } else {
+                breakdown.add(name, clause["value"].as_f64().unwrap_or_default());
+            }
+        }
+        breakdown
+    }
+
+    /// The score due to a clause, zero if it does not add to the score.
+    pub fn component(&self, name: &str) -> f64 {
+        self.components
+            .iter()
+            .find(|component| component.name == name)
+            .map_or(0.0, |component| component.score)
+    }
+
+    /// How much more each clause adds to this score than to the other one, by decreasing
+    /// difference. This is why a document ranks before the other one.
+    pub fn lead_over(&self, other: &ScoreBreakdown) -> Vec<ScoreComponent> {
+        let mut names: Vec<&str> = self
+            .components
+            .iter()
+            .chain(other.components.iter())
+            .map(|component| component.name.as_str())
+            .collect();
+        names.sort_unstable();
+        names.dedup();
+
+        let mut lead: Vec<ScoreComponent> = names
+            .into_iter()
+            .map(|name| ScoreComponent {
+                name: name.to_string(),
+                score: self.component(name) - other.component(name),
+            })
+            .filter(|component| component.score.abs() > f64::EPSILON)
+            .collect();
+        lead.sort_by(|a, b| {
+            b.score
+                .partial_cmp(&a.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        lead
+    }
+
+    fn add(&mut self, name: &str, score: f64) {
+        match self
+            .components
+            .iter_mut()
+            .find(|component| component.name == name)
+        {
+            Some(component) => component.score += score,
+            None => self.components.push(ScoreComponent {
+                name: name.to_string(),
+                score,
+            }),
+        }
+    }
+}
+
+fn description(explanation: &Value) -> &str {
+    explanation["description"].as_str().unwrap_or_default()
+}
+
+fn details(explanation: &Value) -> impl Iterator<Item = &Value> {
+    explanation["details"].as_array().into_iter().flatten()
+}
+
+// The sum of the scores of the clauses of a boolean query.
+fn first_sum(explanation: &Value) -> Option<&Value> {
+    if description(explanation) == "sum of:" {
+        Some(explanation)
+    } else {
+        details(explanation).find_map(first_sum)
+    }
+}
+
+// The field of the first term matched in an explanation, eg. 'label.prefix' in
+// 'weight(label.prefix:hugo in 12) [PerFieldSimilarity], result of:'.
+fn field_of(explanation: &Value) -> Option<&str> {
+    match description(explanation).strip_prefix("weight(") {
+        Some(term) => term.trim_start_matches("Synonym(").split(':').next(),
+        None => details(explanation).find_map(field_of),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn leaf(value: f64, description: &str) -> Value {
+        json!({ "value": value, "description": description, "details": [] })
+    }
+
+    fn explanation() -> Value {
+        json!({
+            "value": 11.5,
+            "description": "sum of:",
+            "details": [
+                leaf(3.0, "weight(type:admin in 7) [PerFieldSimilarity], result of:"),
+                {
+                    "value": 6.0,
+                    "description": "sum of:",
+                    "details": [
+                        {
+                            "value": 4.0,
+                            "description": "max of:",
+                            "details": [
+                                leaf(4.0, "weight(labels.fr.prefix:par in 7) [PerFieldSimilarity], result of:"),
+                                leaf(1.0, "weight(label.prefix:par in 7) [PerFieldSimilarity], result of:"),
+                            ]
+                        },
+                        leaf(2.0, "weight(Synonym(name:par name:paris) in 7) [PerFieldSimilarity], result of:"),
+                    ]
+                },
+                leaf(2.0, "function score, product of:"),
+                leaf(0.5, "function score, product of:"),
+                leaf(0.0, "match on required clause, product of:"),
+            ]
+        })
+    }
+
+    #[test]
+    fn should_attribute_the_score_to_the_clauses() {
+        let breakdown = ScoreBreakdown::from_explanation(
+            &explanation(),
+            &["type", "string", "weight", "admin_weight"],
+        );
+
+        assert!(breakdown.matched);
+        assert_eq!(breakdown.score, 11.5);
+        assert_eq!(breakdown.component("type"), 3.0);
+        assert_eq!(breakdown.component("label_prefix"), 4.0);
+        assert_eq!(breakdown.component("name"), 2.0);
+        assert_eq!(breakdown.component("weight"), 2.0);
+        assert_eq!(breakdown.component("admin_weight"), 0.5);
+        assert_eq!(breakdown.component("proximity"), 0.0);
+    }
+
+    #[test]
+    fn should_give_the_unmatched_clauses() {
+        let explanation = json!({
+            "value": 0.0,
+            "description": "Failure to meet condition(s) of required/prohibited clause(s)",
+            "details": [
+                leaf(0.0, "match on required clause, product of:"),
+                leaf(0.0, "no match on required clause (full_label.prefix:paris)"),
+            ]
+        });
+        let breakdown = ScoreBreakdown::from_explanation(&explanation, &["type", "string"]);
+
+        assert!(!breakdown.matched);
+        assert_eq!(
+            breakdown.unmatched,
+            vec!["no match on required clause (full_label.prefix:paris)"]
+        );
+    }
+
+    #[test]
+    fn should_give_the_lead_of_a_document() {
+        let winner = ScoreBreakdown::from_explanation(
+            &explanation(),
+            &["type", "string", "weight", "admin_weight"],
+        );
+        let loser = ScoreBreakdown {
+            score: 7.0,
+            matched: true,
+            components: vec![
+                ScoreComponent {
+                    name: String::from("type"),
+                    score: 3.0,
+                },
+                ScoreComponent {
+                    name: String::from("label_prefix"),
+                    score: 4.0,
+                },
+            ],
+            unmatched: Vec::new(),
+        };
+
+        let lead = winner.lead_over(&loser);
+        let names: Vec<_> = lead
+            .iter()
+            .map(|component| component.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["name", "weight", "admin_weight"]);
+    }
+}
@@ -0,0 +1,280 @@
+//! Selection of the properties of the places in a response, with the 'fields' and
+//! 'exclude_fields' parameters. The properties left out are not serialized, and the parts of the
+//! documents they come from are not fetched from Elasticsearch, when the other properties do not
+//! need them.
+
+use serde_json::{json, Map, Value};
+use std::collections::BTreeSet;
+
+/// The properties of GeocodeJSON which can be selected.
+pub const PROPERTIES: [&str; 26] = [
+    "id",
+    "type",
+    "zone_type",
+    "label",
+    "name",
+    "names",
+    "labels",
+    "housenumber",
+    "street",
+    "postcode",
+    "city",
+    "citycode",
+    "level",
+    "administrative_regions",
+    "poi_types",
+    "properties",
+    "address",
+    "commercial_modes",
+    "comments",
+    "physical_modes",
+    "lines",
+    "timezone",
+    "codes",
+    "feed_publishers",
+    "bbox",
+    "country_codes",
+];
+
+// The places are identified by these properties, which are always kept.
+const MANDATORY: [&str; 2] = ["id", "type"];
+
+// The fields of the documents which are only needed by a property. The administrative regions of
+// a place keep the fields giving its city and citycode. Missing fields get their default value
+// when the documents are read.
+const SOURCE_FIELDS: [(&str, &[&str]); 11] = [
+    (
+        "administrative_regions",
+        &[
+            "administrative_regions.bbox",
+            "administrative_regions.codes",
+            "administrative_regions.names",
+            "administrative_regions.labels",
+            "administrative_regions.alt_names",
+            "administrative_regions.loc_names",
+            "street.administrative_regions.bbox",
+            "street.administrative_regions.codes",
+            "street.administrative_regions.names",
+            "street.administrative_regions.labels",
+            "street.administrative_regions.alt_names",
+            "street.administrative_regions.loc_names",
+        ],
+    ),
+    ("properties", &["properties"]),
+    ("address", &["address"]),
+    ("commercial_modes", &["commercial_modes"]),
+    ("comments", &["comments"]),
+    ("physical_modes", &["physical_modes"]),
+    ("lines", &["lines"]),
+    ("timezone", &["timezone"]),
+    ("codes", &["codes"]),
+    ("feed_publishers", &["feed_publishers"]),
+    ("country_codes", &["country_codes"]),
+];
+
+// The fields of the documents which are always fetched when only some properties are selected:
+// the documents cannot be read without them, and they give the identifiers, the location, the
+// label, the postcode, the house number, the street, the city and the zone type of the places.
+const REQUIRED_SOURCE_FIELDS: [&str; 14] = [
+    "id",
+    "type",
+    "label",
+    "name",
+    "coord",
+    "weight",
+    "zip_codes",
+    "administrative_regions",
+    "insee",
+    "level",
+    "house_number",
+    "street",
+    "poi_type",
+    "zone_type",
+];
+
+// The other fields of the documents, which are fetched when only some properties are selected if
+// one of these properties needs them.
+const SELECTED_SOURCE_FIELDS: [(&str, &[&str]); 15] = [
+    ("label", &["labels"]),
+    ("name", &["names"]),
+    ("labels", &["labels"]),
+    ("names", &["names"]),
+    ("bbox", &["bbox"]),
+    ("properties", &["properties"]),
+    ("address", &["address"]),
+    ("commercial_modes", &["commercial_modes"]),
+    ("comments", &["comments"]),
+    ("physical_modes", &["physical_modes"]),
+    ("lines", &["lines"]),
+    ("timezone", &["timezone"]),
+    ("codes", &["codes"]),
+    ("feed_publishers", &["feed_publishers"]),
+    ("country_codes", &["country_codes"]),
+];
+
+/// The properties requested for the places. By default, all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldSelection {
+    fields: Option<Vec<String>>,
+    exclude_fields: Vec<String>,
+}
+
+/// Whether a name is one of the properties which can be selected.
+pub fn is_known_field(name: &str) -> bool {
+    PROPERTIES.contains(&name)
+}
+
+impl FieldSelection {
+    /// Only the properties of `fields`, if given, except the ones of `exclude_fields`.
+    pub fn new(fields: Option<Vec<String>>, exclude_fields: Option<Vec<String>>) -> Self {
+        FieldSelection {
+            fields,
+            exclude_fields: exclude_fields.unwrap_or_default(),
+        }
+    }
+
+    pub fn keeps(&self, name: &str) -> bool {
+        MANDATORY.contains(&name)
+            || (self
+                .fields
+                .as_ref()
+                .map_or(true, |fields| fields.iter().any(|field| field == name))
+                && !self.exclude_fields.iter().any(|field| field == name))
+    }
+
+    /// The `_source` of the queries to Elasticsearch. When only some properties are selected,
+    /// the documents are restricted to the fields needed to read them and to the fields of these
+    /// properties. The fields of the properties left out are excluded, and so are the boundaries
+    /// of the admins, unless `boundaries` are returned.
+    pub fn source(&self, boundaries: bool) -> Value {
+        let mut source = json!({ "excludes": self.source_excludes(boundaries) });
+        if let Some(includes) = self.source_includes(boundaries) {
+            source["includes"] = json!(includes);
+        }
+        source
+    }
+
+    fn source_includes(&self, boundaries: bool) -> Option<Vec<String>> {
+        self.fields.as_ref()?;
+        let includes: BTreeSet<_> = REQUIRED_SOURCE_FIELDS
+            .iter()
+            .copied()
+            .chain(
+                SELECTED_SOURCE_FIELDS
+                    .iter()
+                    .filter(|(name, _)| self.keeps(name))
+                    .flat_map(|(_, fields)| fields.iter().copied()),
+            )
+            .chain(boundaries.then(|| "boundary"))
+            .collect();
+        Some(includes.into_iter().map(String::from).collect())
+    }
+
+    fn source_excludes(&self, boundaries: bool) -> Vec<String> {
+        (!boundaries)
+            .then(|| "boundary")
+            .into_iter()
+            .chain(
+                SOURCE_FIELDS
+                    .iter()
+                    .filter(|(name, _)| !self.keeps(name))
+                    .flat_map(|(_, fields)| fields.iter().copied()),
+            )
+            .map(String::from)
+            .collect()
+    }
+
+    /// Removes the properties not selected.
+    pub fn retain(&self, properties: &mut Map<String, Value>) {
+        if *self != FieldSelection::default() {
+            properties.retain(|name, _| !is_known_field(name) || self.keeps(name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn selection(fields: Option<&[&str]>, exclude_fields: &[&str]) -> FieldSelection {
+        let strings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        FieldSelection::new(fields.map(strings), Some(strings(exclude_fields)))
+    }
+
+    #[test]
+    fn should_keep_the_selected_fields_and_the_identifiers() {
+        let fields = selection(Some(&["name", "label", "lines"]), &["lines"]);
+        let mut properties = json!({
+            "id": "stop_area:1",
+            "type": "public_transport:stop_area",
+            "name": "Gare de Lyon",
+            "label": "Gare de Lyon (Paris)",
+            "lines": [],
+            "codes": [],
+        });
+        fields.retain(properties.as_object_mut().unwrap());
+
+        assert_eq!(
+            properties,
+            json!({
+                "id": "stop_area:1",
+                "type": "public_transport:stop_area",
+                "name": "Gare de Lyon",
+                "label": "Gare de Lyon (Paris)",
+            })
+        );
+    }
+
+    #[test]
+    fn should_only_exclude_the_boundary_by_default() {
+        assert_eq!(
+            FieldSelection::default().source(false),
+            json!({ "excludes": ["boundary"] })
+        );
+        assert_eq!(
+            FieldSelection::default().source(true),
+            json!({ "excludes": [] })
+        );
+    }
+
+    #[test]
+    fn should_exclude_the_sources_of_the_fields_left_out() {
+        let source = selection(None, &["lines", "administrative_regions"]).source(false);
+        let excludes = &source["excludes"];
+        assert!(excludes.as_array().unwrap().contains(&json!("lines")));
+        assert!(excludes
+            .as_array()
+            .unwrap()
+            .contains(&json!("administrative_regions.codes")));
+        assert!(!excludes.as_array().unwrap().contains(&json!("codes")));
+        assert!(source.get("includes").is_none());
+    }
+
+    #[test]
+    fn should_include_the_sources_of_the_selected_fields() {
+        let source = selection(Some(&["name", "city", "lines"]), &["lines"]).source(false);
+        let includes = source["includes"].as_array().unwrap();
+        for field in [
+            "id",
+            "type",
+            "coord",
+            "label",
+            "names",
+            "administrative_regions",
+        ] {
+            assert!(
+                includes.contains(&json!(field)),
+                "{} is not included",
+                field
+            );
+        }
+        for field in ["labels", "lines", "codes", "boundary"] {
+            assert!(!includes.contains(&json!(field)), "{} is included", field);
+        }
+
+        let source = selection(Some(&["name"]), &[]).source(true);
+        let includes = source["includes"].as_array().unwrap();
+        assert!(includes.contains(&json!("boundary")));
+    }
+}
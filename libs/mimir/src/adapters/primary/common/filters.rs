@@ -1,9 +1,67 @@
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, time::Duration};
 
 use super::coord::Coord;
 use crate::adapters::primary::bragi::api::Proximity;
 use geojson::Geometry;
 
+/// Typo tolerance of the query: the number of edits allowed for each word, or 'auto' to let
+/// Elasticsearch choose it from the length of the word.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Fuzziness {
+    Auto,
+    Edits(u8),
+}
+
+impl Fuzziness {
+    /// Whether typos are tolerated at all.
+    pub fn is_enabled(self) -> bool {
+        self != Fuzziness::Edits(0)
+    }
+}
+
+impl FromStr for Fuzziness {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(Fuzziness::Auto),
+            "0" => Ok(Fuzziness::Edits(0)),
+            "1" => Ok(Fuzziness::Edits(1)),
+            "2" => Ok(Fuzziness::Edits(2)),
+            _ => Err(format!(
+                "invalid fuzziness '{}', expected one of 'auto', '0', '1', '2'",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Fuzziness {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Fuzziness> for String {
+    fn from(fuzziness: Fuzziness) -> Self {
+        fuzziness.to_string()
+    }
+}
+
+// This is the syntax expected by Elasticsearch.
+impl fmt::Display for Fuzziness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fuzziness::Auto => write!(f, "AUTO"),
+            Fuzziness::Edits(edits) => write!(f, "{}", edits),
+        }
+    }
+}
+
 // How to restrict the range of the query... Except for the place type (ie what indices we're
 // searching, since we use the list of types to create the list of indices to search for just
 // before calling search_documents.
@@ -16,4 +74,21 @@ pub struct Filters {
     pub limit: i64,
     pub timeout: Option<Duration>,
     pub proximity: Option<Proximity>,
+    pub fuzziness: Option<Fuzziness>,
+    // Also match the words which sound like the ones of the query.
+    pub phonetic: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_fuzziness() {
+        assert_eq!("AUTO".parse::<Fuzziness>(), Ok(Fuzziness::Auto));
+        assert_eq!("1".parse::<Fuzziness>(), Ok(Fuzziness::Edits(1)));
+        assert!("3".parse::<Fuzziness>().is_err());
+        assert_eq!(Fuzziness::Auto.to_string(), "AUTO");
+        assert!(!Fuzziness::Edits(0).is_enabled());
+    }
 }
@@ -0,0 +1,569 @@
+//! Formats of the responses of bragi, besides GeocodeJSON: plain GeoJSON with flat properties,
+//! CSV for batch outputs, and a compact JSON without the administrative regions for mobile
+//! clients. All of them are generated from the places of a `GeocodeResult`.
+
+use geo::algorithm::simplify::Simplify;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+
+use crate::adapters::primary::common::{
+    fields::FieldSelection,
+    geocoding::{Feature, FromWithLang, GeocodeJsonProperty, GeocodeJsonResponse},
+};
+use places::{admin::Admin, Place};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not serialize the response to JSON: {}", source))]
+    Json { source: serde_json::Error },
+
+    #[snafu(display("Could not serialize the response to CSV: {}", source))]
+    Csv { source: csv::Error },
+}
+
+/// The places found for a request, in the order of their relevance, before being formatted.
+#[derive(Debug, Clone, Default)]
+pub struct GeocodeResult {
+    pub query: String,
+    pub places: Vec<Place>,
+    /// Language of the names and labels.
+    pub lang: Option<String>,
+    /// Additional languages of the names and labels, in GeocodeJSON only.
+    pub include_langs: Vec<String>,
+    /// Problems which did not prevent an answer, but may have made it incomplete.
+    pub warnings: Vec<String>,
+    /// Properties of the places returned, in GeocodeJSON and GeoJSON.
+    pub fields: FieldSelection,
+    /// Whether the admins have their boundary as geometry, in GeocodeJSON and GeoJSON.
+    pub boundaries: bool,
+    /// Tolerance, in degrees, with which the boundaries are simplified.
+    pub tolerance: Option<f64>,
+}
+
+// The geometry of a place: the boundary of an admin, if requested and known, else its location.
+fn geometry(place: &Place, boundaries: bool, tolerance: Option<f64>) -> geojson::Geometry {
+    match place {
+        Place::Admin(Admin {
+            boundary: Some(boundary),
+            ..
+        }) if boundaries => {
+            let boundary = match tolerance {
+                Some(tolerance) => boundary.simplify(&tolerance),
+                None => boundary.clone(),
+            };
+            geojson::Geometry::new(geojson::Value::from(&boundary))
+        }
+        _ => geojson::Geometry::from(place),
+    }
+}
+
+impl From<GeocodeResult> for GeocodeJsonResponse {
+    fn from(result: GeocodeResult) -> Self {
+        let lang = result.lang.as_deref();
+        let features = result
+            .places
+            .into_iter()
+            .map(|place| {
+                let geometry = geometry(&place, result.boundaries, result.tolerance);
+                Feature {
+                    geometry,
+                    ..Feature::from_with_langs(place, lang, &result.include_langs)
+                }
+            })
+            .collect();
+        GeocodeJsonResponse::new(result.query, features).with_warnings(result.warnings)
+    }
+}
+
+/// Format of a response, given by the 'format' parameter, or else negotiated with the Accept
+/// header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    GeocodeJson,
+    GeoJson,
+    Csv,
+    Compact,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::GeocodeJson
+    }
+}
+
+impl Format {
+    /// The format of the media type with the highest quality in an Accept header, if bragi has
+    /// one. GeocodeJSON is served as 'application/json'.
+    pub fn from_accept(header: &str) -> Option<Format> {
+        let mut types: Vec<(&str, f32)> = header
+            .split(',')
+            .filter_map(|media_type| {
+                let mut parts = media_type.split(';').map(str::trim);
+                let media_type = parts.next().filter(|media_type| !media_type.is_empty())?;
+                let quality = parts
+                    .find_map(|param| param.strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                Some((media_type, quality))
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+
+        // The sort is stable, so media types with the same quality keep the order of the header.
+        types.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        types
+            .iter()
+            .find_map(|(media_type, _)| match media_type.to_lowercase().as_str() {
+                "application/json" => Some(Format::GeocodeJson),
+                "application/geo+json" => Some(Format::GeoJson),
+                "text/csv" => Some(Format::Csv),
+                _ => None,
+            })
+    }
+
+    pub fn formatter(self) -> &'static dyn Formatter {
+        match self {
+            Format::GeocodeJson => &GeocodeJsonFormatter,
+            Format::GeoJson => &GeoJsonFormatter,
+            Format::Csv => &CsvFormatter,
+            Format::Compact => &CompactFormatter,
+        }
+    }
+}
+
+/// Turns the places found for a request into the body of the response.
+pub trait Formatter: Send + Sync {
+    /// The value of the Content-Type header of the response.
+    fn content_type(&self) -> &'static str;
+
+    fn format(&self, result: GeocodeResult) -> Result<Vec<u8>, Error>;
+}
+
+/// GeocodeJSON, the default format.
+pub struct GeocodeJsonFormatter;
+
+impl Formatter for GeocodeJsonFormatter {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn format(&self, result: GeocodeResult) -> Result<Vec<u8>, Error> {
+        let fields = result.fields.clone();
+        let mut response =
+            serde_json::to_value(GeocodeJsonResponse::from(result)).context(JsonSnafu)?;
+        if let Some(features) = response["features"].as_array_mut() {
+            for feature in features {
+                if let Some(properties) = feature["properties"]["geocoding"].as_object_mut() {
+                    fields.retain(properties);
+                }
+            }
+        }
+        serde_json::to_vec(&response).context(JsonSnafu)
+    }
+}
+
+/// A GeoJSON feature collection, whose features carry flat properties.
+pub struct GeoJsonFormatter;
+
+impl Formatter for GeoJsonFormatter {
+    fn content_type(&self) -> &'static str {
+        "application/geo+json"
+    }
+
+    fn format(&self, result: GeocodeResult) -> Result<Vec<u8>, Error> {
+        let lang = result.lang.as_deref();
+        let features: Vec<_> = result
+            .places
+            .into_iter()
+            .map(|place| {
+                let geometry = geometry(&place, result.boundaries, result.tolerance);
+                let mut properties =
+                    serde_json::to_value(FlatProperties::from_with_lang(place, lang))
+                        .context(JsonSnafu)?;
+                if let Some(properties) = properties.as_object_mut() {
+                    result.fields.retain(properties);
+                }
+                Ok(serde_json::json!({
+                    "type": "Feature",
+                    "geometry": geometry,
+                    "properties": properties,
+                }))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        let mut collection = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": features,
+        });
+        if !result.warnings.is_empty() {
+            collection["warnings"] = serde_json::json!(result.warnings);
+        }
+        serde_json::to_vec(&collection).context(JsonSnafu)
+    }
+}
+
+/// One line per place, with the flat properties and the coordinates of the place.
+pub struct CsvFormatter;
+
+const CSV_COLUMNS: [&str; 15] = [
+    "id",
+    "type",
+    "zone_type",
+    "name",
+    "label",
+    "housenumber",
+    "street",
+    "postcode",
+    "city",
+    "citycode",
+    "level",
+    "poi_type",
+    "distance",
+    "lon",
+    "lat",
+];
+
+impl Formatter for CsvFormatter {
+    fn content_type(&self) -> &'static str {
+        "text/csv; charset=utf-8"
+    }
+
+    fn format(&self, result: GeocodeResult) -> Result<Vec<u8>, Error> {
+        let lang = result.lang.as_deref();
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(&CSV_COLUMNS).context(CsvSnafu)?;
+
+        for place in result.places {
+            let (lon, lat) = (place.coord().lon(), place.coord().lat());
+            let properties = FlatProperties::from_with_lang(place, lang);
+            let optional = |value: Option<String>| value.unwrap_or_default();
+            writer
+                .write_record(&[
+                    properties.id,
+                    properties.place_type.to_string(),
+                    optional(properties.zone_type),
+                    optional(properties.name),
+                    optional(properties.label),
+                    optional(properties.housenumber),
+                    optional(properties.street),
+                    optional(properties.postcode),
+                    optional(properties.city),
+                    optional(properties.citycode),
+                    optional(properties.level.map(|level| level.to_string())),
+                    optional(properties.poi_type),
+                    optional(properties.distance.map(|distance| distance.to_string())),
+                    lon.to_string(),
+                    lat.to_string(),
+                ])
+                .context(CsvSnafu)?;
+        }
+
+        writer
+            .into_inner()
+            .map_err(|err| csv::Error::from(err.into_error()))
+            .context(CsvSnafu)
+    }
+}
+
+/// A small JSON document, with the label and the location of the places, but without their
+/// administrative regions.
+pub struct CompactFormatter;
+
+#[derive(Debug, Serialize)]
+struct CompactResponse {
+    query: String,
+    places: Vec<CompactPlace>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct CompactPlace {
+    id: String,
+    #[serde(rename = "type")]
+    place_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    zone_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    postcode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    city: Option<String>,
+    /// As [lon, lat].
+    coord: [f64; 2],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<u32>,
+}
+
+impl Formatter for CompactFormatter {
+    fn content_type(&self) -> &'static str {
+        "application/json"
+    }
+
+    fn format(&self, result: GeocodeResult) -> Result<Vec<u8>, Error> {
+        let lang = result.lang.as_deref();
+        let places = result
+            .places
+            .into_iter()
+            .map(|place| {
+                let coord = [place.coord().lon(), place.coord().lat()];
+                let properties = FlatProperties::from_with_lang(place, lang);
+                CompactPlace {
+                    id: properties.id,
+                    place_type: properties.place_type,
+                    zone_type: properties.zone_type,
+                    name: properties.name,
+                    label: properties.label,
+                    postcode: properties.postcode,
+                    city: properties.city,
+                    coord,
+                    distance: properties.distance,
+                }
+            })
+            .collect();
+
+        let response = CompactResponse {
+            query: result.query,
+            places,
+            warnings: result.warnings,
+        };
+        serde_json::to_vec(&response).context(JsonSnafu)
+    }
+}
+
+// The properties of a place which are not lists, as in GeocodeJSON.
+#[derive(Debug, Serialize)]
+struct FlatProperties {
+    id: String,
+    #[serde(rename = "type")]
+    place_type: &'static str,
+    zone_type: Option<String>,
+    name: Option<String>,
+    label: Option<String>,
+    housenumber: Option<String>,
+    street: Option<String>,
+    postcode: Option<String>,
+    city: Option<String>,
+    citycode: Option<String>,
+    level: Option<u32>,
+    /// Name of the type of a poi.
+    poi_type: Option<String>,
+    distance: Option<u32>,
+}
+
+impl FromWithLang<Place> for FlatProperties {
+    fn from_with_lang(place: Place, lang: Option<&str>) -> Self {
+        let distance = place.distance();
+        let property = GeocodeJsonProperty::from_with_lang(place, lang);
+        FlatProperties {
+            id: property.id,
+            place_type: property.place_type.as_str(),
+            zone_type: property.zone_type,
+            name: property.name,
+            label: property.label,
+            housenumber: property.housenumber,
+            street: property.street,
+            postcode: property.postcode,
+            city: property.city,
+            citycode: property.citycode,
+            level: property.level,
+            poi_type: property
+                .poi_types
+                .into_iter()
+                .next()
+                .map(|poi_type| poi_type.name),
+            distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use places::{admin::Admin, coord::Coord, street::Street};
+    use serde_json::Value;
+    use std::sync::Arc;
+
+    fn result() -> GeocodeResult {
+        let city = Admin {
+            id: String::from("admin:fr:75056"),
+            insee: String::from("75056"),
+            level: 8,
+            name: String::from("Paris"),
+            label: String::from("Paris (75000-75116)"),
+            zone_type: Some(cosmogony::ZoneType::City),
+            ..Admin::default()
+        };
+        let mut street = Street {
+            id: String::from("street:1"),
+            name: String::from("Rue Victor Hugo"),
+            label: String::from("Rue Victor Hugo, Paris"),
+            coord: Coord::new(2.28, 48.87).unwrap(),
+            administrative_regions: vec![Arc::new(city)],
+            zip_codes: vec![String::from("75016")],
+            ..Street::default()
+        };
+        street.distance = Some(120);
+        GeocodeResult {
+            query: String::from("hugo"),
+            places: vec![Place::Street(street)],
+            lang: Some(String::from("fr")),
+            ..GeocodeResult::default()
+        }
+    }
+
+    #[test]
+    fn should_negotiate_the_format() {
+        assert_eq!(
+            Format::from_accept("text/csv;q=0.5, application/geo+json"),
+            Some(Format::GeoJson)
+        );
+        assert_eq!(
+            Format::from_accept("text/html, text/csv;q=0.9, */*;q=0.8"),
+            Some(Format::Csv)
+        );
+        assert_eq!(Format::from_accept("*/*"), None);
+        assert_eq!(Format::from_accept("text/csv;q=0"), None);
+    }
+
+    #[test]
+    fn should_leave_out_the_translations_of_the_places_without_any() {
+        let paris = Admin {
+            id: String::from("admin:fr:75056"),
+            name: String::from("Paris"),
+            label: String::from("Paris (75000-75116)"),
+            names: vec![(String::from("nl"), String::from("Parijs"))]
+                .into_iter()
+                .collect(),
+            ..Admin::default()
+        };
+        let result = GeocodeResult {
+            places: vec![result().places.remove(0), Place::Admin(paris)],
+            include_langs: vec![String::from("nl")],
+            ..result()
+        };
+        let body = GeocodeJsonFormatter.format(result).unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+
+        let street = &response["features"][0]["properties"]["geocoding"];
+        assert!(street.get("names").is_none());
+        assert!(street.get("labels").is_none());
+        let admin = &response["features"][1]["properties"]["geocoding"];
+        assert_eq!(admin["names"]["nl"], "Parijs");
+        assert!(admin.get("labels").is_none());
+    }
+
+    #[test]
+    fn should_only_serialize_the_selected_fields() {
+        let result = GeocodeResult {
+            fields: FieldSelection::new(None, Some(vec![String::from("administrative_regions")])),
+            ..result()
+        };
+        let body = GeocodeJsonFormatter.format(result).unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+
+        let geocoding = &response["features"][0]["properties"]["geocoding"];
+        assert_eq!(geocoding["id"], "street:1");
+        assert_eq!(geocoding["city"], "Paris");
+        assert!(geocoding.get("administrative_regions").is_none());
+    }
+
+    #[test]
+    fn should_return_the_boundary_of_the_admins_on_demand() {
+        let city = Admin {
+            id: String::from("admin:fr:75056"),
+            coord: Coord::new(2.35, 48.85).unwrap(),
+            boundary: Some(geo_types::MultiPolygon(vec![geo_types::polygon![
+                (x: 2.22, y: 48.81),
+                (x: 2.30, y: 48.81),
+                (x: 2.47, y: 48.82),
+                (x: 2.47, y: 48.90),
+                (x: 2.22, y: 48.90),
+            ]])),
+            ..Admin::default()
+        };
+        let geometry_type = |result: GeocodeResult| {
+            let response = GeocodeJsonResponse::from(result);
+            match &response.features[0].geometry.value {
+                geojson::Value::Point(_) => "Point",
+                geojson::Value::MultiPolygon(polygons) => {
+                    // The point on the edge is simplified away.
+                    assert_eq!(polygons[0][0].len(), 5);
+                    "MultiPolygon"
+                }
+                _ => "other",
+            }
+        };
+        let result = GeocodeResult {
+            places: vec![Place::Admin(city)],
+            ..GeocodeResult::default()
+        };
+
+        assert_eq!(geometry_type(result.clone()), "Point");
+        assert_eq!(
+            geometry_type(GeocodeResult {
+                boundaries: true,
+                tolerance: Some(0.01),
+                ..result
+            }),
+            "MultiPolygon"
+        );
+    }
+
+    #[test]
+    fn should_flatten_the_properties_in_geojson() {
+        let body = GeoJsonFormatter.format(result()).unwrap();
+        let collection: Value = serde_json::from_slice(&body).unwrap();
+
+        let feature = &collection["features"][0];
+        assert_eq!(
+            feature["geometry"]["coordinates"],
+            serde_json::json!([2.28, 48.87])
+        );
+        assert_eq!(feature["properties"]["city"], "Paris");
+        assert_eq!(feature["properties"]["citycode"], "75056");
+        assert_eq!(feature["properties"]["distance"], 120);
+        assert!(feature["properties"]["administrative_regions"].is_null());
+    }
+
+    #[test]
+    fn should_write_one_line_per_place_in_csv() {
+        let body = CsvFormatter.format(result()).unwrap();
+        let lines: Vec<_> = std::str::from_utf8(&body).unwrap().lines().collect();
+
+        assert_eq!(lines[0], CSV_COLUMNS.join(","));
+        assert_eq!(
+            lines[1],
+            "street:1,street,,Rue Victor Hugo,\"Rue Victor Hugo, Paris\",,Rue Victor Hugo,\
+             75016,Paris,75056,,,120,2.28,48.87"
+        );
+    }
+
+    #[test]
+    fn should_drop_the_administrative_regions_in_compact() {
+        let body = CompactFormatter.format(result()).unwrap();
+        let response: Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(
+            response,
+            serde_json::json!({
+                "query": "hugo",
+                "places": [{
+                    "id": "street:1",
+                    "type": "street",
+                    "name": "Rue Victor Hugo",
+                    "label": "Rue Victor Hugo, Paris",
+                    "postcode": "75016",
+                    "city": "Paris",
+                    "coord": [2.28, 48.87],
+                    "distance": 120,
+                }],
+            })
+        );
+    }
+}
@@ -1,13 +1,14 @@
 use convert_case::{Case, Casing};
+use schemars::JsonSchema;
 use serde::Serialize;
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use crate::adapters::primary::bragi::api;
 use places::utils::serialize_rect;
 
 /// GeocodeJSON is a an extension of the GeoJSON standard.
 // It must contain the following three items
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct GeocodeJsonResponse {
     /// Since GeocodeJSON must be valid GeoJSON, we must identify the type of object.
     /// We are returning a set of features, so the value of format_type will always be
@@ -16,6 +17,10 @@ pub struct GeocodeJsonResponse {
     pub format_type: String,
     pub geocoding: Geocoding,
     pub features: Vec<Feature>,
+    /// Problems which did not prevent an answer, but may have made it incomplete (eg. a
+    /// cluster which could not be reached).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
 }
 
 impl GeocodeJsonResponse {
@@ -27,8 +32,14 @@ impl GeocodeJsonResponse {
                 query: Some(q),
             },
             features,
+            warnings: vec![],
         }
     }
+
+    pub fn with_warnings(mut self, warnings: Vec<String>) -> Self {
+        self.warnings = warnings;
+        self
+    }
 }
 
 impl FromWithLang<Vec<places::Place>> for GeocodeJsonResponse {
@@ -43,16 +54,17 @@ impl FromWithLang<Vec<places::Place>> for GeocodeJsonResponse {
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct Geocoding {
-    version: String,
-    query: Option<String>,
+    pub version: String,
+    pub query: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct Feature {
     #[serde(rename = "type")]
     pub feature_type: String,
+    #[schemars(with = "serde_json::Value")]
     pub geometry: geojson::Geometry,
     pub properties: Properties,
     // FIXME distance to the lat lon given in query parameters?
@@ -62,14 +74,14 @@ pub struct Feature {
     // pub context: Option<mimir::Context>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct Properties {
     pub geocoding: GeocodeJsonProperty,
 }
 
 /// This structure contains the result of a geocoding query
 /// It adheres to the geocodejson spec
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct GeocodeJsonProperty {
     pub id: String,
     #[serde(rename = "type")]
@@ -78,6 +90,12 @@ pub struct GeocodeJsonProperty {
     pub zone_type: Option<String>,
     pub label: Option<String>,
     pub name: Option<String>,
+    // The names and labels in the languages requested with 'include_langs',
+    // when they are available.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub names: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty", default)]
+    pub labels: BTreeMap<String, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub housenumber: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -119,12 +137,13 @@ pub struct GeocodeJsonProperty {
         skip_serializing_if = "Option::is_none",
         default
     )]
+    #[schemars(with = "Option<[f64; 4]>")]
     pub bbox: Option<geo_types::Rect<f64>>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub country_codes: Vec<String>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct AssociatedAdmin {
     pub id: String,
     pub insee: String,
@@ -138,8 +157,10 @@ pub struct AssociatedAdmin {
         skip_serializing_if = "Option::is_none",
         default
     )]
+    #[schemars(with = "Option<[f64; 4]>")]
     pub bbox: Option<geo_types::Rect<f64>>,
     #[serde(default)]
+    #[schemars(with = "Option<String>")]
     pub zone_type: Option<cosmogony::ZoneType>,
     #[serde(default)]
     pub parent_id: Option<String>, // id of the Admin's parent (from the cosmogony's hierarchy)
@@ -202,6 +223,50 @@ impl FromWithLang<places::Place> for Feature {
     }
 }
 
+impl Feature {
+    /// Same as `from_with_lang`, and adds the names and labels of the place in each of the
+    /// `include_langs` for which a translation is available. Only the admins and the pois are
+    /// translated: the other places get no names nor labels, and the keys are left out of the
+    /// response rather than sent empty.
+    pub fn from_with_langs(
+        place: places::Place,
+        lang: Option<&str>,
+        include_langs: &[String],
+    ) -> Feature {
+        let (names, labels) = match &place {
+            places::Place::Admin(admin) => (
+                i18n_map(&admin.names, include_langs),
+                i18n_map(&admin.labels, include_langs),
+            ),
+            places::Place::Poi(poi) => (
+                i18n_map(&poi.names, include_langs),
+                i18n_map(&poi.labels, include_langs),
+            ),
+            places::Place::Street(_) | places::Place::Addr(_) | places::Place::Stop(_) => {
+                (BTreeMap::new(), BTreeMap::new())
+            }
+        };
+        let mut feature = Feature::from_with_lang(place, lang);
+        feature.properties.geocoding.names = names;
+        feature.properties.geocoding.labels = labels;
+        feature
+    }
+}
+
+fn i18n_map(
+    properties: &places::i18n_properties::I18nProperties,
+    langs: &[String],
+) -> BTreeMap<String, String> {
+    langs
+        .iter()
+        .filter_map(|lang| {
+            properties
+                .get(lang)
+                .map(|value| (lang.clone(), value.to_string()))
+        })
+        .collect()
+}
+
 impl FromWithLang<places::admin::Admin> for GeocodeJsonProperty {
     fn from_with_lang(admin: places::admin::Admin, lang: Option<&str>) -> GeocodeJsonProperty {
         let (name, label) = if let Some(lang) = lang {
@@ -254,9 +319,11 @@ impl FromWithLang<places::admin::Admin> for GeocodeJsonProperty {
             housenumber: None,
             id: admin.id,
             label,
+            labels: BTreeMap::new(),
             level,
             lines: vec![],
             name,
+            names: BTreeMap::new(),
             physical_modes: vec![],
             place_type: api::Type::Zone,
             poi_types: vec![],
@@ -319,9 +386,11 @@ impl FromWithLang<places::street::Street> for GeocodeJsonProperty {
             housenumber: None,
             id: street.id,
             label,
+            labels: BTreeMap::new(),
             level: None,
             lines: vec![],
             name: name.clone(),
+            names: BTreeMap::new(),
             physical_modes: vec![],
             place_type: api::Type::Street,
             poi_types: vec![],
@@ -368,9 +437,11 @@ impl FromWithLang<places::addr::Addr> for GeocodeJsonProperty {
             housenumber,
             id: addr.id,
             label,
+            labels: BTreeMap::new(),
             level: None,
             lines: vec![],
             name,
+            names: BTreeMap::new(),
             physical_modes: vec![],
             place_type: api::Type::House,
             poi_types: vec![],
@@ -442,9 +513,11 @@ impl FromWithLang<places::poi::Poi> for GeocodeJsonProperty {
             housenumber: None,
             id: poi.id,
             label,
+            labels: BTreeMap::new(),
             level: None,
             lines: vec![],
             name,
+            names: BTreeMap::new(),
             physical_modes: vec![],
             place_type: api::Type::Poi,
             poi_types: vec![poi.poi_type],
@@ -508,9 +581,11 @@ impl FromWithLang<places::stop::Stop> for GeocodeJsonProperty {
             housenumber: None,
             id: stop.id,
             label,
+            labels: BTreeMap::new(),
             level: None,
             lines: stop.lines,
             name,
+            names: BTreeMap::new(),
             physical_modes: stop.physical_modes,
             place_type: api::Type::StopArea,
             poi_types: vec![],
@@ -535,13 +610,13 @@ impl FromWithLang<places::Place> for GeocodeJsonProperty {
     }
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct KeyValue {
     pub key: String,
     pub value: String,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, JsonSchema)]
 pub struct NameValue {
     pub name: String,
     pub value: String,
@@ -1,5 +1,9 @@
 pub mod coord;
 pub mod dsl;
+pub mod explain;
+pub mod fields;
 pub mod filters;
+pub mod formats;
 pub mod geocoding;
+pub mod query_parser;
 pub mod settings;
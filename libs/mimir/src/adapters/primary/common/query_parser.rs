@@ -0,0 +1,481 @@
+use serde::{Deserialize, Serialize};
+
+// Rule based parsing of the addresses found in the queries. The words of the query are labelled
+// as house number, street type, street name, postcode, city, or unit (apartment, floor, ...),
+// from a few lists of words and the position of the numbers. The rules target French-like
+// addresses ('12 bis rue X, 75012 Paris'), but also accept the house number after the street
+// ('rue X 12').
+
+const STREET_TYPES: &[&str] = &[
+    "allee",
+    "all",
+    "av",
+    "ave",
+    "avenue",
+    "bd",
+    "bld",
+    "blvd",
+    "boulevard",
+    "bvd",
+    "chaussee",
+    "che",
+    "chemin",
+    "chem",
+    "cite",
+    "cours",
+    "crs",
+    "esplanade",
+    "faubourg",
+    "fg",
+    "hameau",
+    "imp",
+    "impasse",
+    "lieu-dit",
+    "ld",
+    "parvis",
+    "passage",
+    "pass",
+    "pl",
+    "place",
+    "promenade",
+    "quai",
+    "residence",
+    "res",
+    "rle",
+    "route",
+    "rte",
+    "rue",
+    "ruelle",
+    "sentier",
+    "square",
+    "sq",
+    "traverse",
+    "venelle",
+    "villa",
+    "vla",
+    "voie",
+];
+
+const HOUSE_NUMBER_SUFFIXES: &[&str] = &["bis", "ter", "quater", "quinquies"];
+
+const UNIT_WORDS: &[&str] = &[
+    "app",
+    "appart",
+    "appartement",
+    "appt",
+    "apt",
+    "bat",
+    "batiment",
+    "bt",
+    "esc",
+    "escalier",
+    "etage",
+    "floor",
+    "suite",
+    "unit",
+];
+
+const ORDINAL_SUFFIXES: &[&str] = &["e", "eme", "er", "ere"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Label {
+    HouseNumber,
+    StreetType,
+    StreetName,
+    Postcode,
+    City,
+    Unit,
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Token {
+    pub text: String,
+    pub label: Label,
+}
+
+/// The components of the query, when it looks like an address.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedQuery {
+    pub tokens: Vec<Token>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub house_number: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub street_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub street_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub postcode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// The query as given.
+    #[serde(skip)]
+    pub query: String,
+}
+
+impl ParsedQuery {
+    /// The query without its unit: apartments and floors are not part of the labels. Without
+    /// unit, this is the query as given.
+    pub fn text(&self) -> String {
+        if self.unit.is_none() {
+            return self.query.clone();
+        }
+        let text = self
+            .tokens
+            .iter()
+            .filter(|token| token.label != Label::Unit)
+            .map(|token| token.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if text.is_empty() {
+            self.query.clone()
+        } else {
+            text
+        }
+    }
+
+    fn join(&self, label: Label) -> Option<String> {
+        let words: Vec<&str> = self
+            .tokens
+            .iter()
+            .filter(|token| token.label == label)
+            .map(|token| token.text.as_str())
+            .collect();
+        if words.is_empty() {
+            None
+        } else {
+            Some(words.join(" "))
+        }
+    }
+}
+
+// A word of the query, and whether it is followed by a comma.
+struct Word {
+    text: String,
+    norm: String,
+    comma: bool,
+}
+
+pub fn parse(q: &str) -> ParsedQuery {
+    let words = split(q);
+    let mut labels: Vec<Option<Label>> = vec![None; words.len()];
+
+    // The units are labelled first, so that they are not taken for a part of the street, but
+    // they are only kept if the query turns out to be an address.
+    label_units(&words, &mut labels);
+
+    // The first number of five digits is the postcode.
+    let postcode = (0..words.len()).find(|&i| labels[i].is_none() && is_postcode(&words[i].norm));
+    if let Some(i) = postcode {
+        labels[i] = Some(Label::Postcode);
+    }
+
+    let street_type = (0..words.len())
+        .find(|&i| labels[i].is_none() && STREET_TYPES.contains(&words[i].norm.as_str()));
+
+    // The street goes from the street type (or the house number in front of the street name) to
+    // the first comma, postcode or unit.
+    let street_start = match street_type {
+        Some(i) => {
+            labels[i] = Some(Label::StreetType);
+            label_house_number_before(&words, &mut labels, i);
+            // '12 grande rue': the street name may come before the street type.
+            if !labels.contains(&Some(Label::HouseNumber))
+                && label_leading_house_number(&words, &mut labels)
+            {
+                for label in labels[1..i].iter_mut().filter(|l| l.is_none()) {
+                    *label = Some(Label::StreetName);
+                }
+            }
+            Some(i + 1)
+        }
+        // Without street type, only a house number starting the query tells it is an address.
+        None if words.len() > 1 => {
+            label_leading_house_number(&words, &mut labels);
+            labels
+                .iter()
+                .rposition(|label| *label == Some(Label::HouseNumber))
+                .map(|i| i + 1)
+        }
+        None => None,
+    };
+
+    if let Some(start) = street_start {
+        let mut end = start;
+        while end < words.len() && labels[end].is_none() {
+            end += 1;
+            if words[end - 1].comma {
+                break;
+            }
+        }
+
+        // 'rue X 12': the number ending the street is its house number.
+        let has_house_number = labels.contains(&Some(Label::HouseNumber));
+        if !has_house_number && end > start + 1 && is_house_number(&words[end - 1].norm) {
+            labels[end - 1] = Some(Label::HouseNumber);
+            end -= 1;
+        }
+
+        for label in &mut labels[start..end] {
+            *label = Some(Label::StreetName);
+        }
+
+        // The words after the street, after a comma or a postcode, are the city.
+        let city_start = end
+            + labels[end..]
+                .iter()
+                .take_while(|label| label.is_some())
+                .count();
+        if city_start > start && city_start < words.len() {
+            let separated =
+                words[city_start - 1].comma || labels[city_start - 1] == Some(Label::Postcode);
+            if separated {
+                for label in labels[city_start..].iter_mut().filter(|l| l.is_none()) {
+                    *label = Some(Label::City);
+                }
+            }
+        }
+    }
+
+    // The words after the postcode are the city.
+    if let Some(i) = postcode {
+        for label in labels[i + 1..].iter_mut().filter(|l| l.is_none()) {
+            *label = Some(Label::City);
+        }
+    }
+
+    // 'Apt', 'Suite Hôtel': without house number nor street, the words are not units.
+    let is_address = labels.iter().any(|label| {
+        matches!(
+            label,
+            Some(Label::HouseNumber) | Some(Label::StreetType) | Some(Label::StreetName)
+        )
+    });
+    if !is_address {
+        for label in labels.iter_mut().filter(|l| **l == Some(Label::Unit)) {
+            *label = None;
+        }
+    }
+
+    let mut parsed = ParsedQuery {
+        tokens: words
+            .into_iter()
+            .zip(labels)
+            .map(|(word, label)| Token {
+                text: word.text,
+                label: label.unwrap_or(Label::Other),
+            })
+            .collect(),
+        query: q.to_string(),
+        ..ParsedQuery::default()
+    };
+    parsed.house_number = parsed.join(Label::HouseNumber);
+    parsed.street_type = parsed.join(Label::StreetType);
+    parsed.street_name = parsed.join(Label::StreetName);
+    parsed.postcode = parsed.join(Label::Postcode);
+    parsed.city = parsed.join(Label::City);
+    parsed.unit = parsed.join(Label::Unit);
+    parsed
+}
+
+fn split(q: &str) -> Vec<Word> {
+    q.split_whitespace()
+        .flat_map(|chunk| {
+            let parts: Vec<&str> = chunk.split(',').collect();
+            let last = parts.len() - 1;
+            parts
+                .into_iter()
+                .enumerate()
+                .filter(|(_, part)| !part.is_empty())
+                .map(move |(i, part)| (part, i < last))
+                .collect::<Vec<_>>()
+        })
+        .map(|(text, comma)| Word {
+            text: text.to_string(),
+            norm: normalize(text),
+            comma,
+        })
+        .collect()
+}
+
+// Lowercase, without accents nor dots ('av.' is 'av').
+fn normalize(word: &str) -> String {
+    word.chars()
+        .filter(|c| *c != '.')
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            'à' | 'â' | 'ä' => 'a',
+            'é' | 'è' | 'ê' | 'ë' => 'e',
+            'î' | 'ï' => 'i',
+            'ô' | 'ö' => 'o',
+            'ù' | 'û' | 'ü' => 'u',
+            'ç' => 'c',
+            c => c,
+        })
+        .collect()
+}
+
+// 'apt 4', 'bat B', 'étage 3', '3e étage': a unit word is only a unit with its number.
+fn label_units(words: &[Word], labels: &mut [Option<Label>]) {
+    for i in 0..words.len() {
+        if !UNIT_WORDS.contains(&words[i].norm.as_str()) {
+            continue;
+        }
+        if i > 0 && is_ordinal(&words[i - 1].norm) {
+            labels[i - 1] = Some(Label::Unit);
+            labels[i] = Some(Label::Unit);
+        } else if words
+            .get(i + 1)
+            .map_or(false, |next| is_unit_identifier(&next.norm))
+        {
+            labels[i] = Some(Label::Unit);
+            labels[i + 1] = Some(Label::Unit);
+        }
+    }
+}
+
+// '4', '4B', '12', 'B': a number, or a single letter.
+fn is_unit_identifier(word: &str) -> bool {
+    let mut chars = word.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => c.is_alphanumeric(),
+        _ => {
+            word.len() <= 4
+                && word.chars().any(|c| c.is_ascii_digit())
+                && word.chars().all(char::is_alphanumeric)
+        }
+    }
+}
+
+// Labels the house number ending right before the given word, with its suffix ('12 bis').
+fn label_house_number_before(words: &[Word], labels: &mut [Option<Label>], i: usize) {
+    let free = |j: usize| labels[j].is_none();
+    match i {
+        0 => {}
+        1 => {
+            if free(0) && is_house_number(&words[0].norm) {
+                labels[0] = Some(Label::HouseNumber);
+            }
+        }
+        _ => {
+            if free(i - 1) && is_house_number(&words[i - 1].norm) {
+                labels[i - 1] = Some(Label::HouseNumber);
+            } else if free(i - 1)
+                && free(i - 2)
+                && is_house_number_suffix(&words[i - 1].norm)
+                && is_number(&words[i - 2].norm)
+            {
+                labels[i - 2] = Some(Label::HouseNumber);
+                labels[i - 1] = Some(Label::HouseNumber);
+            }
+        }
+    }
+}
+
+// Labels the house number starting the query, if any.
+fn label_leading_house_number(words: &[Word], labels: &mut [Option<Label>]) -> bool {
+    if labels[0].is_some() || !is_house_number(&words[0].norm) {
+        return false;
+    }
+    labels[0] = Some(Label::HouseNumber);
+    if words.len() > 2 && labels[1].is_none() && is_house_number_suffix(&words[1].norm) {
+        labels[1] = Some(Label::HouseNumber);
+    }
+    true
+}
+
+fn is_number(word: &str) -> bool {
+    !word.is_empty() && word.len() <= 4 && word.chars().all(|c| c.is_ascii_digit())
+}
+
+// '12', '12a', '12bis'.
+fn is_house_number(word: &str) -> bool {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    let (number, suffix) = word.split_at(digits);
+    is_number(number)
+        && (suffix.is_empty()
+            || (suffix.len() == 1 && suffix.chars().all(|c| c.is_ascii_alphabetic()))
+            || is_house_number_suffix(suffix))
+}
+
+fn is_house_number_suffix(word: &str) -> bool {
+    HOUSE_NUMBER_SUFFIXES.contains(&word)
+}
+
+fn is_postcode(word: &str) -> bool {
+    word.len() == 5 && word.chars().all(|c| c.is_ascii_digit())
+}
+
+// '3e', '1er', '2eme'.
+fn is_ordinal(word: &str) -> bool {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    let (number, suffix) = word.split_at(digits);
+    is_number(number) && ORDINAL_SUFFIXES.contains(&suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_house_number_with_suffix() {
+        let parsed = parse("12 bis rue Hector Malot, 75012 Paris");
+
+        assert_eq!(parsed.house_number.as_deref(), Some("12 bis"));
+        assert_eq!(parsed.street_type.as_deref(), Some("rue"));
+        assert_eq!(parsed.street_name.as_deref(), Some("Hector Malot"));
+        assert_eq!(parsed.postcode.as_deref(), Some("75012"));
+        assert_eq!(parsed.city.as_deref(), Some("Paris"));
+    }
+
+    #[test]
+    fn should_parse_house_number_after_street() {
+        let parsed = parse("rue Hector Malot 12");
+
+        assert_eq!(parsed.house_number.as_deref(), Some("12"));
+        assert_eq!(parsed.street_name.as_deref(), Some("Hector Malot"));
+    }
+
+    #[test]
+    fn should_remove_units_from_text() {
+        let parsed = parse("3e étage 20 av. Foch apt 4B");
+
+        assert_eq!(parsed.unit.as_deref(), Some("3e étage apt 4B"));
+        assert_eq!(parsed.house_number.as_deref(), Some("20"));
+        assert_eq!(parsed.street_type.as_deref(), Some("av."));
+        assert_eq!(parsed.text(), "20 av. Foch");
+    }
+
+    #[test]
+    fn should_only_remove_units_from_addresses() {
+        let parsed = parse("Apt");
+        assert_eq!(parsed.unit, None);
+        assert_eq!(parsed.text(), "Apt");
+
+        let parsed = parse("Suite Hôtel Paris");
+        assert_eq!(parsed.unit, None);
+        assert_eq!(parsed.text(), "Suite Hôtel Paris");
+
+        // Not an address: 'apt 4' is part of a name.
+        let parsed = parse("camping apt 4");
+        assert_eq!(parsed.text(), "camping apt 4");
+    }
+
+    #[test]
+    fn should_keep_the_punctuation_without_unit() {
+        let parsed = parse("12 rue Hector Malot, Paris");
+        assert_eq!(parsed.text(), "12 rue Hector Malot, Paris");
+    }
+
+    #[test]
+    fn should_not_parse_names() {
+        let parsed = parse("gare de lyon");
+
+        assert_eq!(parsed.house_number, None);
+        assert_eq!(parsed.street_name, None);
+        assert_eq!(parsed.text(), "gare de lyon");
+    }
+}
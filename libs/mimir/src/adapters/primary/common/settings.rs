@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use snafu::{ResultExt, Snafu};
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use tokio::{
     fs::File,
     io::{AsyncReadExt, BufReader},
@@ -13,6 +17,18 @@ pub enum Error {
 
     #[snafu(display("TOML Error: {}", source))]
     InvalidFileContent { source: toml::de::Error },
+
+    #[snafu(display("Config Compilation Error: {}", source))]
+    InvalidConfig { source: common::config::Error },
+
+    #[snafu(display("Config Value Error: {}", source))]
+    InvalidConfigValue { source: config::ConfigError },
+
+    #[snafu(display("Invalid ranking profile '{}': {}", name, source))]
+    InvalidProfile {
+        name: String,
+        source: serde_json::Error,
+    },
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -41,6 +57,13 @@ pub struct StringQueryBoosts {
     pub house_number: f64,
     pub label_ngram_with_coord: f64,
     pub label_ngram: f64,
+    // Query settings written before the phonetic matching do not have this boost.
+    #[serde(default = "default_label_phonetic")]
+    pub label_phonetic: f64,
+}
+
+fn default_label_phonetic() -> f64 {
+    0.6
 }
 
 #[derive(Clone, Copy, Debug, Deserialize, Serialize)]
@@ -98,6 +121,9 @@ pub struct QuerySettings {
     pub string_query: StringQuery,
     pub importance_query: ImportanceQueryBoosts,
     pub reverse_query: ReverseQuery,
+    // Languages with synonyms in Elasticsearch (see 'ctlmimir synonyms').
+    #[serde(default)]
+    pub synonym_langs: Vec<String>,
 }
 
 // This wrapper is used because the configuration file should
@@ -142,13 +168,167 @@ impl Default for QuerySettings {
     }
 }
 
+/// Name of the profile made of the query settings, without overrides.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Named sets of query settings (ranking profiles). Each profile is described in the
+/// configuration by the values it overrides in the query settings.
+#[derive(Clone, Debug)]
+pub struct QueryProfiles {
+    default: QuerySettings,
+    profiles: BTreeMap<String, QuerySettings>,
+}
+
+impl QueryProfiles {
+    pub fn new(
+        default: QuerySettings,
+        overrides: &BTreeMap<String, Value>,
+    ) -> Result<QueryProfiles, Error> {
+        let base = serde_json::to_value(&default).context(InvalidProfileSnafu {
+            name: DEFAULT_PROFILE,
+        })?;
+
+        let profiles = overrides
+            .iter()
+            .filter(|(name, _)| name.as_str() != DEFAULT_PROFILE)
+            .map(|(name, patch)| {
+                let mut settings = base.clone();
+                merge(&mut settings, patch);
+                let settings = serde_json::from_value(settings)
+                    .context(InvalidProfileSnafu { name: name.clone() })?;
+                Ok((name.clone(), settings))
+            })
+            .collect::<Result<_, Error>>()?;
+
+        Ok(QueryProfiles { default, profiles })
+    }
+
+    /// Returns the settings of the profile, the default ones if no profile is given, or None
+    /// if there is no such profile.
+    pub fn get(&self, name: Option<&str>) -> Option<&QuerySettings> {
+        match name {
+            None | Some(DEFAULT_PROFILE) => Some(&self.default),
+            Some(name) => self.profiles.get(name),
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        std::iter::once(DEFAULT_PROFILE.to_string())
+            .chain(self.profiles.keys().cloned())
+            .collect()
+    }
+}
+
+// Recursively replaces the values of 'base' with the ones found in 'patch'.
+fn merge(base: &mut Value, patch: &Value) {
+    match (base, patch) {
+        (Value::Object(base), Value::Object(patch)) => {
+            for (key, value) in patch {
+                match base.get_mut(key) {
+                    Some(base_value) => merge(base_value, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, patch) => *base = patch.clone(),
+    }
+}
+
+/// Where the query settings and the ranking profiles are read from, so that they can be
+/// read again while running.
+#[derive(Clone, Debug)]
+pub struct QueryProfilesSource {
+    pub config_dir: PathBuf,
+    pub run_mode: Option<String>,
+    pub env_prefix: String,
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct QueryProfilesConfig {
+    query: QuerySettings,
+    #[serde(default)]
+    profiles: BTreeMap<String, Value>,
+}
+
+impl QueryProfilesSource {
+    pub fn load(&self) -> Result<QueryProfiles, Error> {
+        let config: QueryProfilesConfig = common::config::config_from(
+            self.config_dir.as_path(),
+            &["query"],
+            self.run_mode.as_deref(),
+            self.env_prefix.as_str(),
+            self.overrides.clone(),
+        )
+        .context(InvalidConfigSnafu)?
+        .try_into()
+        .context(InvalidConfigValueSnafu)?;
+
+        QueryProfiles::new(config.query, &config.profiles)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use serde_json::json;
 
     #[tokio::test]
     async fn should_get_default_query_settings() {
         let _settings = QuerySettings::default();
     }
+
+    #[test]
+    fn should_default_the_phonetic_boost() {
+        let boosts: StringQueryBoosts = serde_json::from_value(serde_json::json!({
+            "name": 1.8,
+            "alt_name": 1.8,
+            "loc_name": 1.8,
+            "label": 0.6,
+            "label_prefix": 0.6,
+            "zip_codes": 1.0,
+            "house_number": 0.001,
+            "label_ngram_with_coord": 3.5,
+            "label_ngram": 1.0
+        }))
+        .unwrap();
+
+        assert_eq!(
+            boosts.label_phonetic,
+            QuerySettings::default().string_query.boosts.label_phonetic
+        );
+    }
+
+    #[test]
+    fn should_override_default_settings_in_profiles() {
+        let overrides = [(
+            "transit".to_string(),
+            json!({ "type_query": { "boosts": { "stop": 40.0 } } }),
+        )]
+        .into_iter()
+        .collect();
+        let profiles = QueryProfiles::new(QuerySettings::default(), &overrides).unwrap();
+
+        let transit = profiles.get(Some("transit")).unwrap();
+        let default = profiles.get(None).unwrap();
+        assert_eq!(transit.type_query.boosts.stop, 40.0);
+        assert_eq!(transit.type_query.boosts.poi, default.type_query.boosts.poi);
+        assert!(profiles.get(Some("delivery")).is_none());
+        assert_eq!(profiles.names(), vec!["default", "transit"]);
+    }
+
+    #[test]
+    fn should_reject_invalid_profiles() {
+        let overrides = [(
+            "broken".to_string(),
+            json!({ "type_query": { "global": "high" } }),
+        )]
+        .into_iter()
+        .collect();
+
+        assert!(QueryProfiles::new(QuerySettings::default(), &overrides).is_err());
+    }
 }
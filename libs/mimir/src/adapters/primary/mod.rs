@@ -1,3 +1,4 @@
 pub mod bragi;
 pub mod common;
+pub mod synonyms;
 pub mod templates;
@@ -0,0 +1,427 @@
+use config::{Config, File, FileFormat};
+use serde_json::{json, Map, Value};
+use snafu::{ResultExt, Snafu};
+use std::path::{Path, PathBuf};
+
+use crate::domain::{
+    model::{configuration::INDEX_ROOT, error::Error as ModelError},
+    ports::primary::configure_backend::ConfigureBackend,
+};
+
+/// Name of the component template holding the synonym analyzers.
+pub const COMPONENT_TEMPLATE: &str = "mimir-synonyms";
+
+const FILE_EXTENSION: &str = "txt";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("IO Error: {} [{}]", source, details))]
+    InvalidIO {
+        details: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Invalid language '{}': expected lowercase letters", lang))]
+    InvalidLang { lang: String },
+
+    #[snafu(display("Invalid synonym rule '{}': {}", rule, details))]
+    InvalidRule { rule: String, details: String },
+
+    #[snafu(display("Synonym rule '{}' already exists for '{}'", rule, lang))]
+    DuplicateRule { lang: String, rule: String },
+
+    #[snafu(display("Synonym rule '{}' does not exist for '{}'", rule, lang))]
+    UnknownRule { lang: String, rule: String },
+
+    #[snafu(display("Config Merge Error: {} [{}]", details, source))]
+    ConfigMerge {
+        details: String,
+        source: config::ConfigError,
+    },
+
+    #[snafu(display("Backend Error: {}", source))]
+    Backend { source: ModelError },
+}
+
+/// Name of the search analyzer applying the synonyms of the language.
+pub fn analyzer(lang: &str) -> String {
+    format!("synonyms_{}", lang)
+}
+
+/// Same as `analyzer`, removing the elisions ("l'", "d'") first.
+pub fn elision_analyzer(lang: &str) -> String {
+    format!("synonyms_elision_{}", lang)
+}
+
+/// Same as `analyzer`, splitting the words into trigrams, for the 'ngram' subfields.
+pub fn ngram_analyzer(lang: &str) -> String {
+    format!("synonyms_ngram_{}", lang)
+}
+
+/// The synonyms of a language, in the Solr format: either equivalent terms ("bd,boulevard"), or
+/// terms replaced by others ("bvd => boulevard").
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynonymSet {
+    pub lang: String,
+    pub rules: Vec<String>,
+}
+
+impl SynonymSet {
+    pub fn new(lang: &str) -> Result<Self, Error> {
+        if lang.is_empty() || !lang.chars().all(|c| c.is_ascii_lowercase()) {
+            return Err(Error::InvalidLang {
+                lang: lang.to_string(),
+            });
+        }
+        Ok(SynonymSet {
+            lang: lang.to_string(),
+            rules: Vec::new(),
+        })
+    }
+
+    /// Reads the rules of a synonym file, one per line. Empty lines and lines starting with '#'
+    /// are skipped.
+    pub fn parse(lang: &str, content: &str) -> Result<Self, Error> {
+        let mut set = SynonymSet::new(lang)?;
+        set.rules = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(normalize_rule)
+            .collect::<Result<_, _>>()?;
+        Ok(set)
+    }
+
+    /// Reads '<dir>/<lang>.txt', which may not exist yet.
+    pub async fn read(dir: &Path, lang: &str) -> Result<Self, Error> {
+        let path = path(dir, lang);
+        match tokio::fs::read_to_string(&path).await {
+            Ok(content) => SynonymSet::parse(lang, &content),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => SynonymSet::new(lang),
+            Err(err) => Err(Error::InvalidIO {
+                details: format!("could not read {}", path.display()),
+                source: err,
+            }),
+        }
+    }
+
+    pub async fn write(&self, dir: &Path) -> Result<(), Error> {
+        let path = path(dir, &self.lang);
+        let mut content = format!(
+            "# Synonyms for '{}', one rule per line. Use 'ctlmimir synonyms' to change them.\n",
+            self.lang
+        );
+        for rule in &self.rules {
+            content.push_str(rule);
+            content.push('\n');
+        }
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context(InvalidIOSnafu {
+                details: format!("could not create {}", dir.display()),
+            })?;
+        tokio::fs::write(&path, content)
+            .await
+            .context(InvalidIOSnafu {
+                details: format!("could not write {}", path.display()),
+            })
+    }
+
+    pub fn add(&mut self, rule: &str) -> Result<(), Error> {
+        let rule = normalize_rule(rule)?;
+        if self.rules.contains(&rule) {
+            return Err(Error::DuplicateRule {
+                lang: self.lang.clone(),
+                rule,
+            });
+        }
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, rule: &str) -> Result<(), Error> {
+        let rule = normalize_rule(rule)?;
+        let len = self.rules.len();
+        self.rules.retain(|r| *r != rule);
+        if self.rules.len() == len {
+            return Err(Error::UnknownRule {
+                lang: self.lang.clone(),
+                rule,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn path(dir: &Path, lang: &str) -> PathBuf {
+    dir.join(lang).with_extension(FILE_EXTENSION)
+}
+
+// Checks the rule, and removes the extra spaces around its terms so that rules can be compared.
+fn normalize_rule(rule: &str) -> Result<String, Error> {
+    let terms = |side: &str| -> Result<String, Error> {
+        let terms: Vec<&str> = side.split(',').map(str::trim).collect();
+        if terms.iter().any(|term| term.is_empty()) {
+            return Err(Error::InvalidRule {
+                rule: rule.to_string(),
+                details: String::from("empty term"),
+            });
+        }
+        Ok(terms.join(","))
+    };
+
+    match rule.split_once("=>") {
+        Some((from, to)) => Ok(format!("{} => {}", terms(from)?, terms(to)?)),
+        None => {
+            let equivalent = terms(rule)?;
+            if !equivalent.contains(',') {
+                return Err(Error::InvalidRule {
+                    rule: rule.to_string(),
+                    details: String::from("expected at least two terms"),
+                });
+            }
+            Ok(equivalent)
+        }
+    }
+}
+
+/// Reads all the synonym files of the directory, sorted by language. There are no synonyms if
+/// the directory does not exist.
+pub async fn read_dir(dir: &Path) -> Result<Vec<SynonymSet>, Error> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => {
+            return Err(Error::InvalidIO {
+                details: format!("{}", dir.display()),
+                source: err,
+            })
+        }
+    };
+
+    let mut sets = Vec::new();
+    while let Some(entry) = entries.next_entry().await.context(InvalidIOSnafu {
+        details: "could not get path",
+    })? {
+        let path = entry.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(FILE_EXTENSION) {
+            continue;
+        }
+        if let Some(lang) = path.file_stem().and_then(|stem| stem.to_str()) {
+            sets.push(SynonymSet::read(dir, lang).await?);
+        }
+    }
+    sets.sort_by(|a, b| a.lang.cmp(&b.lang));
+    Ok(sets)
+}
+
+/// The analysis settings defining the search analyzers of each language. They do not depend on
+/// the other component templates, so that this one can be validated alone.
+///
+/// The synonym filters are updateable, so that their analyzers can be reloaded on open indices.
+/// Only the synonyms read from files (in `synonyms_path`, on the Elasticsearch nodes) can change
+/// this way, the synonyms given inline can only change with the settings of closed indices.
+pub fn analysis(sets: &[SynonymSet], synonyms_path: Option<&str>) -> Value {
+    let mut filters = Map::new();
+    let mut analyzers = Map::new();
+
+    filters.insert(
+        String::from("synonyms_elision"),
+        json!({ "type": "elision", "articles": [ "l", "d" ] }),
+    );
+    // Same as the 'ngram' filter of the base component template.
+    filters.insert(
+        String::from("synonyms_ngram"),
+        json!({ "type": "ngram", "min_gram": 3, "max_gram": 3 }),
+    );
+
+    for set in sets {
+        let filter = format!("synonyms_{}", set.lang);
+        let synonyms = match synonyms_path {
+            Some(dir) => json!({
+                "type": "synonym_graph",
+                "synonyms_path": path(Path::new(dir), &set.lang),
+                "updateable": true
+            }),
+            None => json!({
+                "type": "synonym_graph",
+                "synonyms": set.rules,
+                "updateable": true
+            }),
+        };
+        filters.insert(filter.clone(), synonyms);
+        analyzers.insert(
+            analyzer(&set.lang),
+            json!({
+                "type": "custom",
+                "tokenizer": "standard",
+                "filter": [ "lowercase", "asciifolding", filter ]
+            }),
+        );
+        analyzers.insert(
+            elision_analyzer(&set.lang),
+            json!({
+                "type": "custom",
+                "tokenizer": "standard",
+                "filter": [ "lowercase", "synonyms_elision", "asciifolding", filter ]
+            }),
+        );
+        analyzers.insert(
+            ngram_analyzer(&set.lang),
+            json!({
+                "type": "custom",
+                "tokenizer": "standard",
+                "filter": [ "lowercase", "asciifolding", filter, "synonyms_ngram" ]
+            }),
+        );
+    }
+
+    json!({ "filter": filters, "analyzer": analyzers })
+}
+
+/// Creates (or replaces) the component template holding the synonyms. It only applies to the
+/// indices created afterwards.
+pub async fn import<C: ConfigureBackend>(
+    client: &C,
+    sets: &[SynonymSet],
+    synonyms_path: Option<&str>,
+) -> Result<(), Error> {
+    let template = json!({
+        "elasticsearch": {
+            "name": COMPONENT_TEMPLATE,
+            "template": {
+                "settings": {
+                    "analysis": analysis(sets, synonyms_path)
+                }
+            }
+        }
+    });
+
+    client
+        .configure(
+            String::from("create component template"),
+            to_config(&template)?,
+        )
+        .await
+        .context(BackendSnafu)
+}
+
+/// Updates the component template and the existing indices with the synonyms. As the synonyms are
+/// only used when searching, the indices do not need to be rebuilt.
+///
+/// The search analyzers of the indices are reloaded, which reads the synonym files again without
+/// closing the indices. With `force`, the indices missing some of the analyzers (created before
+/// the synonyms, or before a language was added), or with other inline synonyms, first get the
+/// new analysis settings: they are closed for the update, and cannot be searched meanwhile.
+pub async fn reload<C: ConfigureBackend>(
+    client: &C,
+    sets: &[SynonymSet],
+    synonyms_path: Option<&str>,
+    force: bool,
+) -> Result<(), Error> {
+    import(client, sets, synonyms_path).await?;
+
+    let index = format!("{}_*", INDEX_ROOT);
+    if force {
+        let settings = json!({
+            "elasticsearch": {
+                "index": index,
+                "analysis": analysis(sets, synonyms_path)
+            }
+        });
+
+        client
+            .configure(String::from("update index analysis"), to_config(&settings)?)
+            .await
+            .context(BackendSnafu)?;
+    }
+
+    let settings = json!({ "elasticsearch": { "index": index } });
+
+    client
+        .configure(
+            String::from("reload search analyzers"),
+            to_config(&settings)?,
+        )
+        .await
+        .context(BackendSnafu)
+}
+
+fn to_config(value: &Value) -> Result<Config, Error> {
+    let mut config = Config::default();
+    config
+        .merge(File::from_str(&value.to_string(), FileFormat::Json))
+        .context(ConfigMergeSnafu {
+            details: String::from("could not build the synonyms configuration"),
+        })?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_synonym_file() {
+        let set = SynonymSet::parse("fr", "# comment\n\nst, saint\nbvd , bd=>boulevard\n").unwrap();
+
+        assert_eq!(set.rules, vec!["st,saint", "bvd,bd => boulevard"]);
+        assert!(SynonymSet::parse("fr", "saint").is_err());
+        assert!(SynonymSet::parse("fr", "st,,saint").is_err());
+        assert!(SynonymSet::new("FR").is_err());
+    }
+
+    #[test]
+    fn should_add_and_remove_rules() {
+        let mut set = SynonymSet::new("de").unwrap();
+        set.add("str,strasse").unwrap();
+
+        assert!(set.add("str, strasse").is_err());
+        set.remove("str , strasse").unwrap();
+        assert!(set.rules.is_empty());
+        assert!(set.remove("str,strasse").is_err());
+    }
+
+    #[test]
+    fn should_build_analyzers_for_each_lang() {
+        let sets = vec![
+            SynonymSet::parse("de", "str,strasse").unwrap(),
+            SynonymSet::parse("fr", "st,saint").unwrap(),
+        ];
+        let analysis = analysis(&sets, None);
+
+        assert_eq!(
+            analysis["filter"]["synonyms_fr"]["synonyms"],
+            json!(["st,saint"])
+        );
+        assert_eq!(analysis["filter"]["synonyms_fr"]["updateable"], json!(true));
+        assert_eq!(
+            analysis["analyzer"]["synonyms_elision_de"]["filter"],
+            json!([
+                "lowercase",
+                "synonyms_elision",
+                "asciifolding",
+                "synonyms_de"
+            ])
+        );
+    }
+
+    #[test]
+    fn should_read_the_synonyms_from_files() {
+        let sets = vec![SynonymSet::parse("fr", "st,saint").unwrap()];
+        let analysis = analysis(&sets, Some("mimir/synonyms/"));
+
+        assert_eq!(
+            analysis["filter"]["synonyms_fr"],
+            json!({
+                "type": "synonym_graph",
+                "synonyms_path": "mimir/synonyms/fr.txt",
+                "updateable": true
+            })
+        );
+        assert_eq!(
+            analysis["analyzer"]["synonyms_ngram_fr"]["filter"],
+            json!(["lowercase", "asciifolding", "synonyms_fr", "synonyms_ngram"])
+        );
+    }
+}
@@ -1,28 +1,37 @@
 use elasticsearch::{
     cat::CatIndicesParts,
     cluster::{ClusterHealthParts, ClusterPutComponentTemplateParts},
-    http::response::Exception,
+    http::{
+        response::{Exception, Response},
+        StatusCode,
+    },
     indices::{
-        IndicesCreateParts, IndicesDeleteParts, IndicesForcemergeParts, IndicesGetAliasParts,
-        IndicesPutIndexTemplateParts, IndicesRefreshParts,
+        IndicesCloseParts, IndicesCreateParts, IndicesDeleteParts, IndicesForcemergeParts,
+        IndicesGetAliasParts, IndicesGetIndexTemplateParts, IndicesGetSettingsParts,
+        IndicesOpenParts, IndicesPutIndexTemplateParts, IndicesPutSettingsParts,
+        IndicesRefreshParts, IndicesReloadSearchAnalyzersParts,
     },
     ingest::IngestPutPipelineParts,
     params::TrackTotalHits,
     BulkOperation, BulkParts, ExplainParts, MgetParts, OpenPointInTimeParts, SearchParts,
 };
-use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use futures::{
+    future::{BoxFuture, FutureExt},
+    stream::{self, Stream, StreamExt, TryStreamExt},
+};
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
 use snafu::{ResultExt, Snafu};
 use std::{collections::BTreeMap, convert::TryFrom, pin::Pin, time::Duration};
-use tracing::{info, warn};
+use tracing::{info, instrument, warn, Instrument};
 
 use super::{
     configuration::{
         ComponentTemplateConfiguration, Error as ConfigurationError, IndexTemplateConfiguration,
     },
+    metrics,
     models::{
         ElasticsearchBulkResponse, ElasticsearchForcemergeResponse, ElasticsearchSearchResponse,
     },
@@ -37,7 +46,7 @@ use crate::{
         index::{Index, IndexStatus},
         query::Query,
         stats::InsertStats as ModelInsertStats,
-        status::{StorageHealth, Version as StorageVersion},
+        status::{StorageHealth, StorageIndex, StorageTemplate, Version as StorageVersion},
     },
     utils::futures::with_backoff,
 };
@@ -224,6 +233,51 @@ impl From<Option<Exception>> for Error {
     }
 }
 
+// Whether the analysis settings of an index have all the filters and analyzers of 'analysis'.
+// Elasticsearch gives the values of the settings as strings.
+fn has_analysis(current: &Value, analysis: &Value) -> bool {
+    fn stringify(value: &Value) -> Value {
+        match value {
+            Value::Bool(_) | Value::Number(_) => Value::String(value.to_string()),
+            Value::Array(values) => Value::Array(values.iter().map(stringify).collect()),
+            Value::Object(values) => Value::Object(
+                values
+                    .iter()
+                    .map(|(key, value)| (key.clone(), stringify(value)))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    ["filter", "analyzer", "tokenizer", "char_filter"]
+        .iter()
+        .all(|kind| {
+            analysis[kind].as_object().map_or(true, |definitions| {
+                definitions
+                    .iter()
+                    .all(|(name, definition)| current[kind][name] == stringify(definition))
+            })
+        })
+}
+
+// The url of the node a failed request was sent to, if known.
+fn failed_url(err: &elasticsearch::Error) -> Option<url::Url> {
+    std::error::Error::source(err)?
+        .downcast_ref::<reqwest::Error>()?
+        .url()
+        .cloned()
+}
+
+// Turns an unsuccessful response into its exception.
+async fn check_success(response: Response) -> Result<(), Error> {
+    if response.status_code().is_success() {
+        Ok(())
+    } else {
+        Err(response.exception().await.ok().flatten().into())
+    }
+}
+
 impl ElasticsearchStorage {
     pub(super) async fn create_index(
         &self,
@@ -587,7 +641,9 @@ impl ElasticsearchStorage {
                 let client = self.clone();
 
                 async move {
-                    tokio::spawn(client.bulk_block(index, chunk))
+                    // Each chunk gets its own span, so that the progress of a long indexing job
+                    // can be followed while it runs.
+                    tokio::spawn(client.bulk_block(index, chunk).in_current_span())
                         .await
                         .expect("tokio task panicked")
                         .unwrap_or_else(|err| panic!("Error inserting chunk: {}", err))
@@ -600,6 +656,7 @@ impl ElasticsearchStorage {
         Ok(stats)
     }
 
+    #[instrument(skip(self, chunk), fields(documents = chunk.len()))]
     async fn bulk_block<D>(
         self,
         index: String,
@@ -609,22 +666,28 @@ impl ElasticsearchStorage {
         D: Serialize + Send + Sync + 'static,
     {
         let mut stats = InsertStats::default();
+        let mut attempts = 0;
 
         let resp = with_backoff(
-            || async {
-                self.client
-                    .bulk(BulkParts::Index(index.as_str()))
-                    .request_timeout(self.config.timeout)
-                    .body(chunk.iter().collect())
-                    .send()
-                    .await?
-                    .error_for_status_code()
+            || {
+                attempts += 1;
+                async {
+                    self.client
+                        .bulk(BulkParts::Index(index.as_str()))
+                        .request_timeout(self.config.timeout)
+                        .body(chunk.iter().collect())
+                        .send()
+                        .await?
+                        .error_for_status_code()
+                }
             },
             self.config.bulk_backoff.retry,
             self.config.bulk_backoff.wait,
         )
-        .await
-        .context(ElasticsearchClientSnafu {
+        .await;
+
+        metrics::record_retries(attempts - 1);
+        let resp = resp.context(ElasticsearchClientSnafu {
             details: "cannot bulk insert",
         })?;
 
@@ -664,6 +727,7 @@ impl ElasticsearchStorage {
                 Ok::<_, Error>(())
             })?;
 
+            metrics::record_bulk(&stats);
             Ok(stats)
         }
     }
@@ -917,6 +981,125 @@ impl ElasticsearchStorage {
             .collect())
     }
 
+    // Gives the analysis to the indices matching 'index' which do not have it yet. The other
+    // indices are left open.
+    pub(super) async fn update_index_analysis(
+        &self,
+        index: String,
+        analysis: Value,
+    ) -> Result<(), Error> {
+        let response = self
+            .client
+            .indices()
+            .get_settings(IndicesGetSettingsParts::Index(&[&index]))
+            .request_timeout(self.config.timeout)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot get settings of index {}", index),
+            })?;
+
+        if !response.status_code().is_success() {
+            return Err(response.exception().await.ok().flatten().into());
+        }
+
+        // Response similar to:
+        // {
+        //   "index1": {
+        //     "settings": {
+        //       "index": {
+        //         "analysis": { "filter": { ... }, "analyzer": { ... } },
+        //         ...
+        //       }
+        //     }
+        //   }
+        // }
+        let json = response
+            .json::<Value>()
+            .await
+            .context(ElasticsearchDeserializationSnafu)?;
+
+        let outdated: Vec<String> = json
+            .as_object()
+            .map(|indices| {
+                indices
+                    .iter()
+                    .filter(|(_, value)| {
+                        !has_analysis(&value["settings"]["index"]["analysis"], &analysis)
+                    })
+                    .map(|(index, _)| index.clone())
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for index in outdated {
+            info!("updating the analysis of index {}", index);
+            self.update_index_settings(index, json!({ "analysis": analysis }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    // Reloads the updateable analyzers, and the files they read (eg synonyms), on open indices.
+    pub(super) async fn reload_search_analyzers(&self, index: String) -> Result<(), Error> {
+        let response = self
+            .client
+            .indices()
+            .reload_search_analyzers(IndicesReloadSearchAnalyzersParts::Index(&[&index]))
+            .request_timeout(self.config.timeout)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot reload search analyzers of index {}", index),
+            })?;
+        check_success(response).await
+    }
+
+    // Some settings, like the analysis, can only be changed on closed indices. So the indices
+    // are closed, updated, and opened again, even if the update failed.
+    async fn update_index_settings(&self, index: String, settings: Value) -> Result<(), Error> {
+        let response = self
+            .client
+            .indices()
+            .close(IndicesCloseParts::Index(&[&index]))
+            .request_timeout(self.config.timeout)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot close index {}", index),
+            })?;
+        check_success(response).await?;
+
+        let update = match self
+            .client
+            .indices()
+            .put_settings(IndicesPutSettingsParts::Index(&[&index]))
+            .request_timeout(self.config.timeout)
+            .body(settings)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot update settings of index {}", index),
+            }) {
+            Ok(response) => check_success(response).await,
+            Err(err) => Err(err),
+        };
+
+        let response = self
+            .client
+            .indices()
+            .open(IndicesOpenParts::Index(&[&index]))
+            .request_timeout(self.config.timeout)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot open index {}", index),
+            })?;
+        check_success(response).await?;
+
+        update
+    }
+
     pub(super) async fn refresh_index(&self, index: String) -> Result<(), Error> {
         let response = self
             .client
@@ -1085,6 +1268,40 @@ impl ElasticsearchStorage {
         limit_result: i64,
         timeout: Option<Duration>,
     ) -> Result<Vec<D>, Error>
+    where
+        D: DeserializeOwned + Send + Sync + 'static,
+    {
+        let body = self
+            .search_response(indices, query, limit_result, timeout)
+            .await?;
+        Ok(body.into_hits().collect())
+    }
+
+    /// Same as search_documents, but each document comes with its relevance score.
+    pub(super) async fn search_scored_documents<D>(
+        &self,
+        indices: Vec<String>,
+        query: Query,
+        limit_result: i64,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(f64, D)>, Error>
+    where
+        D: DeserializeOwned + Send + Sync + 'static,
+    {
+        let body = self
+            .search_response(indices, query, limit_result, timeout)
+            .await?;
+        Ok(body.into_scored_hits().collect())
+    }
+
+    #[instrument(skip(self, query))]
+    async fn search_response<D>(
+        &self,
+        indices: Vec<String>,
+        query: Query,
+        limit_result: i64,
+        timeout: Option<Duration>,
+    ) -> Result<ElasticsearchSearchResponse<D>, Error>
     where
         D: DeserializeOwned + Send + Sync + 'static,
     {
@@ -1105,47 +1322,38 @@ impl ElasticsearchStorage {
         let shard_timeout = format!("{}ms", timeout.as_millis());
         let request_timeout = timeout.saturating_add(timeout);
 
-        let search = self
-            .client
-            .search(SearchParts::Index(&indices))
-            // we don't care for the total number of hits, and it takes some time to compute
-            // so we disable it
-            .track_total_hits(TrackTotalHits::Track(false))
-            // global search will end when limit_result are found
-            .size(limit_result)
-            // search in each *shard* will end after shard_timeout
-            .timeout(&shard_timeout)
-            // response will be a 408 REQUEST TIMEOUT
-            // if I did not receive a full http response from elasticsearch
-            // after request_timeout
-            .request_timeout(request_timeout)
-            // search in each *shard* will end after shard_limit_result hits are found
-            // we do not active it, since it means that we may not find the right hit.
-            // We did some test when looking for a specific address : we could not 
-            // obtain the right address even with shard_limit_result = 10_000
-            //.terminate_after(shard_limit_result)
-            ;
-
-        let response = match query {
-            Query::QueryString(q) => {
-                search
-                    .q(&q)
-                    .send()
-                    .await
-                    .context(ElasticsearchClientSnafu {
-                        details: format!("could not search indices {}", indices.join(", ")),
-                    })?
-            }
-            Query::QueryDSL(json) => {
-                search
-                    .body(json)
-                    .send()
-                    .await
-                    .context(ElasticsearchClientSnafu {
-                        details: format!("could not search indices {}", indices.join(", ")),
-                    })?
-            }
-        };
+        let response = self
+            .send_read(|| {
+                let search = self
+                    .client
+                    .search(SearchParts::Index(&indices))
+                    // we don't care for the total number of hits, and it takes some time to compute
+                    // so we disable it
+                    .track_total_hits(TrackTotalHits::Track(false))
+                    // global search will end when limit_result are found
+                    .size(limit_result)
+                    // search in each *shard* will end after shard_timeout
+                    .timeout(&shard_timeout)
+                    // response will be a 408 REQUEST TIMEOUT
+                    // if I did not receive a full http response from elasticsearch
+                    // after request_timeout
+                    .request_timeout(request_timeout)
+                    // search in each *shard* will end after shard_limit_result hits are found
+                    // we do not active it, since it means that we may not find the right hit.
+                    // We did some test when looking for a specific address : we could not 
+                    // obtain the right address even with shard_limit_result = 10_000
+                    //.terminate_after(shard_limit_result)
+                    ;
+
+                match &query {
+                    Query::QueryString(q) => search.q(q).send().boxed(),
+                    Query::QueryDSL(json) => search.body(json.clone()).send().boxed(),
+                }
+            })
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("could not search indices {}", indices.join(", ")),
+            })?;
 
         if response.status_code().is_success() {
             let body = response
@@ -1153,7 +1361,7 @@ impl ElasticsearchStorage {
                 .await
                 .context(ElasticsearchDeserializationSnafu)?;
 
-            Ok(body.into_hits().collect())
+            Ok(body)
         } else {
             Err(response
                 .exception()
@@ -1163,6 +1371,45 @@ impl ElasticsearchStorage {
         }
     }
 
+    // Sends an idempotent read request. When connected to several nodes, the request is
+    // retried if it could not be sent, and it is then sent to the next healthy node.
+    async fn send_read<'a, F>(&'a self, send: F) -> Result<Response, elasticsearch::Error>
+    where
+        F: Fn() -> BoxFuture<'a, Result<Response, elasticsearch::Error>>,
+    {
+        let retries = match self.pool {
+            Some(_) => self.config.nodes.read_retries,
+            None => 0,
+        };
+
+        // After a connection error or a server error, the node is skipped until its next health
+        // check, and the request is sent again to the next healthy node.
+        let mut res = send().await;
+        for _ in 0..retries {
+            let failed_node = match &res {
+                Ok(response) if !response.status_code().is_server_error() => break,
+                Ok(response) => {
+                    warn!(
+                        "retrying on another node after status {} from {}",
+                        response.status_code(),
+                        response.url()
+                    );
+                    Some(response.url().clone())
+                }
+                Err(err) => {
+                    warn!("retrying on another node after error: {}", err);
+                    failed_url(err)
+                }
+            };
+            if let (Some(pool), Some(url)) = (&self.pool, failed_node) {
+                pool.mark_unhealthy(&url);
+            }
+            res = send().await;
+        }
+
+        res
+    }
+
     pub(super) async fn get_documents_by_id<D>(
         &self,
         query: Query,
@@ -1185,24 +1432,29 @@ impl ElasticsearchStorage {
             }) // let's cap the timeout to self.config.timeout to prevent overloading elasticsearch with long requests
             .unwrap_or(self.config.timeout);
 
-        let get = self.client.mget(MgetParts::None).request_timeout(timeout);
-
-        let response = match query {
+        let json = match query {
             Query::QueryString(_) => {
                 return Err(Error::Internal {
                     reason: "QueryString not handled for get document by id".to_string(),
                 })
             }
-            Query::QueryDSL(json) => {
-                get.body(json)
-                    .send()
-                    .await
-                    .context(ElasticsearchClientSnafu {
-                        details: "could not get document by id".to_string(),
-                    })?
-            }
+            Query::QueryDSL(json) => json,
         };
 
+        let response = self
+            .send_read(|| {
+                self.client
+                    .mget(MgetParts::None)
+                    .request_timeout(timeout)
+                    .body(json.clone())
+                    .send()
+                    .boxed()
+            })
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: "could not get document by id".to_string(),
+            })?;
+
         if response.status_code().is_success() {
             let body = response
                 .json::<ElasticsearchGetResponse<D>>()
@@ -1386,6 +1638,175 @@ impl ElasticsearchStorage {
                 .into())
         }
     }
+
+    /// Returns the indices of documents, along with their aliases and the date of their indexing.
+    pub(super) async fn list_indices(&self) -> Result<Vec<StorageIndex>, Error> {
+        let pattern = format!("{}_*", configuration::INDEX_ROOT);
+        let response = self
+            .client
+            .cat()
+            .indices(CatIndicesParts::Index(&[&pattern]))
+            .request_timeout(self.config.timeout)
+            .format("json")
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot list indices '{}'", pattern),
+            })?;
+
+        if !response.status_code().is_success() {
+            return Err(response
+                .exception()
+                .await
+                .expect("failed to fetch Elasticsearch exception")
+                .into());
+        }
+
+        let json = response
+            .json::<Value>()
+            .await
+            .context(ElasticsearchDeserializationSnafu)?;
+        let indices: Vec<ElasticsearchIndex> =
+            serde_json::from_value(json).context(JsonDeserializationSnafu {
+                details: String::from("could not deserialize Elasticsearch indices"),
+            })?;
+
+        let mut aliases = self
+            .find_aliases(configuration::INDEX_ROOT.to_string())
+            .await?;
+        let mut indexed_at = self.indexed_at(&pattern).await?;
+
+        // Indices which do not follow the naming of mimir are not reported.
+        Ok(indices
+            .into_iter()
+            .filter_map(|index| {
+                let (doc_type, dataset) = configuration::split_index_name(&index.name).ok()?;
+                let docs_count = index
+                    .docs_count
+                    .as_deref()
+                    .and_then(|count| count.parse().ok())
+                    .unwrap_or(0);
+                Some(StorageIndex {
+                    doc_type,
+                    dataset,
+                    docs_count,
+                    indexed_at: indexed_at.remove(&index.name),
+                    aliases: aliases.remove(&index.name).unwrap_or_default(),
+                    name: index.name,
+                })
+            })
+            .collect())
+    }
+
+    // The field 'indexed_at' is not indexed, so it is read from one document of each index: all
+    // the documents of an index are indexed by the same import.
+    async fn indexed_at(&self, pattern: &str) -> Result<BTreeMap<String, String>, Error> {
+        let response = self
+            .client
+            .search(SearchParts::Index(&[pattern]))
+            .request_timeout(self.config.timeout)
+            .body(json!({
+                "size": 0,
+                "aggs": {
+                    "indices": {
+                        "terms": { "field": "_index", "size": 1000 },
+                        "aggs": {
+                            "document": {
+                                "top_hits": { "size": 1, "_source": ["indexed_at"] }
+                            }
+                        }
+                    }
+                }
+            }))
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot find the indexing dates of '{}'", pattern),
+            })?;
+
+        if !response.status_code().is_success() {
+            return Err(response
+                .exception()
+                .await
+                .expect("failed to fetch Elasticsearch exception")
+                .into());
+        }
+
+        let json = response
+            .json::<Value>()
+            .await
+            .context(ElasticsearchDeserializationSnafu)?;
+
+        Ok(json["aggregations"]["indices"]["buckets"]
+            .as_array()
+            .map(|buckets| {
+                buckets
+                    .iter()
+                    .filter_map(|bucket| {
+                        let index = bucket["key"].as_str()?;
+                        let indexed_at = bucket["document"]["hits"]["hits"][0]["_source"]
+                            ["indexed_at"]
+                            .as_str()?;
+                        Some((index.to_string(), indexed_at.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Returns the index templates matching the name, with their version.
+    pub(super) async fn index_templates(&self, name: &str) -> Result<Vec<StorageTemplate>, Error> {
+        let response = self
+            .client
+            .indices()
+            .get_index_template(IndicesGetIndexTemplateParts::Name(name))
+            .request_timeout(self.config.timeout)
+            .send()
+            .await
+            .context(ElasticsearchClientSnafu {
+                details: format!("cannot find index templates '{}'", name),
+            })?;
+
+        // Elasticsearch answers 'not found' when no template matches.
+        if response.status_code() == StatusCode::NOT_FOUND {
+            return Ok(Vec::new());
+        }
+
+        if !response.status_code().is_success() {
+            return Err(response
+                .exception()
+                .await
+                .expect("failed to fetch Elasticsearch exception")
+                .into());
+        }
+
+        // Response similar to:
+        // {
+        //   "index_templates": [
+        //     { "name": "mimir-addr", "index_template": { "version": 3, ... } }
+        //   ]
+        // }
+        let json = response
+            .json::<Value>()
+            .await
+            .context(ElasticsearchDeserializationSnafu)?;
+
+        let templates = json["index_templates"]
+            .as_array()
+            .ok_or_else(|| Error::JsonInvalid {
+                details: String::from("expected 'index_templates'"),
+                json: json.clone(),
+            })?
+            .iter()
+            .filter_map(|template| {
+                Some(StorageTemplate {
+                    name: template["name"].as_str()?.to_string(),
+                    version: template["index_template"]["version"].as_u64(),
+                })
+            })
+            .collect();
+        Ok(templates)
+    }
 }
 
 /// This is the information provided by Elasticsearch CAT Indice API
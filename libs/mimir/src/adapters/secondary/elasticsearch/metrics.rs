@@ -0,0 +1,66 @@
+//! Metrics of the insertion of documents, shared by all the importers.
+
+use super::internal::InsertStats;
+
+#[cfg(feature = "metrics")]
+lazy_static::lazy_static! {
+    static ref INDEXED_DOCUMENTS: prometheus::IntCounterVec = prometheus::register_int_counter_vec!(
+        "mimir_indexed_documents_total",
+        "Number of documents sent to Elasticsearch by bulk requests, by result.",
+        &["result"]
+    )
+    .unwrap();
+
+    static ref BULK_REQUESTS: prometheus::IntCounter = prometheus::register_int_counter!(
+        "mimir_bulk_requests_total",
+        "Number of successful bulk requests."
+    )
+    .unwrap();
+
+    static ref BULK_RETRIES: prometheus::IntCounter = prometheus::register_int_counter!(
+        "mimir_bulk_retries_total",
+        "Number of bulk requests sent again after an error, following 'bulk_backoff'."
+    )
+    .unwrap();
+}
+
+#[cfg(feature = "metrics")]
+pub(super) fn record_bulk(stats: &InsertStats) {
+    BULK_REQUESTS.inc();
+    for (result, count) in [
+        ("created", stats.created),
+        ("updated", stats.updated),
+        ("skipped", stats.skipped),
+        ("deleted", stats.deleted),
+    ] {
+        INDEXED_DOCUMENTS
+            .with_label_values(&[result])
+            .inc_by(count as u64);
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(super) fn record_bulk(_stats: &InsertStats) {}
+
+#[cfg(feature = "metrics")]
+pub(super) fn record_retries(retries: u64) {
+    BULK_RETRIES.inc_by(retries);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(super) fn record_retries(_retries: u64) {}
+
+/// Number of documents processed by Elasticsearch since the start of the process, whatever their
+/// result.
+#[cfg(feature = "metrics")]
+pub fn indexed_documents() -> u64 {
+    ["created", "updated", "skipped", "deleted"]
+        .iter()
+        .map(|result| INDEXED_DOCUMENTS.with_label_values(&[result]).get())
+        .sum()
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn indexed_documents() -> u64 {
+    0
+}
@@ -1,4 +1,4 @@
-use crate::utils::deserialize::deserialize_duration;
+use crate::utils::deserialize::{deserialize_duration, deserialize_opt_duration};
 use elasticsearch::Elasticsearch;
 use serde::{Deserialize, Serialize};
 use std::{path::PathBuf, time::Duration};
@@ -8,7 +8,9 @@ pub mod configuration;
 pub mod explain;
 pub(super) mod internal;
 pub mod list;
+pub mod metrics;
 pub mod models;
+pub mod pool;
 pub mod query;
 pub mod remote;
 pub mod status;
@@ -21,6 +23,8 @@ pub struct ElasticsearchStorage {
     pub(crate) client: Elasticsearch,
     /// Client configuration
     pub config: ElasticsearchStorageConfig,
+    /// Pool of nodes, when the client is connected to several nodes
+    pub(crate) pool: Option<pool::MultiNodeConnectionPool>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -36,6 +40,113 @@ pub struct ElasticsearchStorageConfig {
     pub wait_for_active_shards: u64,
     pub force_merge: ElasticsearchStorageForceMergeConfig,
     pub bulk_backoff: ElasticsearchStorageBackoffConfig,
+    #[serde(default)]
+    pub auth: Option<ElasticsearchStorageAuthConfig>,
+    #[serde(default)]
+    pub tls: ElasticsearchStorageTlsConfig,
+    #[serde(default)]
+    pub nodes: ElasticsearchStorageNodesConfig,
+    /// Directory of the synonym files, relative to the config directory of the Elasticsearch
+    /// nodes. The synonyms are then reloaded without closing the indices. If None, the synonyms
+    /// are given in the index settings.
+    #[serde(default)]
+    pub synonyms_path: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ElasticsearchStorageNodesConfig {
+    /// Other nodes of the cluster, used along with `url`.
+    pub urls: Vec<Url>,
+    /// If set, the nodes of the cluster are discovered (sniffed) at this interval.
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub sniff_interval: Option<Duration>,
+    /// Interval between two checks of the health of each node.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub health_check_interval: Duration,
+    /// Number of times a failed read (search, get by id), with a connection error or a server
+    /// error, is retried on another node.
+    pub read_retries: u8,
+}
+
+impl Default for ElasticsearchStorageNodesConfig {
+    fn default() -> Self {
+        ElasticsearchStorageNodesConfig {
+            urls: vec![],
+            sniff_interval: None,
+            health_check_interval: Duration::from_secs(10),
+            read_retries: 2,
+        }
+    }
+}
+
+impl ElasticsearchStorageNodesConfig {
+    /// The multi-node pool is used when several nodes are given, or when sniffing is enabled.
+    pub fn is_multi_node(&self) -> bool {
+        !self.urls.is_empty() || self.sniff_interval.is_some()
+    }
+}
+
+/// Credentials sent with each request to Elasticsearch.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ElasticsearchStorageAuthConfig {
+    Basic {
+        username: String,
+        #[serde(serialize_with = "redact")]
+        password: String,
+    },
+    ApiKey {
+        id: String,
+        #[serde(serialize_with = "redact")]
+        key: String,
+    },
+    Bearer {
+        #[serde(serialize_with = "redact")]
+        token: String,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ElasticsearchStorageTlsConfig {
+    /// PEM file with the certificate authority used to validate the server's certificate.
+    /// The system's certificates are used if None.
+    pub ca_bundle: Option<PathBuf>,
+    /// PKCS#12 archive with the client certificate and its private key. It cannot be
+    /// used together with `auth`.
+    pub client_certificate: Option<PathBuf>,
+    #[serde(serialize_with = "redact_opt")]
+    pub client_certificate_password: Option<String>,
+    /// If false, the server's certificate is validated against the `ca_bundle`, but
+    /// its hostname is not checked.
+    pub verify_hostname: bool,
+}
+
+impl Default for ElasticsearchStorageTlsConfig {
+    fn default() -> Self {
+        ElasticsearchStorageTlsConfig {
+            ca_bundle: None,
+            client_certificate: None,
+            client_certificate_password: None,
+            verify_hostname: true,
+        }
+    }
+}
+
+// Secrets are not displayed when the configuration is serialized (eg by the 'config' commands).
+fn redact<S: serde::Serializer>(_: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str("********")
+}
+
+fn redact_opt<S: serde::Serializer>(
+    secret: &Option<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match secret {
+        Some(secret) => redact(secret, serializer),
+        None => serializer.serialize_none(),
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -248,4 +359,20 @@ pub mod tests {
             .to_string()
             .contains("Elasticsearch Invalid version"));
     }
+
+    #[test]
+    fn should_not_serialize_secrets() {
+        let config = ElasticsearchStorageConfig {
+            auth: Some(ElasticsearchStorageAuthConfig::Basic {
+                username: "mimir".to_string(),
+                password: "secret".to_string(),
+            }),
+            ..ElasticsearchStorageConfig::default_testing()
+        };
+
+        let json = serde_json::to_string(&config).unwrap();
+
+        assert!(json.contains("\"username\":\"mimir\""));
+        assert!(!json.contains("secret"));
+    }
 }
@@ -22,6 +22,8 @@ pub struct ElasticsearchHits<D> {
 pub struct ElasticsearchHit<D> {
     #[serde(rename = "_source")]
     pub source: D,
+    #[serde(rename = "_score", default)]
+    pub score: Option<f64>,
     #[serde(default)]
     pub sort: Vec<Value>,
 }
@@ -31,6 +33,15 @@ impl<D> ElasticsearchSearchResponse<D> {
     pub fn into_hits(self) -> impl Iterator<Item = D> {
         self.hits.hits.into_iter().map(|hit| hit.source)
     }
+
+    /// Consume the response into an iterator over the responded documents and their score.
+    /// Documents without score (eg. when the results are sorted) get a null score.
+    pub fn into_scored_hits(self) -> impl Iterator<Item = (f64, D)> {
+        self.hits
+            .hits
+            .into_iter()
+            .map(|hit| (hit.score.unwrap_or_default(), hit.source))
+    }
 }
 
 // Get API
@@ -0,0 +1,302 @@
+use elasticsearch::http::{
+    headers::HeaderMap,
+    response::Response,
+    transport::{Connection, ConnectionPool, SingleNodeConnectionPool, Transport},
+    Method,
+};
+use serde_json::Value;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock, Weak,
+};
+use tracing::{info, warn};
+use url::Url;
+
+use super::{remote::transport_builder, ElasticsearchStorageConfig};
+
+/// The state of a node of the cluster, as seen by the pool.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub url: Url,
+    pub healthy: bool,
+}
+
+#[derive(Debug)]
+struct Inner {
+    nodes: RwLock<Vec<Node>>,
+    next: AtomicUsize,
+}
+
+/// A connection pool over several nodes of a cluster.
+///
+/// Requests are sent to the healthy nodes in turn. The health of each node is checked
+/// periodically, and the list of nodes can be discovered from the cluster itself (sniffing).
+/// If no node is known to be healthy, all the nodes are used.
+#[derive(Debug, Clone)]
+pub struct MultiNodeConnectionPool {
+    inner: Arc<Inner>,
+}
+
+impl MultiNodeConnectionPool {
+    pub fn new(urls: Vec<Url>) -> Self {
+        let nodes = urls
+            .into_iter()
+            .map(|url| Node { url, healthy: true })
+            .collect();
+
+        MultiNodeConnectionPool {
+            inner: Arc::new(Inner {
+                nodes: RwLock::new(nodes),
+                next: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Builds the pool with the nodes found in the configuration: the main url, followed by
+    /// the additional urls.
+    pub fn from_config(config: &ElasticsearchStorageConfig) -> Self {
+        let mut urls = vec![config.url.clone()];
+        for url in &config.nodes.urls {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        MultiNodeConnectionPool::new(urls)
+    }
+
+    // The next node to send a request to, among the healthy ones.
+    fn next_url(&self) -> Url {
+        let nodes = self.inner.nodes.read().expect("nodes lock poisoned");
+        let healthy: Vec<&Node> = nodes.iter().filter(|node| node.healthy).collect();
+        let candidates: Vec<&Node> = if healthy.is_empty() {
+            nodes.iter().collect()
+        } else {
+            healthy
+        };
+        let index = self.inner.next.fetch_add(1, Ordering::Relaxed) % candidates.len();
+        candidates[index].url.clone()
+    }
+
+    /// Returns the nodes currently known by the pool.
+    pub fn nodes(&self) -> Vec<Node> {
+        self.inner
+            .nodes
+            .read()
+            .expect("nodes lock poisoned")
+            .clone()
+    }
+
+    /// Skips a node which failed a request, until its next health check.
+    pub(super) fn mark_unhealthy(&self, url: &Url) {
+        self.set_health(url, false);
+    }
+
+    /// Sends a request to the root endpoint of the nodes in turn, and returns the response of
+    /// the first one which answers. The nodes which do not answer are marked unhealthy.
+    pub(super) async fn probe(&self, config: &ElasticsearchStorageConfig) -> Option<Response> {
+        for node in self.nodes() {
+            match root(&node.url, config).await {
+                Some(response) => {
+                    self.set_health(&node.url, true);
+                    return Some(response);
+                }
+                None => self.set_health(&node.url, false),
+            }
+        }
+        None
+    }
+
+    fn set_health(&self, url: &Url, healthy: bool) {
+        let mut nodes = self.inner.nodes.write().expect("nodes lock poisoned");
+        if let Some(node) = nodes.iter_mut().find(|node| &node.url == url) {
+            if node.healthy != healthy {
+                if healthy {
+                    info!("Elasticsearch node {} is back", url);
+                } else {
+                    warn!("Elasticsearch node {} is unhealthy", url);
+                }
+            }
+            node.healthy = healthy;
+        }
+    }
+
+    // Replaces the nodes with the ones discovered in the cluster, keeping the health of the
+    // nodes we already know about.
+    fn set_nodes(&self, urls: Vec<Url>) {
+        let mut nodes = self.inner.nodes.write().expect("nodes lock poisoned");
+        let updated: Vec<Node> = urls
+            .into_iter()
+            .map(|url| {
+                let healthy = nodes
+                    .iter()
+                    .find(|node| node.url == url)
+                    .map_or(true, |node| node.healthy);
+                Node { url, healthy }
+            })
+            .collect();
+        if updated != *nodes {
+            info!(
+                "Elasticsearch nodes: {}",
+                updated
+                    .iter()
+                    .map(|node| node.url.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            *nodes = updated;
+        }
+    }
+
+    /// Spawns a task which checks the health of the nodes, and discovers them if sniffing
+    /// is enabled. The task stops when the pool is dropped.
+    pub fn spawn_monitoring(&self, config: ElasticsearchStorageConfig) {
+        let weak: Weak<Inner> = Arc::downgrade(&self.inner);
+
+        tokio::spawn(async move {
+            let mut last_sniff = tokio::time::Instant::now();
+            loop {
+                tokio::time::sleep(config.nodes.health_check_interval).await;
+
+                let pool = match weak.upgrade() {
+                    Some(inner) => MultiNodeConnectionPool { inner },
+                    None => break,
+                };
+
+                if let Some(sniff_interval) = config.nodes.sniff_interval {
+                    if last_sniff.elapsed() >= sniff_interval {
+                        last_sniff = tokio::time::Instant::now();
+                        match sniff(&pool, &config).await {
+                            Ok(urls) if !urls.is_empty() => pool.set_nodes(urls),
+                            Ok(_) => warn!("Sniffing found no Elasticsearch node"),
+                            Err(err) => warn!("Could not sniff Elasticsearch nodes: {}", err),
+                        }
+                    }
+                }
+
+                for node in pool.nodes() {
+                    let healthy = check_health(&node.url, &config).await;
+                    pool.set_health(&node.url, healthy);
+                }
+            }
+        });
+    }
+}
+
+impl ConnectionPool for MultiNodeConnectionPool {
+    fn next(&self) -> Connection {
+        Connection::new(self.next_url())
+    }
+}
+
+// A node is healthy if it answers the root endpoint successfully.
+async fn check_health(url: &Url, config: &ElasticsearchStorageConfig) -> bool {
+    root(url, config).await.is_some()
+}
+
+// The successful response of a node to the root endpoint, if any.
+async fn root(url: &Url, config: &ElasticsearchStorageConfig) -> Option<Response> {
+    let transport = transport_builder(SingleNodeConnectionPool::new(url.clone()), config)
+        .ok()?
+        .build()
+        .ok()?;
+
+    transport
+        .send::<String, String>(
+            Method::Get,
+            "/",
+            HeaderMap::new(),
+            None,
+            None,
+            Some(config.timeout),
+        )
+        .await
+        .ok()
+        .filter(|response| response.status_code().is_success())
+}
+
+// Asks the cluster for the http addresses of its nodes.
+async fn sniff(
+    pool: &MultiNodeConnectionPool,
+    config: &ElasticsearchStorageConfig,
+) -> Result<Vec<Url>, Box<dyn std::error::Error + Send + Sync>> {
+    let transport: Transport = transport_builder(pool.clone(), config)?.build()?;
+
+    let json = transport
+        .send::<String, String>(
+            Method::Get,
+            "/_nodes/http",
+            HeaderMap::new(),
+            None,
+            None,
+            Some(config.timeout),
+        )
+        .await?
+        .json::<Value>()
+        .await?;
+
+    Ok(publish_urls(&json, config.url.scheme()))
+}
+
+// Extracts the urls of the nodes from the response of '_nodes/http'. The publish address
+// is either 'ip:port' or 'hostname/ip:port'.
+fn publish_urls(json: &Value, scheme: &str) -> Vec<Url> {
+    let mut urls: Vec<Url> = json
+        .get("nodes")
+        .and_then(Value::as_object)
+        .map(|nodes| {
+            nodes
+                .values()
+                .filter_map(|node| node.pointer("/http/publish_address")?.as_str())
+                .filter_map(|address| {
+                    let address = address.rsplit('/').next()?;
+                    Url::parse(&format!("{}://{}", scheme, address)).ok()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn should_skip_unhealthy_nodes() {
+        let a = Url::parse("http://es-a:9200").unwrap();
+        let b = Url::parse("http://es-b:9200").unwrap();
+        let pool = MultiNodeConnectionPool::new(vec![a.clone(), b.clone()]);
+
+        pool.set_health(&a, false);
+        for _ in 0..3 {
+            assert_eq!(pool.next_url(), b);
+        }
+
+        // When no node is healthy, we still try them all.
+        pool.set_health(&b, false);
+        let urls: Vec<Url> = (0..2).map(|_| pool.next_url()).collect();
+        assert!(urls.contains(&a) && urls.contains(&b));
+    }
+
+    #[test]
+    fn should_extract_publish_addresses() {
+        let json = json!({
+            "nodes": {
+                "n1": { "http": { "publish_address": "10.0.0.1:9200" } },
+                "n2": { "http": { "publish_address": "es-2/10.0.0.2:9200" } },
+                "n3": {}
+            }
+        });
+
+        assert_eq!(
+            publish_urls(&json, "https"),
+            vec![
+                Url::parse("https://10.0.0.1:9200").unwrap(),
+                Url::parse("https://10.0.0.2:9200").unwrap(),
+            ]
+        );
+    }
+}
@@ -22,6 +22,20 @@ impl Search for ElasticsearchStorage {
         .await
         .map_err(|err| SearchError::DocumentRetrievalError { source: err.into() })
     }
+
+    async fn search_scored_documents<D: DeserializeOwned + Send + Sync + 'static>(
+        &self,
+        parameters: SearchParameters,
+    ) -> Result<Vec<(f64, D)>, SearchError> {
+        self.search_scored_documents(
+            parameters.es_indices_to_search_in,
+            parameters.query,
+            parameters.result_limit,
+            parameters.timeout,
+        )
+        .await
+        .map_err(|err| SearchError::DocumentRetrievalError { source: err.into() })
+    }
 }
 
 #[async_trait]
@@ -1,9 +1,12 @@
 use async_trait::async_trait;
 use elasticsearch::{
+    auth::{ClientCertificate, Credentials},
+    cert::{Certificate, CertificateValidation},
     http::{
         headers::HeaderMap,
         transport::{
-            BuildError as TransportBuilderError, SingleNodeConnectionPool, TransportBuilder,
+            BuildError as TransportBuilderError, ConnectionPool, SingleNodeConnectionPool,
+            TransportBuilder,
         },
         Method,
     },
@@ -12,9 +15,16 @@ use elasticsearch::{
 use semver::{Version, VersionReq};
 use serde_json::Value;
 use snafu::{ResultExt, Snafu};
+use std::{
+    fmt::Debug,
+    path::{Path, PathBuf},
+};
 use url::Url;
 
-use super::{ElasticsearchStorage, ElasticsearchStorageConfig};
+use super::{
+    pool::MultiNodeConnectionPool, ElasticsearchStorage, ElasticsearchStorageAuthConfig,
+    ElasticsearchStorageConfig,
+};
 use crate::domain::ports::secondary::remote::{Error as RemoteError, Remote};
 
 #[derive(Debug, Snafu)]
@@ -50,6 +60,24 @@ pub enum Error {
     #[snafu(display("JSON Deserialization Invalid: {} {:?}", details, json))]
     JsonDeserializationInvalid { details: String, json: Value },
 
+    /// Certificate or key file which could not be read
+    #[snafu(display("Cannot read {}: {}", path.display(), source))]
+    InvalidCertificateFile {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    /// Certificate which could not be parsed
+    #[snafu(display("Invalid certificate {}: {}", path.display(), source))]
+    InvalidCertificate {
+        path: PathBuf,
+        source: elasticsearch::Error,
+    },
+
+    /// Inconsistent authentication or TLS configuration
+    #[snafu(display("Invalid Elasticsearch security configuration: {}", msg))]
+    InvalidSecurityConfiguration { msg: String },
+
     /// Invalid Version Requirements
     #[snafu(display("Invalid Version Requirement Specification {}: {}", details, source))]
     VersionRequirementInvalid {
@@ -83,22 +111,66 @@ impl Remote for SingleNodeConnectionPool {
     /// }
     ///
     /// ```
+    ///
+    /// If several nodes are configured (`nodes.urls`), or if sniffing is enabled, the client
+    /// is connected to a `MultiNodeConnectionPool` built from the configuration instead.
     async fn conn(self, config: Self::Config) -> Result<Self::Conn, RemoteError> {
-        let version_req = VersionReq::parse(&config.version_req)
-            .context(VersionRequirementInvalidSnafu {
-                details: &config.version_req,
-            })
-            .map_err(|err| RemoteError::Connection {
-                source: Box::new(err),
-            })?;
-        let transport = TransportBuilder::new(self)
-            .build()
-            .context(ElasticsearchTransportSnafu)
-            .map_err(|err| RemoteError::Connection {
-                source: Box::new(err),
-            })?;
+        if config.nodes.is_multi_node() {
+            return MultiNodeConnectionPool::from_config(&config)
+                .conn(config)
+                .await;
+        }
+        connect(self, config, None).await
+    }
+}
 
-        let response = transport
+#[async_trait]
+impl Remote for MultiNodeConnectionPool {
+    type Conn = ElasticsearchStorage;
+    type Config = ElasticsearchStorageConfig;
+
+    /// Returns an Elasticsearch client, which sends requests to the healthy nodes of the pool.
+    ///
+    /// The health of the nodes is monitored in the background.
+    async fn conn(self, config: Self::Config) -> Result<Self::Conn, RemoteError> {
+        connect(self.clone(), config, Some(self)).await
+    }
+}
+
+async fn connect<P>(
+    pool: P,
+    config: ElasticsearchStorageConfig,
+    multi_node_pool: Option<MultiNodeConnectionPool>,
+) -> Result<ElasticsearchStorage, RemoteError>
+where
+    P: ConnectionPool + Debug + Clone + Send + 'static,
+{
+    let version_req = VersionReq::parse(&config.version_req)
+        .context(VersionRequirementInvalidSnafu {
+            details: &config.version_req,
+        })
+        .map_err(|err| RemoteError::Connection {
+            source: Box::new(err),
+        })?;
+    let transport = transport_builder(pool, &config)
+        .map_err(|err| RemoteError::Connection {
+            source: Box::new(err),
+        })?
+        .build()
+        .context(ElasticsearchTransportSnafu)
+        .map_err(|err| RemoteError::Connection {
+            source: Box::new(err),
+        })?;
+
+    // With several nodes, the version is asked to the first one which answers, so that the
+    // connection does not depend on a single node.
+    let probed = match &multi_node_pool {
+        Some(pool) => pool.probe(&config).await,
+        None => None,
+    };
+    let response = match probed {
+        Some(response) => response,
+        None => transport
             .send::<String, String>(
                 Method::Get,
                 "/",
@@ -111,95 +183,164 @@ impl Remote for SingleNodeConnectionPool {
             .context(ElasticsearchConnectionSnafu)
             .map_err(|err| RemoteError::Connection {
                 source: Box::new(err),
-            })?;
+            })?,
+    };
 
-        if response.status_code().is_success() {
-            // Response similar to:
-            // {
-            //   "index1": {
-            //      "aliases": {
-            //         "alias1": {},
-            //         "alias2": {}
-            //      }
-            //   },
-            //   "index2": {
-            //      "aliases": {
-            //         "alias3": {}
-            //      }
-            //   }
-            // }
-            let json = response
-                .json::<Value>()
-                .await
-                .context(JsonDeserializationSnafu)
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?;
-            let version_number = json
-                .as_object()
-                .ok_or(Error::JsonDeserializationInvalid {
-                    details: String::from("expected JSON object"),
-                    json: json.clone(),
-                })
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?
-                .get("version")
-                .ok_or(Error::JsonDeserializationInvalid {
-                    details: String::from("expected 'version'"),
-                    json: json.clone(),
-                })
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?
-                .as_object()
-                .ok_or(Error::JsonDeserializationInvalid {
-                    details: String::from("expected JSON object"),
-                    json: json.clone(),
-                })
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?
-                .get("number")
-                .ok_or(Error::JsonDeserializationInvalid {
-                    details: String::from("expected 'version.number'"),
-                    json: json.clone(),
-                })
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?
-                .as_str()
-                .ok_or(Error::JsonDeserializationInvalid {
-                    details: String::from("expected JSON string"),
-                    json: json.clone(),
-                })
-                .map_err(|err| RemoteError::Connection {
-                    source: Box::new(err),
-                })?;
-            let version = Version::parse(version_number).unwrap();
-            if !version_req.matches(&version) {
-                Err(RemoteError::Connection {
-                    source: Box::new(Error::ElasticsearchException {
-                        msg: format!(
-                            "Elasticsearch Invalid version: Expected '{}', got '{}'",
-                            version_req, version
-                        ),
-                    }),
-                })
-            } else {
-                let client = Elasticsearch::new(transport);
-                Ok(ElasticsearchStorage { client, config })
-            }
-        } else {
+    if response.status_code().is_success() {
+        // Response similar to:
+        // {
+        //   "index1": {
+        //      "aliases": {
+        //         "alias1": {},
+        //         "alias2": {}
+        //      }
+        //   },
+        //   "index2": {
+        //      "aliases": {
+        //         "alias3": {}
+        //      }
+        //   }
+        // }
+        let json = response
+            .json::<Value>()
+            .await
+            .context(JsonDeserializationSnafu)
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?;
+        let version_number = json
+            .as_object()
+            .ok_or(Error::JsonDeserializationInvalid {
+                details: String::from("expected JSON object"),
+                json: json.clone(),
+            })
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?
+            .get("version")
+            .ok_or(Error::JsonDeserializationInvalid {
+                details: String::from("expected 'version'"),
+                json: json.clone(),
+            })
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?
+            .as_object()
+            .ok_or(Error::JsonDeserializationInvalid {
+                details: String::from("expected JSON object"),
+                json: json.clone(),
+            })
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?
+            .get("number")
+            .ok_or(Error::JsonDeserializationInvalid {
+                details: String::from("expected 'version.number'"),
+                json: json.clone(),
+            })
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?
+            .as_str()
+            .ok_or(Error::JsonDeserializationInvalid {
+                details: String::from("expected JSON string"),
+                json: json.clone(),
+            })
+            .map_err(|err| RemoteError::Connection {
+                source: Box::new(err),
+            })?;
+        let version = Version::parse(version_number).unwrap();
+        if !version_req.matches(&version) {
             Err(RemoteError::Connection {
                 source: Box::new(Error::ElasticsearchException {
-                    msg: String::from("Elasticsearch Response Error"),
+                    msg: format!(
+                        "Elasticsearch Invalid version: Expected '{}', got '{}'",
+                        version_req, version
+                    ),
                 }),
             })
+        } else {
+            let client = Elasticsearch::new(transport);
+            if let Some(pool) = &multi_node_pool {
+                pool.spawn_monitoring(config.clone());
+            }
+            Ok(ElasticsearchStorage {
+                client,
+                config,
+                pool: multi_node_pool,
+            })
         }
+    } else {
+        Err(RemoteError::Connection {
+            source: Box::new(Error::ElasticsearchException {
+                msg: String::from("Elasticsearch Response Error"),
+            }),
+        })
     }
 }
 
+/// Returns a transport builder with the credentials and the certificate validation
+/// found in the configuration.
+pub(super) fn transport_builder<P>(
+    pool: P,
+    config: &ElasticsearchStorageConfig,
+) -> Result<TransportBuilder, Error>
+where
+    P: ConnectionPool + Debug + Clone + Send + 'static,
+{
+    let mut builder = TransportBuilder::new(pool);
+
+    let credentials = match (&config.auth, &config.tls.client_certificate) {
+        (Some(_), Some(_)) => {
+            return Err(Error::InvalidSecurityConfiguration {
+                msg: String::from("auth and tls.client_certificate cannot be used together"),
+            })
+        }
+        (Some(ElasticsearchStorageAuthConfig::Basic { username, password }), None) => {
+            Some(Credentials::Basic(username.clone(), password.clone()))
+        }
+        (Some(ElasticsearchStorageAuthConfig::ApiKey { id, key }), None) => {
+            Some(Credentials::ApiKey(id.clone(), key.clone()))
+        }
+        (Some(ElasticsearchStorageAuthConfig::Bearer { token }), None) => {
+            Some(Credentials::Bearer(token.clone()))
+        }
+        (None, Some(path)) => Some(Credentials::Certificate(ClientCertificate::Pkcs12(
+            read_file(path)?,
+            config.tls.client_certificate_password.clone(),
+        ))),
+        (None, None) => None,
+    };
+
+    if let Some(credentials) = credentials {
+        builder = builder.auth(credentials);
+    }
+
+    let validation = match (&config.tls.ca_bundle, config.tls.verify_hostname) {
+        (Some(path), verify_hostname) => {
+            let certificate = Certificate::from_pem(&read_file(path)?)
+                .context(InvalidCertificateSnafu { path })?;
+            if verify_hostname {
+                CertificateValidation::Full(certificate)
+            } else {
+                CertificateValidation::Certificate(certificate)
+            }
+        }
+        (None, true) => CertificateValidation::Default,
+        (None, false) => {
+            return Err(Error::InvalidSecurityConfiguration {
+                msg: String::from("tls.verify_hostname = false requires a tls.ca_bundle"),
+            })
+        }
+    };
+
+    Ok(builder.cert_validation(validation))
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).context(InvalidCertificateFileSnafu { path })
+}
+
 /// Opens a connection to elasticsearch given a url
 pub fn connection_pool_url(url: &Url) -> SingleNodeConnectionPool {
     SingleNodeConnectionPool::new(url.clone())
@@ -2,15 +2,19 @@ use async_trait::async_trait;
 
 use super::ElasticsearchStorage;
 use crate::domain::{
-    model::status::StorageStatus,
+    model::status::{StorageNode, StorageStatus},
     ports::secondary::status::{Error as StatusError, Status},
 };
 
+// The index templates of mimir, see 'config/elasticsearch/templates/indices'.
+const TEMPLATES_NAME: &str = "mimir-*";
+
 #[async_trait]
 impl Status for ElasticsearchStorage {
     /// Returns the status of the Elasticsearch Backend
     ///
-    /// The status is a combination of the cluster's health, and its version.
+    /// The status is a combination of the cluster's health, its version, the nodes
+    /// the client sends its requests to, the indices of documents and the index templates.
     ///
     /// # Example
     ///
@@ -43,10 +47,39 @@ impl Status for ElasticsearchStorage {
                 .map_err(|err| StatusError::VersionRetrievalError {
                     source: Box::new(err),
                 })?;
+        let indices =
+            self.list_indices()
+                .await
+                .map_err(|err| StatusError::IndicesRetrievalError {
+                    source: Box::new(err),
+                })?;
+        let templates = self.index_templates(TEMPLATES_NAME).await.map_err(|err| {
+            StatusError::TemplatesRetrievalError {
+                source: Box::new(err),
+            }
+        })?;
+
+        let nodes = match &self.pool {
+            Some(pool) => pool
+                .nodes()
+                .into_iter()
+                .map(|node| StorageNode {
+                    url: node.url.to_string(),
+                    healthy: node.healthy,
+                })
+                .collect(),
+            None => vec![StorageNode {
+                url: self.config.url.to_string(),
+                healthy: true,
+            }],
+        };
 
         Ok(StorageStatus {
             health: cluster_health,
             version: cluster_version,
+            nodes,
+            indices,
+            templates,
         })
     }
 }
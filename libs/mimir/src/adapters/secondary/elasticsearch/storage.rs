@@ -239,6 +239,40 @@ impl<'s> Storage<'s> for ElasticsearchStorage {
                     }
                 })
             }
+            "update index analysis" => {
+                let index = config.get_str("elasticsearch.index").map_err(|err| {
+                    StorageError::IndexSettingsUpdateError {
+                        index: String::from("NA"),
+                        source: Box::new(err),
+                    }
+                })?;
+                let analysis = config
+                    .get::<serde_json::Value>("elasticsearch.analysis")
+                    .map_err(|err| StorageError::IndexSettingsUpdateError {
+                        index: index.clone(),
+                        source: Box::new(err),
+                    })?;
+                self.update_index_analysis(index.clone(), analysis)
+                    .await
+                    .map_err(|err| StorageError::IndexSettingsUpdateError {
+                        index,
+                        source: Box::new(err),
+                    })
+            }
+            "reload search analyzers" => {
+                let index = config.get_str("elasticsearch.index").map_err(|err| {
+                    StorageError::IndexSettingsUpdateError {
+                        index: String::from("NA"),
+                        source: Box::new(err),
+                    }
+                })?;
+                self.reload_search_analyzers(index.clone())
+                    .await
+                    .map_err(|err| StorageError::IndexSettingsUpdateError {
+                        index,
+                        source: Box::new(err),
+                    })
+            }
             _ => Err(StorageError::UnrecognizedDirective { details: directive }),
         }
     }
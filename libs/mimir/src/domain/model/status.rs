@@ -17,10 +17,37 @@ impl fmt::Display for StorageHealth {
 
 pub type Version = String;
 
+#[derive(Debug)]
+pub struct StorageNode {
+    pub url: String,
+    pub healthy: bool,
+}
+
+/// An index of documents, with the aliases it is published under.
+#[derive(Debug)]
+pub struct StorageIndex {
+    pub name: String,
+    pub doc_type: String,
+    pub dataset: String,
+    pub docs_count: u64,
+    /// Date of the indexing of the documents, unknown for an empty index.
+    pub indexed_at: Option<String>,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct StorageTemplate {
+    pub name: String,
+    pub version: Option<u64>,
+}
+
 #[derive(Debug)]
 pub struct StorageStatus {
     pub health: StorageHealth,
     pub version: Version,
+    pub nodes: Vec<StorageNode>,
+    pub indices: Vec<StorageIndex>,
+    pub templates: Vec<StorageTemplate>,
 }
 
 #[derive(Debug)]
@@ -21,6 +21,16 @@ pub trait SearchDocuments {
         result_limit: i64,
         timeout: Option<Duration>,
     ) -> Result<Vec<D>, ModelError>;
+
+    /// Same as search_documents, but each document comes with its relevance score, so that
+    /// results coming from different storages can be merged.
+    async fn search_scored_documents<D: DeserializeOwned + Send + Sync + 'static>(
+        &self,
+        es_indices_to_search_in: Vec<String>,
+        query: Query,
+        result_limit: i64,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(f64, D)>, ModelError>;
 }
 
 #[async_trait]
@@ -44,4 +54,21 @@ where
         .await
         .map_err(|err| ModelError::DocumentRetrievalError { source: err.into() })
     }
+
+    async fn search_scored_documents<D: DeserializeOwned + Send + Sync + 'static>(
+        &self,
+        es_indices_to_search_in: Vec<String>,
+        query: Query,
+        result_limit: i64,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<(f64, D)>, ModelError> {
+        self.search_scored_documents(Parameters {
+            es_indices_to_search_in,
+            query,
+            result_limit,
+            timeout,
+        })
+        .await
+        .map_err(|err| ModelError::DocumentRetrievalError { source: err.into() })
+    }
 }
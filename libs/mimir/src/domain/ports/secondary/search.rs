@@ -31,4 +31,10 @@ pub trait Search {
         &self,
         parameters: Parameters,
     ) -> Result<Vec<D>, Error>;
+
+    /// Same as search_documents, but each document comes with its relevance score.
+    async fn search_scored_documents<D: DeserializeOwned + Send + Sync + 'static>(
+        &self,
+        parameters: Parameters,
+    ) -> Result<Vec<(f64, D)>, Error>;
 }
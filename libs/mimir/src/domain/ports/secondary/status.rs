@@ -9,6 +9,10 @@ pub enum Error {
     HealthRetrievalError { source: Box<dyn std::error::Error> },
     #[snafu(display("Version Retrieval Error: {}", source))]
     VersionRetrievalError { source: Box<dyn std::error::Error> },
+    #[snafu(display("Indices Retrieval Error: {}", source))]
+    IndicesRetrievalError { source: Box<dyn std::error::Error> },
+    #[snafu(display("Templates Retrieval Error: {}", source))]
+    TemplatesRetrievalError { source: Box<dyn std::error::Error> },
 }
 
 #[async_trait]
@@ -34,6 +38,12 @@ impl From<Error> for ModelError {
             Error::VersionRetrievalError { source } => {
                 ModelError::DocumentRetrievalError { source }
             }
+            Error::IndicesRetrievalError { source } => {
+                ModelError::DocumentRetrievalError { source }
+            }
+            Error::TemplatesRetrievalError { source } => {
+                ModelError::DocumentRetrievalError { source }
+            }
         }
     }
 }
@@ -40,6 +40,12 @@ pub enum Error {
         source: Box<dyn std::error::Error>,
     },
 
+    #[snafu(display("Index '{}' settings update error: {}", index, source))]
+    IndexSettingsUpdateError {
+        index: String,
+        source: Box<dyn std::error::Error>,
+    },
+
     #[snafu(display("Unrecognized directive: {}", details))]
     UnrecognizedDirective { details: String },
 }
@@ -1 +1,2 @@
 mod generate_and_update;
+mod synonyms;
@@ -0,0 +1,92 @@
+use elasticsearch::indices::IndicesCreateParts;
+use serde_json::{json, Value};
+use serial_test::serial;
+
+use crate::{
+    adapters::{
+        primary::common::{
+            dsl::{self, QueryType},
+            filters::Filters,
+            settings::QuerySettings,
+        },
+        secondary::elasticsearch::{remote, ElasticsearchStorage, ElasticsearchStorageConfig},
+    },
+    domain::{
+        model::query::Query,
+        ports::{primary::search_documents::SearchDocuments, secondary::remote::Remote},
+    },
+    utils::docker,
+};
+
+// An index created before the synonyms, without their analyzers.
+const INDEX: &str = "legacy_poi";
+
+async fn legacy_index() -> ElasticsearchStorage {
+    docker::initialize()
+        .await
+        .expect("elasticsearch docker initialization failed");
+
+    let client = remote::connection_test_pool()
+        .conn(ElasticsearchStorageConfig::default_testing())
+        .await
+        .expect("could not connect to Elasticsearch");
+
+    let label = json!({
+        "type": "text",
+        "fields": {
+            "prefix": { "type": "text" },
+            "ngram": { "type": "text" }
+        }
+    });
+    client
+        .client
+        .indices()
+        .create(IndicesCreateParts::Index(INDEX))
+        .body(json!({
+            "mappings": {
+                "properties": { "label": label, "full_label": label }
+            }
+        }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status_code())
+        .expect("could not create the index");
+
+    client
+}
+
+async fn search(
+    client: &ElasticsearchStorage,
+    settings: &QuerySettings,
+    query_type: QueryType,
+) -> Result<Vec<Value>, crate::domain::model::error::Error> {
+    let query = dsl::build_query(
+        "boulevard voltaire",
+        &Filters::default(),
+        "fr",
+        settings,
+        query_type,
+        None,
+    );
+    client
+        .search_documents(vec![INDEX.to_string()], Query::QueryDSL(query), 10, None)
+        .await
+}
+
+#[tokio::test]
+#[serial]
+async fn should_search_an_index_without_synonyms() {
+    let client = legacy_index().await;
+
+    // By default, the queries do not depend on the synonym analyzers.
+    let settings = QuerySettings::default();
+    assert!(search(&client, &settings, QueryType::PREFIX).await.is_ok());
+    assert!(search(&client, &settings, QueryType::FUZZY).await.is_ok());
+
+    // Once the synonyms of a language are enabled, the indices need their analyzers.
+    let settings = QuerySettings {
+        synonym_langs: vec![String::from("fr")],
+        ..settings
+    };
+    assert!(search(&client, &settings, QueryType::PREFIX).await.is_err());
+}
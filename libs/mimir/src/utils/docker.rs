@@ -24,7 +24,7 @@ use tokio::time::{sleep, Duration};
 
 use crate::{
     adapters::{
-        primary::templates,
+        primary::{synonyms, templates},
         secondary::elasticsearch::{remote, ElasticsearchStorageConfig},
     },
     domain::ports::secondary::remote::{Error as RemoteError, Remote},
@@ -82,6 +82,24 @@ pub async fn initialize_with_param(cleanup: bool) -> Result<(), Error> {
         .await
         .context(TemplateLoadingSnafu)?;
 
+    let path: PathBuf = [
+        env!("CARGO_MANIFEST_DIR"),
+        "..",
+        "..",
+        "config",
+        "elasticsearch",
+        "synonyms",
+    ]
+    .iter()
+    .collect();
+
+    let sets = synonyms::read_dir(&path)
+        .await
+        .context(SynonymsLoadingSnafu)?;
+    synonyms::import(&client, &sets, None)
+        .await
+        .context(SynonymsLoadingSnafu)?;
+
     let path: PathBuf = [
         env!("CARGO_MANIFEST_DIR"),
         "..",
@@ -127,6 +145,9 @@ pub enum Error {
 
     #[snafu(display("Template Loading Error: {}", source))]
     TemplateLoading { source: templates::Error },
+
+    #[snafu(display("Synonyms Loading Error: {}", source))]
+    SynonymsLoading { source: synonyms::Error },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
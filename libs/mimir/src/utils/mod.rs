@@ -1,3 +1,4 @@
 pub mod deserialize;
 pub mod docker;
 pub mod futures;
+pub mod telemetry;
@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+
+use crate::utils::deserialize::deserialize_duration;
+
+/// Export of the spans to an OpenTelemetry collector.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TracingSettings {
+    /// OTLP/HTTP endpoint of the collector, eg. 'http://localhost:4318/v1/traces'. The spans are
+    /// not exported without it.
+    pub otlp_endpoint: Option<Url>,
+    /// Name of the service in the traces. Defaults to the name of the executable.
+    pub service_name: Option<String>,
+    /// Ratio of the traces which are exported, between 0 and 1. Traces started by a caller
+    /// (with a 'traceparent' header) follow the decision of the caller.
+    pub sampling_ratio: f64,
+    /// Timeout on the export of a batch of spans.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,
+}
+
+impl Default for TracingSettings {
+    fn default() -> Self {
+        TracingSettings {
+            otlp_endpoint: None,
+            service_name: None,
+            sampling_ratio: 1.0,
+            timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl TracingSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.otlp_endpoint.is_some()
+    }
+}
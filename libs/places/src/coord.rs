@@ -66,6 +66,23 @@ impl serde::Serialize for Coord {
     }
 }
 
+impl schemars::JsonSchema for Coord {
+    fn schema_name() -> String {
+        String::from("Coord")
+    }
+
+    fn json_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::Schema {
+        // The schema of the custom serialization above.
+        #[allow(dead_code)]
+        #[derive(schemars::JsonSchema)]
+        struct LonLat {
+            lon: f64,
+            lat: f64,
+        }
+        LonLat::json_schema(gen)
+    }
+}
+
 impl From<Coord> for Geometry {
     fn from(coord: Coord) -> Geometry {
         Geometry::new(geojson::Value::Point(vec![coord.lon(), coord.lat()]))
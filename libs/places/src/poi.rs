@@ -1,5 +1,6 @@
 use common::document::{ContainerDocument, Document};
 use geojson::Geometry;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{collections::BTreeMap, sync::Arc};
 
@@ -23,6 +24,7 @@ pub struct Poi {
     pub weight: f64,
     pub zip_codes: Vec<String>,
     pub poi_type: PoiType,
+    #[serde(default)]
     pub properties: BTreeMap<String, String>,
     pub address: Option<Address>,
     #[serde(default)]
@@ -44,7 +46,7 @@ pub struct Poi {
     pub full_label_extra: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, JsonSchema)]
 pub struct PoiType {
     pub id: String,
     pub name: String,
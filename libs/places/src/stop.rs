@@ -1,4 +1,5 @@
 use geojson::Geometry;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{cmp::Ordering, collections::BTreeMap, sync::Arc};
 use tracing::warn;
@@ -9,33 +10,43 @@ use super::{context::Context, coord::Coord, Members};
 use crate::{admin::Admin, utils::normalize_id};
 use common::document::{ContainerDocument, Document};
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, JsonSchema,
+)]
 pub struct CommercialMode {
     pub id: String,
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, JsonSchema,
+)]
 pub struct PhysicalMode {
     pub id: String,
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, JsonSchema,
+)]
 pub struct Network {
     pub id: String,
     pub name: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Default, JsonSchema,
+)]
 pub struct Line {
     pub id: String,
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub code: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
     pub color: Option<Rgb>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
     pub text_color: Option<Rgb>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub commercial_mode: Option<CommercialMode>,
@@ -43,6 +54,7 @@ pub struct Line {
     pub network: Option<Network>,
     pub physical_modes: Vec<PhysicalMode>,
     #[serde(skip_serializing)]
+    #[schemars(skip)]
     pub sort_order: Option<u32>, // we do not serialise this field, it is only used to sort the Lines
 }
 
@@ -89,7 +101,9 @@ impl FromTransitModel<transit_model::objects::Line> for Line {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, JsonSchema,
+)]
 pub struct FeedPublisher {
     pub id: String,
     pub license: String,
@@ -97,7 +111,7 @@ pub struct FeedPublisher {
     pub url: String,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
 pub struct Comment {
     pub name: String,
 }
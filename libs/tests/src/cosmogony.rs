@@ -152,6 +152,7 @@ pub async fn index_admins(
             number_of_replicas: 0,
         },
         french_id_retrocompatibility,
+        None,
         client,
     )
     .await
@@ -121,7 +121,7 @@ pub async fn index_pois(
     .context(PoiOsmExtractionSnafu)?;
 
     let pois: Vec<Poi> = futures::stream::iter(pois)
-        .map(mimirsbrunn::osm_reader::poi::compute_weight)
+        .map(|poi| config.pois.weights.apply_to_poi(poi))
         .then(|poi| {
             mimirsbrunn::osm_reader::poi::add_address(client, poi, config.pois.max_distance_reverse)
         })
@@ -0,0 +1,394 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! An in-memory index of addresses and streets, used to attach the closest address to
+//! pois without issuing one reverse query to Elasticsearch per poi.
+
+use futures::stream::{StreamExt, TryStreamExt};
+use mimir::{
+    adapters::secondary::elasticsearch::ElasticsearchStorage,
+    domain::ports::primary::list_documents::ListDocuments,
+};
+use places::{addr::Addr, admin::Admin, coord::Coord, street::Street, Address};
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, HashMap},
+    iter::FromIterator,
+    path::PathBuf,
+    sync::Arc,
+};
+use tracing::info;
+
+use crate::{
+    addr_reader::{self, import_addresses_from_input_path},
+    admin_geofinder::AdminGeoFinder,
+    bano::Bano,
+    openaddresses::OpenAddress,
+    osm_reader::{self, street::StreetExclusion},
+};
+
+/// Mean radius of the earth, in meters.
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// Number of items processed by each task in `AddrGeoFinder::par_map`.
+const CHUNK_SIZE: usize = 1000;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Address Retrieval Error: {}", source))]
+    AddressRetrieval {
+        source: mimir::domain::model::error::Error,
+    },
+
+    #[snafu(display("Address Reader Error: {}", source))]
+    AddressReader { source: addr_reader::Error },
+
+    #[snafu(display("OSM PBF Reader Error: {}", source))]
+    OsmPbfReader { source: osm_reader::Error },
+
+    #[snafu(display("Street Extraction Error: {}", source))]
+    StreetExtraction { source: osm_reader::street::Error },
+}
+
+/// Where the addresses attached to pois are looked up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "lowercase")]
+pub enum AddressLookup {
+    /// One reverse query to Elasticsearch per poi.
+    Elasticsearch,
+    /// The addresses and streets indexed in Elasticsearch are loaded in memory.
+    Index,
+    /// The addresses are read in BANO files.
+    Bano { path: PathBuf },
+    /// The addresses are read in OpenAddresses files.
+    OpenAddresses {
+        path: PathBuf,
+        #[serde(default = "default_id_precision")]
+        id_precision: usize,
+    },
+    /// The streets are extracted from an OSM PBF file.
+    Osm {
+        path: PathBuf,
+        #[serde(default)]
+        exclusions: StreetExclusion,
+    },
+}
+
+impl Default for AddressLookup {
+    fn default() -> Self {
+        AddressLookup::Elasticsearch
+    }
+}
+
+fn default_id_precision() -> usize {
+    6
+}
+
+// This is the object stored in the RTree: the address, with its position on the unit
+// sphere scaled to the earth radius. The euclidean distance between two such points (the
+// chord) grows with the distance along the earth surface, so the nearest neighbor in the
+// RTree is the closest address, wherever it is on the globe.
+struct LocatedAddress {
+    point: [f64; 3],
+    address: Address,
+}
+
+impl RTreeObject for LocatedAddress {
+    type Envelope = AABB<[f64; 3]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.point)
+    }
+}
+
+impl PointDistance for LocatedAddress {
+    fn distance_2(&self, point: &[f64; 3]) -> f64 {
+        self.point.distance_2(point)
+    }
+}
+
+fn to_cartesian(coord: &Coord) -> [f64; 3] {
+    let (lat, lon) = (coord.lat().to_radians(), coord.lon().to_radians());
+    [
+        EARTH_RADIUS * lat.cos() * lon.cos(),
+        EARTH_RADIUS * lat.cos() * lon.sin(),
+        EARTH_RADIUS * lat.sin(),
+    ]
+}
+
+// Converts a chord length into the length of the corresponding arc on the earth surface.
+fn chord_to_arc(chord: f64) -> f64 {
+    2.0 * EARTH_RADIUS * (chord / (2.0 * EARTH_RADIUS)).min(1.0).asin()
+}
+
+fn address_id(address: &Address) -> &str {
+    match address {
+        Address::Street(street) => &street.id,
+        Address::Addr(addr) => &addr.id,
+    }
+}
+
+fn address_coord(address: &Address) -> &Coord {
+    match address {
+        Address::Street(street) => &street.coord,
+        Address::Addr(addr) => &addr.coord,
+    }
+}
+
+/// Finds the closest address (or street) of a given location, in memory.
+pub struct AddrGeoFinder {
+    rtree: RTree<LocatedAddress>,
+}
+
+impl AddrGeoFinder {
+    /// Builds the AddrGeoFinder from the given source, or returns None if the addresses
+    /// are to be looked up in Elasticsearch.
+    pub async fn build(
+        lookup: &AddressLookup,
+        admins_geofinder: Arc<AdminGeoFinder>,
+        client: &ElasticsearchStorage,
+    ) -> Result<Option<AddrGeoFinder>, Error> {
+        let addresses = match lookup {
+            AddressLookup::Elasticsearch => return Ok(None),
+            AddressLookup::Index => fetch_addresses_from_elasticsearch(client).await?,
+            AddressLookup::Bano { path } => {
+                let admins_by_insee: BTreeMap<String, Arc<Admin>> = admins_geofinder
+                    .iter()
+                    .filter(|a| !a.insee.is_empty())
+                    .map(|a| (a.insee.clone(), a))
+                    .collect();
+                let into_addr = move |b: Bano| b.into_addr(&admins_by_insee, &admins_geofinder);
+                read_addresses(path.clone(), false, into_addr).await?
+            }
+            AddressLookup::OpenAddresses { path, id_precision } => {
+                let id_precision = *id_precision;
+                let into_addr = move |a: OpenAddress| a.into_addr(&admins_geofinder, id_precision);
+                read_addresses(path.clone(), true, into_addr).await?
+            }
+            AddressLookup::Osm { path, exclusions } => {
+                let mut osm_reader =
+                    osm_reader::make_osm_reader(path).context(OsmPbfReaderSnafu)?;
+                osm_reader::street::streets(
+                    &mut osm_reader,
+                    &admins_geofinder,
+                    exclusions,
+                    #[cfg(feature = "db-storage")]
+                    None,
+                )
+                .context(StreetExtractionSnafu)?
+                .into_iter()
+                .map(Address::Street)
+                .collect()
+            }
+        };
+
+        info!("{} addresses loaded in the AddrGeoFinder", addresses.len());
+
+        Ok(Some(addresses.into_iter().collect()))
+    }
+
+    /// Returns the closest address of the given location, if it is not further than
+    /// `max_distance` meters. Addresses at the same distance are ordered by id, so that
+    /// the result does not depend on the order in which the addresses were loaded.
+    pub fn get(&self, coord: &Coord, max_distance: f64) -> Option<Address> {
+        let point = to_cartesian(coord);
+        let mut neighbors = self.rtree.nearest_neighbor_iter(&point);
+        let closest = neighbors.next()?;
+        let distance_2 = closest.distance_2(&point);
+
+        if chord_to_arc(distance_2.sqrt()) > max_distance {
+            return None;
+        }
+
+        std::iter::once(closest)
+            .chain(neighbors.take_while(|neighbor| neighbor.distance_2(&point) <= distance_2))
+            .min_by(|a, b| address_id(&a.address).cmp(address_id(&b.address)))
+            .map(|located| located.address.clone())
+    }
+
+    /// Applies `f` to all the items, spreading the work over the blocking threads of the tokio
+    /// runtime so that it does not hold up the async workers, and returns the results in the
+    /// order of the items.
+    pub async fn par_map<T, B, F>(self: &Arc<Self>, items: Vec<T>, f: F) -> Vec<B>
+    where
+        T: Send + 'static,
+        B: Send + 'static,
+        F: Fn(&AddrGeoFinder, T) -> B + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+
+        futures::stream::iter(items)
+            .chunks(CHUNK_SIZE)
+            .map(|chunk| {
+                let geofinder = self.clone();
+                let f = f.clone();
+                tokio::task::spawn_blocking(move || {
+                    chunk
+                        .into_iter()
+                        .map(|item| f(&geofinder, item))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .buffered(num_cpus::get())
+            .flat_map(|chunk| futures::stream::iter(chunk.expect("tokio task panicked")))
+            .collect()
+            .await
+    }
+}
+
+impl FromIterator<Address> for AddrGeoFinder {
+    fn from_iter<I: IntoIterator<Item = Address>>(iter: I) -> Self {
+        let located = iter
+            .into_iter()
+            .map(|address| LocatedAddress {
+                point: to_cartesian(address_coord(&address)),
+                address,
+            })
+            .collect();
+
+        AddrGeoFinder {
+            rtree: RTree::bulk_load(located),
+        }
+    }
+}
+
+// Reads the addresses in BANO or OpenAddresses files.
+async fn read_addresses<F, T>(
+    path: PathBuf,
+    has_headers: bool,
+    into_addr: F,
+) -> Result<Vec<Address>, Error>
+where
+    F: Fn(T) -> Result<Addr, crate::error::Error> + Send + Sync + 'static,
+    T: serde::de::DeserializeOwned + Send + Sync + 'static,
+{
+    let addresses = import_addresses_from_input_path(path, has_headers, into_addr)
+        .await
+        .context(AddressReaderSnafu)?
+        .map(Address::Addr)
+        .collect()
+        .await;
+
+    Ok(addresses)
+}
+
+// Loads all the addresses and streets indexed in Elasticsearch. The admins are shared between
+// the addresses, as they would otherwise be duplicated for each address.
+async fn fetch_addresses_from_elasticsearch(
+    client: &ElasticsearchStorage,
+) -> Result<Vec<Address>, Error> {
+    let streets: Vec<Street> = ListDocuments::<Street>::list_documents(client)
+        .await
+        .context(AddressRetrievalSnafu)?
+        .try_collect()
+        .await
+        .context(AddressRetrievalSnafu)?;
+
+    let addrs: Vec<Addr> = ListDocuments::<Addr>::list_documents(client)
+        .await
+        .context(AddressRetrievalSnafu)?
+        .try_collect()
+        .await
+        .context(AddressRetrievalSnafu)?;
+
+    let mut admins_cache: HashMap<String, Arc<Admin>> = HashMap::new();
+    let mut addresses = Vec::with_capacity(streets.len() + addrs.len());
+
+    for mut street in streets {
+        share_admins(&mut street.administrative_regions, &mut admins_cache);
+        addresses.push(Address::Street(street));
+    }
+
+    for mut addr in addrs {
+        share_admins(&mut addr.street.administrative_regions, &mut admins_cache);
+        addresses.push(Address::Addr(addr));
+    }
+
+    Ok(addresses)
+}
+
+fn share_admins(admins: &mut [Arc<Admin>], admins_cache: &mut HashMap<String, Arc<Admin>>) {
+    admins.iter_mut().for_each(|admin| {
+        if let Some(cached) = admins_cache.get(&admin.id) {
+            *admin = cached.clone();
+        } else {
+            admins_cache.insert(admin.id.clone(), admin.clone());
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn street(id: &str, lon: f64, lat: f64) -> Address {
+        Address::Street(Street {
+            id: id.to_string(),
+            coord: Coord::new(lon, lat).unwrap(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn should_find_the_closest_address_within_max_distance() {
+        let geofinder: AddrGeoFinder = vec![
+            street("street:far", 2.3600, 48.8600),
+            street("street:close", 2.3510, 48.8570),
+        ]
+        .into_iter()
+        .collect();
+
+        let coord = Coord::new(2.3500, 48.8566).unwrap();
+
+        let address = geofinder.get(&coord, 1000.0).unwrap();
+        assert_eq!(address_id(&address), "street:close");
+
+        // street:close is about 86m away.
+        assert!(geofinder.get(&coord, 50.0).is_none());
+    }
+
+    #[test]
+    fn should_break_ties_by_id() {
+        let geofinder: AddrGeoFinder = vec![
+            street("street:b", 2.3510, 48.8570),
+            street("street:a", 2.3510, 48.8570),
+            street("street:c", 2.3510, 48.8570),
+        ]
+        .into_iter()
+        .collect();
+
+        let coord = Coord::new(2.3500, 48.8566).unwrap();
+
+        let address = geofinder.get(&coord, 1000.0).unwrap();
+        assert_eq!(address_id(&address), "street:a");
+    }
+}
@@ -18,7 +18,7 @@ use tokio::{
 use tracing::{info_span, warn};
 use tracing_futures::Instrument;
 
-use crate::utils;
+use crate::utils::{self, metrics};
 use places::addr::Addr;
 
 #[derive(Debug, Snafu)]
@@ -54,8 +54,16 @@ where
     metadata(&path).await.context(InvalidPathSnafu)?;
     let into_addr = Arc::new(into_addr);
 
-    let recs = records_from_path(&path, has_headers)
-        .filter_map(|rec| future::ready(rec.map_err(|err| warn!("Invalid CSV: {}", err)).ok()));
+    let recs = records_from_path(&path, has_headers).filter_map(|rec| {
+        metrics::read("addr", 1);
+        future::ready(
+            rec.map_err(|err| {
+                warn!("Invalid CSV: {}", err);
+                metrics::rejected("addr", "invalid_csv");
+            })
+            .ok(),
+        )
+    });
 
     let stream = recs
         .chunks(1000)
@@ -67,7 +75,10 @@ where
                         .into_iter()
                         .filter_map(|rec| {
                             into_addr(rec)
-                                .map_err(|err| warn!("Invalid address has been ignored: {}", err))
+                                .map_err(|err| {
+                                    warn!("Invalid address has been ignored: {}", err);
+                                    metrics::rejected("addr", "invalid_address");
+                                })
                                 .ok()
                         })
                         .filter(|addr| {
@@ -77,7 +88,8 @@ where
                                 warn!(
                                     "Address {} has no street name and has been ignored.",
                                     addr.id
-                                )
+                                );
+                                metrics::rejected("addr", "no_street_name");
                             }
 
                             !empty_name
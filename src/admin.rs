@@ -45,6 +45,8 @@ use tracing::{info, warn};
 use crate::{
     osm_reader::{admin, osm_utils},
     settings::admin_settings::{AdminFromCosmogonyFile, AdminSettings},
+    utils::metrics,
+    wikidata::Wikidata,
 };
 use mimir::{
     adapters::secondary::elasticsearch::{self, ElasticsearchStorage},
@@ -223,6 +225,7 @@ pub async fn index_cosmogony(
     langs: Vec<String>,
     config: &ContainerConfig,
     french_id_retrocompatibility: bool,
+    wikidata: Option<Wikidata>,
     client: &ElasticsearchStorage,
 ) -> Result<(), Error> {
     let file_config = AdminFromCosmogonyFile {
@@ -230,7 +233,13 @@ pub async fn index_cosmogony(
         langs,
         french_id_retrocompatibility,
     };
-    let admins = read_admin_in_cosmogony_file(&file_config)?;
+    let admins = read_admin_in_cosmogony_file(&file_config)?.map(move |admin| {
+        metrics::read("admin", 1);
+        match &wikidata {
+            Some(wikidata) => wikidata.enrich_admin(admin),
+            None => admin,
+        }
+    });
     import_admins(client, config, futures::stream::iter(admins)).await
 }
 
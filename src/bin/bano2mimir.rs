@@ -33,7 +33,10 @@ use mimir::domain::ports::primary::generate_index::GenerateIndex;
 use mimirsbrunn::addr_reader::import_addresses_from_input_path;
 use mimirsbrunn::admin_geofinder::AdminGeoFinder;
 use mimirsbrunn::settings::admin_settings::AdminSettings;
-use mimirsbrunn::utils::template::update_templates;
+use mimirsbrunn::utils::{
+    metrics::{self, with_metrics},
+    template::update_templates,
+};
 use snafu::{ResultExt, Snafu};
 
 use mimir::{adapters::secondary::elasticsearch, domain::ports::secondary::remote::Remote};
@@ -68,10 +71,13 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
@@ -81,6 +87,7 @@ fn main() -> Result<(), Error> {
     }
 }
 
+#[tracing::instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -102,6 +109,7 @@ async fn run(
     // Lets say we're indexing a single bano department.... we don't need to retrieve
     // the admins for other regions!
     let into_addr = {
+        let _phase = metrics::phase("admins");
         let admin_settings = AdminSettings::build(&settings.admins);
         let admins_geofinder = AdminGeoFinder::build(&admin_settings, &client).await?;
 
@@ -111,9 +119,18 @@ async fn run(
             .map(|a| (a.insee.clone(), a))
             .collect();
 
-        move |b: Bano| b.into_addr(&admins_by_insee, &admins_geofinder)
+        let weights = settings.weights;
+
+        move |b: Bano| {
+            b.into_addr(&admins_by_insee, &admins_geofinder)
+                .map(|addr| match &weights {
+                    Some(weights) => weights.apply_to_addr(addr),
+                    None => addr,
+                })
+        }
     };
 
+    let _phase = metrics::phase("addresses");
     let addresses = import_addresses_from_input_path(opts.input, false, into_addr)
         .await
         .map_err(Box::new)?;
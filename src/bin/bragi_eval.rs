@@ -0,0 +1,338 @@
+use clap::Parser;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use mimir::{
+    adapters::{
+        primary::{
+            bragi::handlers::build_es_indices_to_search,
+            common::{
+                coord::Coord,
+                dsl::{build_query, QueryType},
+                fields::FieldSelection,
+                filters::Filters,
+                settings::{QueryProfiles, QuerySettings},
+            },
+        },
+        secondary::elasticsearch::{self, ElasticsearchStorage},
+    },
+    domain::{
+        model::query::Query,
+        ports::{primary::search_documents::SearchDocuments, secondary::remote::Remote},
+    },
+    utils::telemetry::TracingSettings,
+};
+use mimirsbrunn::{
+    eval::{self, CaseResult, Report},
+    settings::bragi_eval as settings,
+    tuning,
+};
+use serde_json::Value;
+use snafu::{ResultExt, Snafu};
+use std::fs;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Settings (Configuration or CLI) Error: {}", source))]
+    Settings { source: settings::Error },
+
+    #[snafu(display("Elasticsearch Connection Pool {}", source))]
+    ElasticsearchConnection {
+        source: mimir::domain::ports::secondary::remote::Error,
+    },
+
+    #[snafu(display("Golden Set Error: {}", source))]
+    GoldenSet { source: eval::Error },
+
+    #[snafu(display("Ranking Profile Error: {}", msg))]
+    Profile { msg: String },
+
+    #[snafu(display("Tuning Error: {}", source))]
+    Tuning { source: tuning::Error },
+
+    #[snafu(display("Tuning Error: {}", msg))]
+    TuningBackend { msg: String },
+
+    #[snafu(display("Search Error for '{}': {}", query, msg))]
+    Search { query: String, msg: String },
+
+    #[snafu(display("Could not write the results to {}: {}", path, source))]
+    Output {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Execution Error {}", source))]
+    Execution { source: Box<dyn std::error::Error> },
+}
+
+// Where the queries of the golden set are sent.
+enum Backend {
+    Bragi {
+        client: reqwest::Client,
+        url: String,
+    },
+    Elasticsearch {
+        client: ElasticsearchStorage,
+    },
+}
+
+fn main() -> Result<(), Error> {
+    let opts = settings::Opts::parse();
+    let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
+
+    match opts.cmd {
+        settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
+            None,
+            &TracingSettings::default(),
+            run(opts, settings),
+        )
+        .context(ExecutionSnafu),
+        settings::Command::Tune => mimirsbrunn::utils::launch::launch_with_runtime(
+            None,
+            &TracingSettings::default(),
+            tune(opts, settings),
+        )
+        .context(ExecutionSnafu),
+        settings::Command::Config => {
+            println!("{}", serde_json::to_string_pretty(&settings).unwrap());
+            Ok(())
+        }
+    }
+}
+
+async fn run(
+    opts: settings::Opts,
+    settings: settings::Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let cases = eval::read_golden_set(&opts.golden_set).context(GoldenSetSnafu)?;
+    tracing::info!("Evaluating {} queries", cases.len());
+
+    let query_settings = profile_settings(&settings)?;
+    let backend = match &settings.bragi_url {
+        Some(url) => Backend::Bragi {
+            client: reqwest::Client::builder()
+                .timeout(settings.timeout)
+                .build()?,
+            url: url.trim_end_matches('/').to_string(),
+        },
+        None => Backend::Elasticsearch {
+            client: connect(&settings).await?,
+        },
+    };
+
+    let report = evaluate(&backend, &cases, &settings, query_settings).await?;
+    println!("{}", report);
+
+    if let Some(baseline) = &opts.baseline {
+        let baseline = Report::read(baseline).context(GoldenSetSnafu)?;
+        println!("{}", report.diff(&baseline));
+    }
+
+    if let Some(output) = &opts.output {
+        fs::write(output, serde_json::to_string_pretty(&report)?).context(OutputSnafu {
+            path: output.display().to_string(),
+        })?;
+    }
+
+    Ok(())
+}
+
+async fn tune(
+    opts: settings::Opts,
+    settings: settings::Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // The query settings are only used when querying Elasticsearch directly.
+    if settings.bragi_url.is_some() {
+        return Err(Error::TuningBackend {
+            msg: "the tuning needs a direct access to Elasticsearch, remove 'bragi_url'"
+                .to_string(),
+        }
+        .into());
+    }
+
+    let cases = eval::read_golden_set(&opts.golden_set).context(GoldenSetSnafu)?;
+    tracing::info!(
+        "Tuning {} parameters on {} queries",
+        settings.tuning.parameters.len(),
+        cases.len()
+    );
+
+    let initial = profile_settings(&settings)?;
+    let backend = Backend::Elasticsearch {
+        client: connect(&settings).await?,
+    };
+
+    let tuning = {
+        let (backend, cases, settings) = (&backend, &cases, &settings);
+        tuning::tune(initial, &settings.tuning, move |query_settings| {
+            evaluate(backend, cases, settings, query_settings)
+        })
+        .await
+        .context(TuningSnafu)?
+    };
+
+    println!("{}", tuning.best_report);
+    println!("{}", tuning.best_report.diff(&tuning.initial_report));
+    tracing::info!("Ran the golden set {} times", tuning.evaluations);
+
+    let profile = tuning
+        .profile(&settings.tuning.profile_name)
+        .context(TuningSnafu)?;
+    match &opts.output {
+        Some(output) => fs::write(output, profile).context(OutputSnafu {
+            path: output.display().to_string(),
+        })?,
+        None => println!("{}", profile),
+    }
+
+    Ok(())
+}
+
+// The query settings of the selected ranking profile.
+fn profile_settings(settings: &settings::Settings) -> Result<QuerySettings, Error> {
+    let profiles =
+        QueryProfiles::new(settings.query.clone(), &settings.profiles).map_err(|err| {
+            Error::Profile {
+                msg: err.to_string(),
+            }
+        })?;
+    profiles
+        .get(settings.profile.as_deref())
+        .cloned()
+        .ok_or_else(|| Error::Profile {
+            msg: format!(
+                "unknown profile, expected one of: {}",
+                profiles.names().join(", ")
+            ),
+        })
+}
+
+async fn connect(settings: &settings::Settings) -> Result<ElasticsearchStorage, Error> {
+    elasticsearch::remote::connection_pool_url(&settings.elasticsearch.url)
+        .conn(settings.elasticsearch.clone())
+        .await
+        .context(ElasticsearchConnectionSnafu)
+}
+
+// Runs every query of the golden set.
+async fn evaluate(
+    backend: &Backend,
+    cases: &[eval::Case],
+    settings: &settings::Settings,
+    query_settings: QuerySettings,
+) -> Result<Report, Error> {
+    let query_settings = &query_settings;
+    let results: Vec<CaseResult> = stream::iter(cases.iter())
+        .map(|case| async move {
+            let found = backend.search(case, settings, query_settings).await?;
+            Ok::<_, Error>(CaseResult::new(case, found))
+        })
+        .buffered(settings.concurrency.max(1))
+        .try_collect()
+        .await?;
+
+    Ok(Report::new(results, &settings.cutoffs))
+}
+
+impl Backend {
+    // Returns the ids of the places found for the query of the case, in the order of the results.
+    async fn search(
+        &self,
+        case: &eval::Case,
+        settings: &settings::Settings,
+        query_settings: &QuerySettings,
+    ) -> Result<Vec<String>, Error> {
+        match self {
+            Backend::Bragi { client, url } => {
+                let mut params = vec![
+                    ("q", case.query.clone()),
+                    ("limit", settings.limit.to_string()),
+                    ("lang", settings.lang.clone()),
+                ];
+                if let (Some(lat), Some(lon)) = (case.lat, case.lon) {
+                    params.push(("lat", lat.to_string()));
+                    params.push(("lon", lon.to_string()));
+                }
+                if let Some(profile) = &settings.profile {
+                    params.push(("profile", profile.clone()));
+                }
+
+                let response: Value = client
+                    .get(format!("{}/api/v1/autocomplete", url))
+                    .query(&params)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|err| Error::Search {
+                        query: case.query.clone(),
+                        msg: err.to_string(),
+                    })?
+                    .json()
+                    .await
+                    .map_err(|err| Error::Search {
+                        query: case.query.clone(),
+                        msg: err.to_string(),
+                    })?;
+
+                Ok(response["features"]
+                    .as_array()
+                    .map(|features| {
+                        features
+                            .iter()
+                            .filter_map(|feature| {
+                                feature
+                                    .pointer("/properties/geocoding/id")
+                                    .and_then(Value::as_str)
+                                    .map(String::from)
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default())
+            }
+            Backend::Elasticsearch { client } => {
+                // Same as bragi's autocomplete: a prefix query, followed by a fuzzy query if
+                // nothing was found.
+                let filters = Filters {
+                    coord: case
+                        .lat
+                        .zip(case.lon)
+                        .map(|(lat, lon)| Coord::new(lat, lon)),
+                    limit: settings.limit,
+                    ..Default::default()
+                };
+                let indices = build_es_indices_to_search(&None, &None, &None);
+                let source = FieldSelection::default().source(false);
+
+                for query_type in [QueryType::PREFIX, QueryType::FUZZY] {
+                    let dsl = build_query(
+                        &case.query,
+                        &filters,
+                        &settings.lang,
+                        query_settings,
+                        query_type,
+                        Some(&source),
+                    );
+                    let places: Vec<Value> = client
+                        .search_documents(
+                            indices.clone(),
+                            Query::QueryDSL(dsl),
+                            settings.limit,
+                            Some(settings.timeout),
+                        )
+                        .await
+                        .map_err(|err| Error::Search {
+                            query: case.query.clone(),
+                            msg: err.to_string(),
+                        })?;
+
+                    if !places.is_empty() {
+                        return Ok(places
+                            .iter()
+                            .filter_map(|place| place["id"].as_str().map(String::from))
+                            .collect());
+                    }
+                }
+                Ok(vec![])
+            }
+        }
+    }
+}
@@ -32,7 +32,14 @@ use clap::Parser;
 use snafu::{ResultExt, Snafu};
 
 use mimir::{adapters::secondary::elasticsearch, domain::ports::secondary::remote::Remote};
-use mimirsbrunn::{settings::cosmogony2mimir as settings, utils::template::update_templates};
+use mimirsbrunn::{
+    settings::cosmogony2mimir as settings,
+    utils::{
+        metrics::{self, with_metrics},
+        template::update_templates,
+    },
+    wikidata::load_if_configured,
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -58,10 +65,13 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
@@ -71,6 +81,7 @@ fn main() -> Result<(), Error> {
     }
 }
 
+#[tracing::instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -92,13 +103,17 @@ async fn run(
         update_templates(&client, opts.config_dir).await?;
     }
 
+    let wikidata = load_if_configured(&settings.wikidata).await?;
+
     tracing::info!("Indexing cosmogony from {:?}", &opts.input);
+    let _phase = metrics::phase("admins");
 
     mimirsbrunn::admin::index_cosmogony(
         &opts.input,
         settings.langs,
         &settings.container,
         settings.french_id_retrocompatibility,
+        wikidata,
         &client,
     )
     .await
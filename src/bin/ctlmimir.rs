@@ -1,7 +1,15 @@
 use clap::Parser;
-use mimir::{adapters::secondary::elasticsearch, domain::ports::secondary::remote::Remote};
+use mimir::{
+    adapters::{
+        primary::synonyms::{self, SynonymSet},
+        secondary::elasticsearch::{self, ElasticsearchStorage},
+    },
+    domain::ports::secondary::remote::Remote,
+    utils::telemetry::TracingSettings,
+};
 use mimirsbrunn::{settings::ctlmimir as settings, utils::template::update_templates};
 use snafu::{ResultExt, Snafu};
+use std::path::PathBuf;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -13,6 +21,15 @@ pub enum Error {
         source: mimir::domain::ports::secondary::remote::Error,
     },
 
+    #[snafu(display("Synonyms Error: {}", source))]
+    Synonyms { source: synonyms::Error },
+
+    #[snafu(display(
+        "The synonyms are read from '{}' on the Elasticsearch nodes: edit the files there, then run 'synonyms reload'",
+        path
+    ))]
+    RemoteSynonyms { path: String },
+
     #[snafu(display("Execution Error {}", source))]
     Execution { source: Box<dyn std::error::Error> },
 }
@@ -24,6 +41,7 @@ fn main() -> Result<(), Error> {
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
+            &TracingSettings::default(),
             run(opts, settings),
         )
         .context(ExecutionSnafu),
@@ -31,6 +49,15 @@ fn main() -> Result<(), Error> {
             println!("{}", serde_json::to_string_pretty(&settings).unwrap());
             Ok(())
         }
+        settings::Command::Synonyms { ref cmd } => {
+            let dir = opts.config_dir.join("elasticsearch").join("synonyms");
+            mimirsbrunn::utils::launch::launch_with_runtime(
+                settings.nb_threads,
+                &TracingSettings::default(),
+                manage_synonyms(cmd, dir, settings),
+            )
+            .context(ExecutionSnafu)
+        }
     }
 }
 
@@ -38,6 +65,79 @@ async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let client = connect(settings).await?;
+
+    // Update all the template components and indexes
+    update_templates(&client, opts.config_dir).await?;
+
+    Ok(())
+}
+
+async fn manage_synonyms(
+    cmd: &settings::SynonymsCommand,
+    dir: PathBuf,
+    settings: settings::Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // With synonyms_path, Elasticsearch reads the synonym files on its own nodes, so editing the
+    // local ones would change nothing.
+    if let Some(path) = &settings.elasticsearch.synonyms_path {
+        if matches!(
+            cmd,
+            settings::SynonymsCommand::Add { .. } | settings::SynonymsCommand::Remove { .. }
+        ) {
+            return Err(Box::new(Error::RemoteSynonyms { path: path.clone() }));
+        }
+    }
+
+    match cmd {
+        settings::SynonymsCommand::List { lang } => {
+            let sets = match lang {
+                Some(lang) => vec![SynonymSet::read(&dir, lang).await.context(SynonymsSnafu)?],
+                None => synonyms::read_dir(&dir).await.context(SynonymsSnafu)?,
+            };
+            for set in sets {
+                for rule in set.rules {
+                    println!("{}\t{}", set.lang, rule);
+                }
+            }
+        }
+        settings::SynonymsCommand::Add { lang, rule } => {
+            let mut set = SynonymSet::read(&dir, lang).await.context(SynonymsSnafu)?;
+            set.add(rule).context(SynonymsSnafu)?;
+            set.write(&dir).await.context(SynonymsSnafu)?;
+            tracing::info!(
+                "Added '{}' to '{}', run 'synonyms reload' to apply it",
+                rule,
+                lang
+            );
+        }
+        settings::SynonymsCommand::Remove { lang, rule } => {
+            let mut set = SynonymSet::read(&dir, lang).await.context(SynonymsSnafu)?;
+            set.remove(rule).context(SynonymsSnafu)?;
+            set.write(&dir).await.context(SynonymsSnafu)?;
+            tracing::info!(
+                "Removed '{}' from '{}', run 'synonyms reload' to apply it",
+                rule,
+                lang
+            );
+        }
+        settings::SynonymsCommand::Reload { force } => {
+            let sets = synonyms::read_dir(&dir).await.context(SynonymsSnafu)?;
+            let client = connect(settings).await?;
+            let synonyms_path = client.config.synonyms_path.clone();
+            synonyms::reload(&client, &sets, synonyms_path.as_deref(), *force)
+                .await
+                .context(SynonymsSnafu)?;
+            tracing::info!("Reloaded the synonyms of {} languages", sets.len());
+        }
+    }
+
+    Ok(())
+}
+
+async fn connect(
+    settings: settings::Settings,
+) -> Result<ElasticsearchStorage, Box<dyn std::error::Error>> {
     tracing::info!(
         "Trying to connect to elasticsearch at {}",
         &settings.elasticsearch.url
@@ -49,9 +149,5 @@ async fn run(
         .map_err(Box::new)?;
 
     tracing::info!("Connected to elasticsearch.");
-
-    // Update all the template components and indexes
-    update_templates(&client, opts.config_dir).await?;
-
-    Ok(())
+    Ok(client)
 }
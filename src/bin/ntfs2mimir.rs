@@ -32,7 +32,14 @@ use clap::Parser;
 use snafu::{ResultExt, Snafu};
 
 use mimir::{adapters::secondary::elasticsearch, domain::ports::secondary::remote::Remote};
-use mimirsbrunn::{settings::ntfs2mimir as settings, utils::template::update_templates};
+use mimirsbrunn::{
+    settings::ntfs2mimir as settings,
+    utils::{
+        metrics::{self, with_metrics},
+        template::update_templates,
+    },
+    weights::WeightDistribution,
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -58,19 +65,29 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
             println!("{}", serde_json::to_string_pretty(&settings).unwrap());
             Ok(())
         }
+        settings::Command::DryRun => mimirsbrunn::utils::launch::launch_with_runtime(
+            settings.nb_threads,
+            &tracing,
+            dry_run(opts, settings),
+        )
+        .context(ExecutionSnafu),
     }
 }
 
+#[tracing::instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -92,12 +109,36 @@ async fn run(
         update_templates(&client, opts.config_dir).await?;
     }
 
+    let _phase = metrics::phase("stops");
     mimirsbrunn::stops::index_ntfs(&opts.input, &settings, &client)
         .await
         .context(ImportSnafu)
         .map_err(|err| Box::new(err) as Box<dyn snafu::Error>) // TODO Investigate why the need to cast?
 }
 
+// Build the stops without indexing them, and print the distribution of their weights.
+async fn dry_run(
+    opts: settings::Opts,
+    settings: settings::Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = elasticsearch::remote::connection_pool_url(&settings.elasticsearch.url)
+        .conn(settings.elasticsearch.clone())
+        .await
+        .context(ElasticsearchConnectionSnafu)
+        .map_err(Box::new)?;
+
+    let stops = mimirsbrunn::stops::build_stops(&opts.input, &settings, &client)
+        .await
+        .context(ImportSnafu)
+        .map_err(Box::new)?;
+
+    let distribution: WeightDistribution = stops.iter().map(|stop| stop.weight).collect();
+
+    println!("{}", serde_json::to_string_pretty(&distribution).unwrap());
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use futures::TryStreamExt;
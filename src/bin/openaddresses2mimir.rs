@@ -39,7 +39,10 @@ use mimirsbrunn::{
     admin_geofinder::AdminGeoFinder,
     openaddresses::OpenAddress,
     settings::{admin_settings::AdminSettings, openaddresses2mimir as settings},
-    utils::template::update_templates,
+    utils::{
+        metrics::{self, with_metrics},
+        template::update_templates,
+    },
 };
 
 #[derive(Debug, Snafu)]
@@ -71,10 +74,13 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
@@ -84,6 +90,7 @@ fn main() -> Result<(), Error> {
     }
 }
 
+#[tracing::instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -105,12 +112,21 @@ async fn run(
 
     // Fetch and index admins for `into_addr`
     let into_addr = {
+        let _phase = metrics::phase("admins");
         let admin_settings = AdminSettings::build(&settings.admins);
         let admins_geofinder = AdminGeoFinder::build(&admin_settings, &client).await?;
         let id_precision = settings.coordinates.id_precision;
-        move |a: OpenAddress| a.into_addr(&admins_geofinder, id_precision)
+        let weights = settings.weights;
+        move |a: OpenAddress| {
+            a.into_addr(&admins_geofinder, id_precision)
+                .map(|addr| match &weights {
+                    Some(weights) => weights.apply_to_addr(addr),
+                    None => addr,
+                })
+        }
     };
 
+    let _phase = metrics::phase("addresses");
     let addresses = import_addresses_from_input_path(opts.input, true, into_addr)
         .await
         .map_err(Box::new)?;
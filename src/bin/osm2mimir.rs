@@ -2,6 +2,7 @@ use clap::Parser;
 use futures::stream::StreamExt;
 use mimir::domain::model::configuration::ContainerConfig;
 use snafu::{ResultExt, Snafu};
+use std::sync::Arc;
 use tracing::instrument;
 
 use mimir::{
@@ -9,10 +10,16 @@ use mimir::{
     domain::ports::{primary::generate_index::GenerateIndex, secondary::remote::Remote},
 };
 use mimirsbrunn::{
+    addr_geofinder::{self, AddrGeoFinder},
     admin_geofinder::AdminGeoFinder,
     osm_reader::street::streets,
     settings::{admin_settings::AdminSettings, osm2mimir as settings},
-    utils::template::update_templates,
+    utils::{
+        metrics::{self, with_metrics},
+        template::update_templates,
+    },
+    weights::WeightDistribution,
+    wikidata::{enrich_poi, load_if_configured, Wikidata},
 };
 
 #[derive(Debug, Snafu)]
@@ -45,6 +52,9 @@ pub enum Error {
         source: mimirsbrunn::osm_reader::poi::Error,
     },
 
+    #[snafu(display("Address GeoFinder Error {}", source))]
+    AddrGeoFinder { source: addr_geofinder::Error },
+
     #[snafu(display("Poi Index Creation Error {}", source))]
     PoiIndexCreation {
         source: mimir::domain::model::error::Error,
@@ -64,19 +74,29 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
             println!("{}", serde_json::to_string_pretty(&settings).unwrap());
             Ok(())
         }
+        settings::Command::DryRun => mimirsbrunn::utils::launch::launch_with_runtime(
+            settings.nb_threads,
+            &tracing,
+            dry_run(opts, settings),
+        )
+        .context(ExecutionSnafu),
     }
 }
 
+#[instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -96,9 +116,13 @@ async fn run(
 
     let admin_settings = AdminSettings::build(&settings.admins);
 
-    let admins_geofinder = AdminGeoFinder::build(&admin_settings, &client).await?;
+    let admins_geofinder = {
+        let _phase = metrics::phase("admins");
+        Arc::new(AdminGeoFinder::build(&admin_settings, &client).await?)
+    };
 
     if settings.streets.import {
+        let _phase = metrics::phase("streets");
         let streets = streets(
             &mut osm_reader,
             &admins_geofinder,
@@ -107,18 +131,23 @@ async fn run(
             settings.database.as_ref(),
         )
         .context(StreetOsmExtractionSnafu)?;
+        metrics::read("street", streets.len());
+        metrics::expect(streets.len());
 
         import_streets(streets, &client, &settings.container_street).await?;
     }
 
     if settings.pois.import {
+        let _phase = metrics::phase("pois");
+        let wikidata = load_if_configured(&settings.wikidata).await?;
+
         import_pois(
             &mut osm_reader,
             &admins_geofinder,
-            &settings.pois.config.clone().unwrap_or_default(),
+            &settings.pois,
+            wikidata.as_ref(),
             &client,
             &settings.container_poi,
-            settings.pois.max_distance_reverse,
         )
         .await?;
     }
@@ -126,6 +155,44 @@ async fn run(
     Ok(())
 }
 
+// Extract the pois and compute their weight, without indexing them nor looking up
+// their address, and print the distribution of their weights.
+async fn dry_run(
+    opts: settings::Opts,
+    settings: settings::Settings,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut osm_reader =
+        mimirsbrunn::osm_reader::make_osm_reader(&opts.input).context(OsmPbfReaderSnafu)?;
+
+    let client = elasticsearch::remote::connection_pool_url(&settings.elasticsearch.url)
+        .conn(settings.elasticsearch.clone())
+        .await
+        .context(ElasticsearchConnectionSnafu)?;
+
+    let admin_settings = AdminSettings::build(&settings.admins);
+
+    let admins_geofinder = AdminGeoFinder::build(&admin_settings, &client).await?;
+
+    let pois = mimirsbrunn::osm_reader::poi::pois(
+        &mut osm_reader,
+        &settings.pois.config.clone().unwrap_or_default(),
+        &admins_geofinder,
+    )
+    .context(PoiOsmExtractionSnafu)?;
+
+    let wikidata = load_if_configured(&settings.wikidata).await?;
+
+    let distribution: WeightDistribution = pois
+        .into_iter()
+        .map(|poi| settings.pois.weights.apply_to_poi(poi))
+        .map(|poi| enrich_poi(wikidata.as_ref(), poi).weight)
+        .collect();
+
+    println!("{}", serde_json::to_string_pretty(&distribution).unwrap());
+
+    Ok(())
+}
+
 #[instrument(skip_all)]
 async fn import_streets(
     streets: Vec<places::street::Street>,
@@ -147,22 +214,59 @@ async fn import_streets(
 #[instrument(skip_all)]
 async fn import_pois(
     osm_reader: &mut mimirsbrunn::osm_reader::OsmPbfReader,
-    admins_geofinder: &AdminGeoFinder,
-    poi_config: &mimirsbrunn::osm_reader::poi::PoiConfig,
+    admins_geofinder: &Arc<AdminGeoFinder>,
+    poi_settings: &settings::Poi,
+    wikidata: Option<&Wikidata>,
     client: &ElasticsearchStorage,
     config: &ContainerConfig,
-    max_distance_reverse: usize,
 ) -> Result<(), Error> {
     // This function rely on AdminGeoFinder::get_objs_and_deps
     // which use all available cpu/cores to decode osm file and cannot be limited by tokio runtime
-    let pois = mimirsbrunn::osm_reader::poi::pois(osm_reader, poi_config, admins_geofinder)
-        .context(PoiOsmExtractionSnafu)?;
-
-    let pois: Vec<places::poi::Poi> = futures::stream::iter(pois)
-        .map(mimirsbrunn::osm_reader::poi::compute_weight)
-        .then(|poi| mimirsbrunn::osm_reader::poi::add_address(client, poi, max_distance_reverse))
-        .collect()
-        .await;
+    let pois = mimirsbrunn::osm_reader::poi::pois(
+        osm_reader,
+        &poi_settings.config.clone().unwrap_or_default(),
+        admins_geofinder,
+    )
+    .context(PoiOsmExtractionSnafu)?;
+    metrics::read("poi", pois.len());
+    metrics::expect(pois.len());
+
+    let pois: Vec<places::poi::Poi> = pois
+        .into_iter()
+        .map(|poi| poi_settings.weights.apply_to_poi(poi))
+        .map(|poi| enrich_poi(wikidata, poi))
+        .collect();
+
+    let addr_geofinder = AddrGeoFinder::build(
+        &poi_settings.address_lookup,
+        admins_geofinder.clone(),
+        client,
+    )
+    .await
+    .context(AddrGeoFinderSnafu)?;
+
+    let max_distance_reverse = poi_settings.max_distance_reverse;
+    let pois: Vec<places::poi::Poi> = match addr_geofinder {
+        None => {
+            futures::stream::iter(pois)
+                .then(|poi| {
+                    mimirsbrunn::osm_reader::poi::add_address(client, poi, max_distance_reverse)
+                })
+                .collect()
+                .await
+        }
+        Some(addr_geofinder) => {
+            Arc::new(addr_geofinder)
+                .par_map(pois, move |addr_geofinder, poi| {
+                    mimirsbrunn::osm_reader::poi::add_local_address(
+                        addr_geofinder,
+                        poi,
+                        max_distance_reverse,
+                    )
+                })
+                .await
+        }
+    };
 
     let _ = client
         .generate_index(config, futures::stream::iter(pois))
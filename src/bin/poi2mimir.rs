@@ -2,7 +2,16 @@ use clap::Parser;
 use snafu::{ResultExt, Snafu};
 
 use mimir::{adapters::secondary::elasticsearch, domain::ports::secondary::remote::Remote};
-use mimirsbrunn::{settings::poi2mimir as settings, utils::template::update_templates};
+use mimirsbrunn::{
+    admin_geofinder::AdminGeoFinder,
+    settings::{admin_settings::AdminSettings, poi2mimir as settings},
+    utils::{
+        metrics::{self, with_metrics},
+        template::update_templates,
+    },
+    weights::WeightDistribution,
+    wikidata::load_if_configured,
+};
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -25,19 +34,31 @@ fn main() -> Result<(), Error> {
     let opts = settings::Opts::parse();
     let settings = settings::Settings::new(&opts).context(SettingsSnafu)?;
 
+    let tracing = settings.tracing.clone();
+    let metrics = settings.metrics.clone();
     match opts.cmd {
         settings::Command::Run => mimirsbrunn::utils::launch::launch_with_runtime(
             settings.nb_threads,
-            run(opts, settings),
+            &tracing,
+            with_metrics(&metrics, run(opts, settings)),
         )
         .context(ExecutionSnafu),
         settings::Command::Config => {
             println!("{}", serde_json::to_string_pretty(&settings).unwrap());
             Ok(())
         }
+        settings::Command::DryRun { with_admins } => {
+            mimirsbrunn::utils::launch::launch_with_runtime(
+                settings.nb_threads,
+                &tracing,
+                dry_run(opts, settings, with_admins),
+            )
+        }
+        .context(ExecutionSnafu),
     }
 }
 
+#[tracing::instrument(skip_all)]
 async fn run(
     opts: settings::Opts,
     settings: settings::Settings,
@@ -52,7 +73,44 @@ async fn run(
         update_templates(&client, opts.config_dir).await?;
     }
 
-    mimirsbrunn::pois::index_pois(opts.input, &client, settings).await?;
+    let wikidata = load_if_configured(&settings.wikidata).await?;
+
+    let _phase = metrics::phase("pois");
+    mimirsbrunn::pois::index_pois(opts.input, &client, settings, wikidata.as_ref()).await?;
+
+    Ok(())
+}
+
+// Weigh the pois without indexing them, and print the distribution of their weights. Their
+// addresses are not needed, so Elasticsearch is only queried for the admins, if asked.
+async fn dry_run(
+    opts: settings::Opts,
+    settings: settings::Settings,
+    with_admins: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let admins_geofinder = if with_admins {
+        let client = elasticsearch::remote::connection_pool_url(&settings.elasticsearch.url)
+            .conn(settings.elasticsearch.clone())
+            .await
+            .context(ElasticsearchConnectionSnafu)?;
+        let admin_settings = AdminSettings::build(&settings.admins);
+        Some(AdminGeoFinder::build(&admin_settings, &client).await?)
+    } else {
+        None
+    };
+
+    let wikidata = load_if_configured(&settings.wikidata).await?;
+
+    let pois = mimirsbrunn::pois::weigh_pois(
+        &opts.input,
+        &settings,
+        admins_geofinder.as_ref(),
+        wikidata.as_ref(),
+    )?;
+
+    let distribution: WeightDistribution = pois.iter().map(|poi| poi.weight).collect();
+
+    println!("{}", serde_json::to_string_pretty(&distribution).unwrap());
 
     Ok(())
 }
@@ -1,19 +1,28 @@
-use mimirsbrunn::utils::logger::logger_init;
+use mimirsbrunn::utils::logger::{logger_init, set_remote_parent};
 use snafu::{ResultExt, Snafu};
-use std::net::ToSocketAddrs;
+use std::{
+    net::{SocketAddr, ToSocketAddrs},
+    sync::{Arc, RwLock},
+};
 use tokio::runtime;
 use tracing::{info, instrument};
-use warp::{path, Filter};
+use warp::{path, Filter, Reply};
 
-use crate::settings::build_settings;
+use crate::settings::{build_profiles_source, build_settings};
 
 use super::settings::{Error as SettingsError, Opts};
 use mimir::{
     adapters::{
-        primary::bragi::{
-            handlers::{self, Settings},
-            prometheus_handler::update_metrics,
-            routes,
+        primary::{
+            bragi::{
+                api_keys::{ApiKeys, Error as ApiKeysError},
+                clusters::{default_score_factor, Cluster, DEFAULT_CLUSTER},
+                grpc::{self, Error as GrpcError},
+                handlers::{self, Settings},
+                prometheus_handler::update_metrics,
+                routes,
+            },
+            common::settings::{Error as QuerySettingsError, QueryProfiles, QueryProfilesSource},
         },
         secondary::elasticsearch::remote::connection_pool_url,
     },
@@ -25,9 +34,27 @@ pub enum Error {
     #[snafu(display("Could not establish Elasticsearch Connection: {}", source))]
     ElasticsearchConnection { source: PortRemoteError },
 
+    #[snafu(display("Could not connect to Elasticsearch cluster '{}': {}", name, source))]
+    ClusterConnection {
+        name: String,
+        source: PortRemoteError,
+    },
+
     #[snafu(display("Could not generate settings: {}", source))]
     SettingsProcessing { source: SettingsError },
 
+    #[snafu(display("Could not build ranking profiles: {}", source))]
+    ProfilesProcessing { source: QuerySettingsError },
+
+    #[snafu(display("Could not read API keys: {}", source))]
+    ApiKeys { source: ApiKeysError },
+
+    #[snafu(display("Could not serve the gRPC API: {}", source))]
+    Grpc { source: GrpcError },
+
+    #[snafu(display("Invalid CORS settings: {}", source))]
+    Cors { source: routes::CorsError },
+
     #[snafu(display("Socket Addr Error with host {} / port {}: {}", host, port, source))]
     SockAddr {
         host: String,
@@ -46,7 +73,6 @@ pub enum Error {
 
 pub fn run(opts: &Opts) -> Result<(), Error> {
     let settings = build_settings(opts).context(SettingsProcessingSnafu)?;
-    let _log_guard = logger_init().map_err(|err| Error::InitLog { source: err })?;
 
     let runtime = runtime::Builder::new_multi_thread()
         .worker_threads(settings.nb_threads.unwrap_or_else(num_cpus::get))
@@ -54,7 +80,13 @@ pub fn run(opts: &Opts) -> Result<(), Error> {
         .build()
         .expect("Failed to build tokio runtime.");
 
-    runtime.block_on(run_server(settings))
+    // The export of the traces runs in the runtime, and is flushed before the runtime is dropped.
+    let _log_guard = {
+        let _enter = runtime.enter();
+        logger_init(&settings.tracing).map_err(|err| Error::InitLog { source: err })?
+    };
+
+    runtime.block_on(run_server(settings, build_profiles_source(opts)))
 }
 
 pub fn config(opts: &Opts) -> Result<(), Error> {
@@ -64,23 +96,68 @@ pub fn config(opts: &Opts) -> Result<(), Error> {
 }
 
 #[instrument(skip(settings))]
-pub async fn run_server(settings: Settings) -> Result<(), Error> {
+pub async fn run_server(
+    settings: Settings,
+    profiles_source: QueryProfilesSource,
+) -> Result<(), Error> {
     info!(
         "Connecting to Elasticsearch at {}",
         &settings.elasticsearch.url
     );
 
+    let api_keys = Arc::new(ApiKeys::new(settings.api_keys.clone()).context(ApiKeysSnafu)?);
+    api_keys.watch();
+
     // Wrap Elasticsearch client and settings in a context that will be accessible for all
     // handlers.
-    let ctx_builder = {
+    let ctx = {
         let client = connection_pool_url(&settings.elasticsearch.url)
             .conn(settings.elasticsearch.clone())
             .await
             .context(ElasticsearchConnectionSnafu)?;
 
-        let settings = settings.clone();
-        let ctx = handlers::Context { client, settings };
+        let mut clusters = vec![Cluster {
+            name: DEFAULT_CLUSTER.to_string(),
+            client,
+            routing: settings.routing.clone(),
+            timeout: None,
+            score_factor: default_score_factor(),
+        }];
+
+        for cluster in &settings.clusters {
+            info!(
+                "Connecting to Elasticsearch cluster '{}' at {}",
+                cluster.name, cluster.url
+            );
+            let client = connection_pool_url(&cluster.url)
+                .conn(cluster.elasticsearch(&settings.elasticsearch))
+                .await
+                .context(ClusterConnectionSnafu {
+                    name: cluster.name.clone(),
+                })?;
+            clusters.push(Cluster {
+                name: cluster.name.clone(),
+                client,
+                routing: cluster.routing.clone(),
+                timeout: cluster.timeout,
+                score_factor: cluster.score_factor,
+            });
+        }
+
+        let profiles = QueryProfiles::new(settings.query.clone(), &settings.profiles)
+            .context(ProfilesProcessingSnafu)?;
+        info!("Ranking profiles: {}", profiles.names().join(", "));
+
+        handlers::Context {
+            clusters,
+            settings: settings.clone(),
+            profiles: Arc::new(RwLock::new(profiles)),
+            profiles_source: Some(profiles_source),
+        }
+    };
 
+    let ctx_builder = {
+        let ctx = ctx.clone();
         move || {
             let ctx = ctx.clone();
             move || ctx.clone()
@@ -90,40 +167,63 @@ pub async fn run_server(settings: Settings) -> Result<(), Error> {
     let endpoints = {
         warp::get()
             .and(path!("api" / "v1" / "autocomplete"))
+            .and(routes::api_key(api_keys.clone()))
             .map(ctx_builder())
             .and(routes::validate_query())
             .and(warp::any().map(|| None)) // the shape is None
+            .and(routes::accept_language())
+            .and(routes::accept_format())
             .and_then(handlers::forward_autocomplete_geocoder)
     }
     .or({
         warp::post()
             .and(path!("api" / "v1" / "autocomplete"))
+            .and(routes::api_key(api_keys.clone()))
             .map(ctx_builder())
             .and(routes::validate_query())
             .and(routes::validate_geojson_body())
+            .and(routes::accept_language())
+            .and(routes::accept_format())
             .and_then(handlers::forward_autocomplete_geocoder)
     })
     .or({
         warp::get()
             .and(path!("api" / "v1" / "search"))
+            .and(routes::api_key(api_keys.clone()))
             .map(ctx_builder())
             .and(routes::validate_query())
             .and(warp::any().map(|| None)) // the shape is None
+            .and(routes::accept_language())
+            .and(routes::accept_format())
             .and_then(handlers::forward_search_geocoder)
     })
     .or({
         warp::get()
             .and(path!("api" / "v1" / "reverse"))
+            .and(routes::api_key(api_keys.clone()))
             .map(ctx_builder())
             .and(routes::validate_query())
+            .and(routes::accept_format())
             .and_then(handlers::reverse_geocoder)
     })
+    .or({
+        warp::get()
+            .and(path!("api" / "v1" / "features" / String))
+            .and(routes::api_key(api_keys.clone()))
+            .and(warp::any().map(ctx_builder()))
+            .and(routes::validate_query())
+            .and(routes::accept_language())
+            .and(routes::accept_format())
+            .and_then(handlers::features)
+    })
     .or({
         warp::get()
             .and(path!("api" / "v1" / "autocomplete-explain"))
+            .and(routes::api_key(api_keys.clone()))
             .map(ctx_builder())
             .and(routes::validate_query())
             .and(warp::any().map(|| None)) // the shape is None
+            .and(routes::accept_language())
             .and_then(handlers::forward_geocoder_explain)
     })
     .or({
@@ -132,40 +232,151 @@ pub async fn run_server(settings: Settings) -> Result<(), Error> {
             .map(ctx_builder())
             .and_then(handlers::status)
     })
+    .or({
+        warp::post()
+            .and(path!("api" / "v1" / "admin" / "profiles" / "reload"))
+            .and(routes::admin_authorization(settings.admin_token.clone()))
+            .map(ctx_builder())
+            .and_then(handlers::reload_profiles)
+    })
     .or({
         warp::get()
             .and(path!("api" / "v1" / "metrics"))
             .and_then(handlers::metrics)
+    })
+    .or({
+        warp::get()
+            .and(path!("api" / "v1" / "openapi.json"))
+            .map(ctx_builder())
+            .and_then(handlers::openapi)
+    })
+    .or({
+        warp::get()
+            .and(path!("api" / "v1" / "docs"))
+            .and_then(handlers::swagger_ui)
     });
 
-    let api = endpoints
-        .recover(routes::report_invalid)
-        .with(warp::wrap_fn(|filter| {
-            routes::cache_filter(filter, settings.http_cache_duration)
-        }))
+    // The probes of the orchestrators are not cached.
+    let health = {
+        warp::get()
+            .and(path!("health" / "live"))
+            .and_then(handlers::health_live)
+    }
+    .or({
+        warp::get()
+            .and(path!("health" / "ready"))
+            .map(ctx_builder())
+            .and_then(handlers::health_ready)
+    });
+
+    let endpoints = routes::etag(
+        endpoints
+            .recover(routes::report_invalid)
+            .with(warp::wrap_fn(|filter| {
+                routes::cache_filter(filter, settings.http_cache_duration)
+            })),
+        settings.etag,
+        settings.compression.clone(),
+    );
+
+    let api = routes::compression(
+        routes::count_requests(api_keys.clone(), health.or(endpoints)),
+        settings.compression.clone(),
+    );
+
+    // Preflight requests are answered by the CORS filter.
+    let api = match routes::cors(&settings.cors).context(CorsSnafu)? {
+        Some(cors) => api.with(cors).map(Reply::into_response).boxed(),
+        None => api.boxed(),
+    };
+
+    let api = api
         .with(warp::log::custom(update_metrics))
         .with(warp::trace(|info| {
-            tracing::info_span!(
+            let span = tracing::info_span!(
                 "request",
                 method = %info.method(),
                 path = %info.path(),
-            )
+            );
+            // The request may be part of a trace started by the caller.
+            set_remote_parent(&span, info.request_headers());
+            span
         }));
 
     info!("api ready");
 
-    let host = settings.service.host;
-    let port = settings.service.port;
-    let addr = (host.as_str(), port);
-    let addr = addr
+    let addr = socket_addr(&settings.service.host, settings.service.port)?;
+    if !settings.grpc.enabled {
+        info!("Serving bragi on {}", addr);
+        warp::serve(api).run(addr).await;
+        return Ok(());
+    }
+
+    let grpc_addr = socket_addr(&settings.service.host, settings.grpc.port)?;
+    info!(
+        "Serving bragi on {}, and its gRPC API on {}",
+        addr, grpc_addr
+    );
+    tokio::select! {
+        _ = warp::serve(api).run(addr) => Ok(()),
+        res = grpc::serve(ctx, api_keys, grpc_addr) => res.context(GrpcSnafu),
+    }
+}
+
+fn socket_addr(host: &str, port: u16) -> Result<SocketAddr, Error> {
+    (host, port)
         .to_socket_addrs()
-        .context(SockAddrSnafu { host, port })?
+        .context(SockAddrSnafu {
+            host: host.to_string(),
+            port,
+        })?
         .next()
         .ok_or(Error::AddrResolution {
             msg: String::from("Cannot resolve bragi addr."),
-        })?;
+        })
+}
 
-    info!("Serving bragi on {}", addr);
-    warp::serve(api).run(addr).await;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use mimir::adapters::primary::bragi::{api_keys::ApiKeysSettings, openapi};
+    use regex::Regex;
+    use std::collections::BTreeSet;
+
+    // The routes of this file, as (method, path), eg. ("get", "/api/v1/status"). The
+    // parameters of the path are named 'id', as in the specification.
+    fn routes() -> BTreeSet<(String, String)> {
+        let route = Regex::new(r#"warp::(get|post)\(\)\s*\.and\(path!\(([^)]*)\)\)"#).unwrap();
+        route
+            .captures_iter(include_str!("server.rs"))
+            .map(|captures| {
+                let path: String = captures[2]
+                    .split('/')
+                    .map(|segment| match segment.trim().strip_prefix('"') {
+                        Some(segment) => format!("/{}", segment.trim_end_matches('"')),
+                        None => String::from("/{id}"),
+                    })
+                    .collect();
+                (captures[1].to_string(), path)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_specify_all_the_routes() {
+        let spec = openapi::spec(&ApiKeysSettings::default());
+        let specified: BTreeSet<(String, String)> = spec["paths"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .flat_map(|(path, item)| {
+                item.as_object()
+                    .unwrap()
+                    .keys()
+                    .map(move |method| (method.clone(), path.clone()))
+            })
+            .collect();
+
+        assert!(routes().len() > 10);
+        assert_eq!(routes(), specified);
+    }
 }
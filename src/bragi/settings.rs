@@ -1,4 +1,4 @@
-use mimir::adapters::primary::bragi::handlers::Settings;
+use mimir::adapters::primary::{bragi::handlers::Settings, common::settings::QueryProfilesSource};
 use snafu::{ResultExt, Snafu};
 use std::{env, path::PathBuf};
 
@@ -77,7 +77,7 @@ pub enum Command {
 pub fn build_settings(opts: &Opts) -> Result<Settings, Error> {
     common::config::config_from(
         opts.config_dir.as_ref(),
-        &["bragi", "elasticsearch", "query"],
+        &["bragi", "elasticsearch", "query", "tracing"],
         opts.run_mode.as_deref(),
         "BRAGI",
         opts.settings.clone(),
@@ -89,6 +89,16 @@ pub fn build_settings(opts: &Opts) -> Result<Settings, Error> {
     })
 }
 
+/// The source of the ranking profiles, read again when they are reloaded.
+pub fn build_profiles_source(opts: &Opts) -> QueryProfilesSource {
+    QueryProfilesSource {
+        config_dir: opts.config_dir.clone(),
+        run_mode: opts.run_mode.clone(),
+        env_prefix: String::from("BRAGI"),
+        overrides: opts.settings.clone(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
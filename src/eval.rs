@@ -0,0 +1,502 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Offline evaluation of the relevance of the autocomplete.
+//!
+//! A golden set lists queries along with the ids of the places expected in the results. The
+//! results of each query are compared to the expected ids, and summarized with the mean
+//! reciprocal rank (MRR), the recall at several cutoffs, and the nDCG, for all the queries and
+//! for each type of document.
+
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// Name of the group of metrics computed over all the queries.
+pub const ALL: &str = "all";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not read {}: {}", path, source))]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[snafu(display("Invalid JSON in {}: {}", path, source))]
+    InvalidJson {
+        path: String,
+        source: serde_json::Error,
+    },
+
+    #[snafu(display("Invalid line {} in {}: {}", line, path, details))]
+    InvalidLine {
+        path: String,
+        line: usize,
+        details: String,
+    },
+}
+
+/// A query of the golden set, along with the ids of the places expected in its results.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Case {
+    pub query: String,
+    #[serde(default)]
+    pub lat: Option<f32>,
+    #[serde(default)]
+    pub lon: Option<f32>,
+    pub expected: Vec<String>,
+    /// The type of the expected places. If absent, it is the prefix of the first expected id
+    /// (eg 'poi' for 'poi:osm:node:534918694').
+    #[serde(default)]
+    pub doc_type: Option<String>,
+}
+
+impl Case {
+    pub fn doc_type(&self) -> String {
+        self.doc_type.clone().unwrap_or_else(|| {
+            self.expected
+                .first()
+                .and_then(|id| id.split(':').next())
+                .unwrap_or("unknown")
+                .to_string()
+        })
+    }
+}
+
+/// Reads a golden set, either a JSON array of cases ('.json'), or a TSV file. Each line of a
+/// TSV file holds the query, the latitude, the longitude (both may be empty), the comma
+/// separated expected ids, and optionally the type of document. Empty lines and lines
+/// starting with '#' are skipped.
+pub fn read_golden_set<P: AsRef<Path>>(path: P) -> Result<Vec<Case>, Error> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).context(ReadFileSnafu {
+        path: path.display().to_string(),
+    })?;
+
+    if path.extension().map_or(false, |ext| ext == "json") {
+        serde_json::from_str(&content).context(InvalidJsonSnafu {
+            path: path.display().to_string(),
+        })
+    } else {
+        parse_tsv(&content).map_err(|(line, details)| Error::InvalidLine {
+            path: path.display().to_string(),
+            line,
+            details,
+        })
+    }
+}
+
+fn parse_tsv(content: &str) -> Result<Vec<Case>, (usize, String)> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+        .map(|(index, line)| {
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+            if fields.len() < 4 {
+                return Err((
+                    line_number,
+                    format!("expected at least 4 fields, found {}", fields.len()),
+                ));
+            }
+
+            let coord = |field: &str| {
+                if field.is_empty() {
+                    Ok(None)
+                } else {
+                    field
+                        .parse::<f32>()
+                        .map(Some)
+                        .map_err(|err| (line_number, format!("'{}': {}", field, err)))
+                }
+            };
+
+            Ok(Case {
+                query: fields[0].to_string(),
+                lat: coord(fields[1])?,
+                lon: coord(fields[2])?,
+                expected: fields[3]
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|id| !id.is_empty())
+                    .map(String::from)
+                    .collect(),
+                doc_type: fields
+                    .get(4)
+                    .filter(|doc_type| !doc_type.is_empty())
+                    .map(|doc_type| doc_type.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// The ids found for a case of the golden set, in the order of the results.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub query: String,
+    pub doc_type: String,
+    pub expected: Vec<String>,
+    pub found: Vec<String>,
+}
+
+impl CaseResult {
+    pub fn new(case: &Case, found: Vec<String>) -> Self {
+        CaseResult {
+            query: case.query.clone(),
+            doc_type: case.doc_type(),
+            expected: case.expected.clone(),
+            found,
+        }
+    }
+
+    /// The rank (starting at 1) of the first expected id found in the results.
+    pub fn rank(&self) -> Option<usize> {
+        self.found
+            .iter()
+            .position(|id| self.expected.contains(id))
+            .map(|position| position + 1)
+    }
+
+    pub fn reciprocal_rank(&self) -> f64 {
+        self.rank().map_or(0.0, |rank| 1.0 / rank as f64)
+    }
+
+    /// The proportion of the expected ids found in the first k results.
+    pub fn recall(&self, k: usize) -> f64 {
+        if self.expected.is_empty() {
+            return 0.0;
+        }
+        let found = self
+            .expected
+            .iter()
+            .filter(|id| self.found.iter().take(k).any(|found| found == *id))
+            .count();
+        found as f64 / self.expected.len() as f64
+    }
+
+    /// The normalized discounted cumulative gain of the first k results, each expected id
+    /// having a relevance of 1.
+    pub fn ndcg(&self, k: usize) -> f64 {
+        let dcg: f64 = self
+            .found
+            .iter()
+            .take(k)
+            .enumerate()
+            .filter(|(_, id)| self.expected.contains(id))
+            .map(|(position, _)| discount(position))
+            .sum();
+        let ideal: f64 = (0..self.expected.len().min(k)).map(discount).sum();
+        if ideal > 0.0 {
+            dcg / ideal
+        } else {
+            0.0
+        }
+    }
+}
+
+fn discount(position: usize) -> f64 {
+    1.0 / ((position + 2) as f64).log2()
+}
+
+/// Metrics averaged over a group of cases.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub count: usize,
+    pub mrr: f64,
+    pub recall: BTreeMap<usize, f64>,
+    pub ndcg: f64,
+}
+
+impl Metrics {
+    fn new(results: &[&CaseResult], cutoffs: &[usize]) -> Self {
+        let count = results.len();
+        let mean = |value: &dyn Fn(&CaseResult) -> f64| {
+            if count == 0 {
+                0.0
+            } else {
+                results.iter().map(|result| value(*result)).sum::<f64>() / count as f64
+            }
+        };
+        let max_cutoff = cutoffs.iter().copied().max().unwrap_or(1);
+
+        Metrics {
+            count,
+            mrr: mean(&CaseResult::reciprocal_rank),
+            recall: cutoffs
+                .iter()
+                .map(|&k| (k, mean(&|result| result.recall(k))))
+                .collect(),
+            ndcg: mean(&|result| result.ndcg(max_cutoff)),
+        }
+    }
+}
+
+/// The results of a run over a golden set, which can be saved and compared to a later run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Report {
+    pub cutoffs: Vec<usize>,
+    pub metrics: BTreeMap<String, Metrics>,
+    pub cases: Vec<CaseResult>,
+}
+
+impl Report {
+    pub fn new(cases: Vec<CaseResult>, cutoffs: &[usize]) -> Self {
+        let mut groups: BTreeMap<String, Vec<&CaseResult>> = BTreeMap::new();
+        for case in &cases {
+            groups.entry(ALL.to_string()).or_default().push(case);
+            groups.entry(case.doc_type.clone()).or_default().push(case);
+        }
+
+        let metrics = groups
+            .into_iter()
+            .map(|(group, results)| (group, Metrics::new(&results, cutoffs)))
+            .collect();
+
+        Report {
+            cutoffs: cutoffs.to_vec(),
+            metrics,
+            cases,
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path).context(ReadFileSnafu {
+            path: path.display().to_string(),
+        })?;
+        serde_json::from_str(&content).context(InvalidJsonSnafu {
+            path: path.display().to_string(),
+        })
+    }
+
+    /// Compares this run to a previous one.
+    pub fn diff<'a>(&'a self, baseline: &'a Report) -> Diff<'a> {
+        let previous: BTreeMap<&str, &CaseResult> = baseline
+            .cases
+            .iter()
+            .map(|case| (case.query.as_str(), case))
+            .collect();
+
+        let changes = self
+            .cases
+            .iter()
+            .filter_map(|case| {
+                let before = previous.get(case.query.as_str())?;
+                if before.rank() != case.rank() {
+                    Some(RankChange {
+                        query: &case.query,
+                        before: before.rank(),
+                        after: case.rank(),
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        Diff {
+            report: self,
+            baseline,
+            changes,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:<12} {:>6} {:>7}", "group", "count", "mrr")?;
+        for k in &self.cutoffs {
+            write!(f, " {:>9}", format!("recall@{}", k))?;
+        }
+        writeln!(f, " {:>7}", "ndcg")?;
+
+        for (group, metrics) in &self.metrics {
+            write!(f, "{:<12} {:>6} {:>7.3}", group, metrics.count, metrics.mrr)?;
+            for k in &self.cutoffs {
+                write!(f, " {:>9.3}", metrics.recall.get(k).unwrap_or(&0.0))?;
+            }
+            writeln!(f, " {:>7.3}", metrics.ndcg)?;
+        }
+        Ok(())
+    }
+}
+
+/// A query whose first relevant result moved between two runs.
+#[derive(Debug, PartialEq)]
+pub struct RankChange<'a> {
+    pub query: &'a str,
+    pub before: Option<usize>,
+    pub after: Option<usize>,
+}
+
+impl RankChange<'_> {
+    pub fn is_regression(&self) -> bool {
+        match (self.before, self.after) {
+            (Some(before), Some(after)) => after > before,
+            (Some(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The differences between a run and a previous one: the variation of each metric, and the
+/// queries whose first relevant result moved.
+#[derive(Debug)]
+pub struct Diff<'a> {
+    pub report: &'a Report,
+    pub baseline: &'a Report,
+    pub changes: Vec<RankChange<'a>>,
+}
+
+impl fmt::Display for Diff<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rank = |rank: Option<usize>| rank.map_or("-".to_string(), |rank| rank.to_string());
+
+        for (group, metrics) in &self.report.metrics {
+            let before = match self.baseline.metrics.get(group) {
+                Some(before) => before,
+                None => continue,
+            };
+            write!(f, "{:<12} mrr {:+.3}", group, metrics.mrr - before.mrr)?;
+            for (k, recall) in &metrics.recall {
+                if let Some(previous) = before.recall.get(k) {
+                    write!(f, "  recall@{} {:+.3}", k, recall - previous)?;
+                }
+            }
+            writeln!(f, "  ndcg {:+.3}", metrics.ndcg - before.ndcg)?;
+        }
+
+        for change in &self.changes {
+            writeln!(
+                f,
+                "{} '{}': rank {} -> {}",
+                if change.is_regression() { "-" } else { "+" },
+                change.query,
+                rank(change.before),
+                rank(change.after)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn result(query: &str, expected: &[&str], found: &[&str]) -> CaseResult {
+        let case = Case {
+            query: query.to_string(),
+            lat: None,
+            lon: None,
+            expected: expected.iter().map(|id| id.to_string()).collect(),
+            doc_type: None,
+        };
+        CaseResult::new(&case, found.iter().map(|id| id.to_string()).collect())
+    }
+
+    #[test]
+    fn should_parse_tsv_golden_set() {
+        let content = "# query\tlat\tlon\texpected\n\
+                       hector malot\t48.84\t2.37\tpoi:osm:node:1, poi:osm:node:2\n\
+                       \n\
+                       rue de rivoli\t\t\tstreet:1\tstreet\n";
+
+        let cases = parse_tsv(content).unwrap();
+        assert_eq!(cases.len(), 2);
+        assert_eq!(cases[0].lat, Some(48.84));
+        assert_eq!(cases[0].expected, vec!["poi:osm:node:1", "poi:osm:node:2"]);
+        assert_eq!(cases[0].doc_type(), "poi");
+        assert_eq!(cases[1].lat, None);
+        assert_eq!(cases[1].doc_type(), "street");
+        assert_eq!(parse_tsv("q\tx\t\tid").unwrap_err().0, 1);
+    }
+
+    #[test]
+    fn should_compute_metrics() {
+        let res = result("q", &["a", "b"], &["x", "a", "y", "b"]);
+
+        assert_eq!(res.rank(), Some(2));
+        assert_relative_eq!(res.reciprocal_rank(), 0.5);
+        assert_relative_eq!(res.recall(1), 0.0);
+        assert_relative_eq!(res.recall(2), 0.5);
+        assert_relative_eq!(res.recall(4), 1.0);
+        // dcg = 1/log2(3) + 1/log2(5), idcg = 1 + 1/log2(3)
+        assert_relative_eq!(
+            res.ndcg(4),
+            (1.0 / 3f64.log2() + 1.0 / 5f64.log2()) / (1.0 + 1.0 / 3f64.log2())
+        );
+        assert_relative_eq!(result("q", &["a"], &["x"]).ndcg(10), 0.0);
+    }
+
+    #[test]
+    fn should_report_by_doc_type_and_diff() {
+        let baseline = Report::new(
+            vec![
+                result("paris", &["admin:1"], &["admin:1"]),
+                result("rivoli", &["street:1"], &["street:2", "street:1"]),
+            ],
+            &[1, 5],
+        );
+        let report = Report::new(
+            vec![
+                result("paris", &["admin:1"], &["admin:2"]),
+                result("rivoli", &["street:1"], &["street:1"]),
+            ],
+            &[1, 5],
+        );
+
+        assert_eq!(report.metrics[ALL].count, 2);
+        assert_relative_eq!(report.metrics["street"].mrr, 1.0);
+        assert_relative_eq!(baseline.metrics["street"].recall[&1], 0.0);
+
+        let diff = report.diff(&baseline);
+        assert_eq!(
+            diff.changes,
+            vec![
+                RankChange {
+                    query: "paris",
+                    before: Some(1),
+                    after: None
+                },
+                RankChange {
+                    query: "rivoli",
+                    before: Some(2),
+                    after: Some(1)
+                },
+            ]
+        );
+        assert!(diff.changes[0].is_regression());
+        assert!(!diff.changes[1].is_regression());
+    }
+}
@@ -28,15 +28,21 @@
 // https://groups.google.com/d/forum/navitia
 // www.navitia.io
 
+pub mod addr_geofinder;
 pub mod addr_reader;
 pub mod admin;
 pub mod admin_geofinder;
 pub mod bano;
 pub mod error;
+pub mod eval;
 pub mod labels;
 pub mod openaddresses;
 pub mod osm_reader;
+pub mod poi_reader;
 pub mod pois;
 pub mod settings;
 pub mod stops;
+pub mod tuning;
 pub mod utils;
+pub mod weights;
+pub mod wikidata;
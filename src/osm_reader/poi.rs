@@ -49,7 +49,7 @@ use places::{
     poi::{Poi, PoiType},
 };
 
-use crate::{admin_geofinder::AdminGeoFinder, labels};
+use crate::{addr_geofinder::AddrGeoFinder, admin_geofinder::AdminGeoFinder, labels};
 
 use super::{
     osm_utils::{get_way_coord, make_centroid},
@@ -266,18 +266,6 @@ pub fn pois(
         .collect())
 }
 
-pub fn compute_weight(poi: Poi) -> Poi {
-    let weight = poi
-        .administrative_regions
-        .iter()
-        .find(|admin| admin.is_city())
-        .map(|admin| admin.weight);
-    match weight {
-        Some(weight) => Poi { weight, ..poi },
-        None => poi,
-    }
-}
-
 // FIXME Return a Result
 pub async fn add_address<T>(backend: &T, poi: Poi, max_distance_reverse: usize) -> Poi
 where
@@ -348,6 +336,29 @@ where
     // }
 }
 
+/// Same as `add_address`, but the address is found in the given AddrGeoFinder.
+pub fn add_local_address(
+    addr_geofinder: &AddrGeoFinder,
+    poi: Poi,
+    max_distance_reverse: usize,
+) -> Poi {
+    match addr_geofinder.get(&poi.coord, max_distance_reverse as f64) {
+        Some(address) => Poi {
+            address: Some(address),
+            ..poi
+        },
+        None => {
+            warn!(
+                "Cannot find a closest address for poi {:?} at lat {} / lon {}",
+                poi.id,
+                poi.coord.lat(),
+                poi.coord.lon()
+            );
+            poi
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
@@ -68,7 +68,7 @@ pub enum Kind {
     Relation = 2,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct StreetExclusion {
     pub highway: Option<Vec<String>>,
     pub public_transport: Option<Vec<String>>,
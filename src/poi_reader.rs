@@ -0,0 +1,478 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+/// In this module we put the code to read pois from custom datasets: CSV files, GeoJSON
+/// FeatureCollections, and NDJSON files (one GeoJSON Feature, or one flat JSON object, per line).
+///
+/// Each record is a set of fields, which are mapped to the poi's id, name, type and coordinates
+/// using a configurable mapping. The other fields become the poi's properties.
+use places::{coord::Coord, poi::PoiType, utils::normalize_id};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::Path,
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("IO Error: {}", source))]
+    InvalidIO { source: std::io::Error },
+
+    #[snafu(display("CSV Error: {}", source))]
+    Csv { source: csv::Error },
+
+    #[snafu(display("Invalid JSON: {}", source))]
+    Json { source: serde_json::Error },
+
+    #[snafu(display("Invalid GeoJSON: {}", details))]
+    InvalidGeoJson { details: String },
+
+    #[snafu(display("Invalid CSV delimiter '{}', it must be an ASCII character", delimiter))]
+    InvalidDelimiter { delimiter: char },
+
+    #[snafu(display("Invalid Record: {}", details))]
+    InvalidRecord { details: String },
+}
+
+/// Format of the poi input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputFormat {
+    /// Navitia '.poi' format
+    Navitia,
+    Csv,
+    GeoJson,
+    NdJson,
+}
+
+impl Default for InputFormat {
+    fn default() -> Self {
+        InputFormat::Navitia
+    }
+}
+
+/// Names of the fields holding the poi's attributes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FieldMapping {
+    pub id: String,
+    pub name: String,
+    pub poi_type_id: String,
+    /// If None, or if the field is missing, the poi type id is used as its name.
+    pub poi_type_name: Option<String>,
+    /// Latitude and longitude, used if the record has no Point geometry.
+    pub lat: String,
+    pub lon: String,
+    /// Fields kept as properties. If None, all the fields which are not mapped are kept.
+    pub properties: Option<Vec<String>>,
+}
+
+impl Default for FieldMapping {
+    fn default() -> Self {
+        FieldMapping {
+            id: String::from("id"),
+            name: String::from("name"),
+            poi_type_id: String::from("poi_type_id"),
+            poi_type_name: Some(String::from("poi_type_name")),
+            lat: String::from("lat"),
+            lon: String::from("lon"),
+            properties: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSettings {
+    #[serde(default)]
+    pub format: InputFormat,
+    #[serde(default)]
+    pub mapping: FieldMapping,
+    /// Delimiter of CSV files.
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+}
+
+impl Default for InputSettings {
+    fn default() -> Self {
+        InputSettings {
+            format: InputFormat::default(),
+            mapping: FieldMapping::default(),
+            delimiter: default_delimiter(),
+        }
+    }
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// A poi as read in the input, before it is attached to its admins and address.
+#[derive(Debug, Clone)]
+pub struct InputPoi {
+    pub id: String,
+    pub name: String,
+    pub coord: Coord,
+    pub poi_type: PoiType,
+    pub properties: BTreeMap<String, String>,
+}
+
+/// Read the pois found in the file at `path`, which must not be in the Navitia format.
+/// Invalid records are skipped, only a file which cannot be read is an error.
+pub fn read_pois(path: &Path, settings: &InputSettings) -> Result<Vec<InputPoi>, Error> {
+    let reader = BufReader::new(File::open(path).context(InvalidIOSnafu)?);
+    let records = match settings.format {
+        InputFormat::Csv => read_csv(reader, settings.delimiter)?,
+        InputFormat::GeoJson => read_geojson(reader)?,
+        InputFormat::NdJson => read_ndjson(reader)?,
+        InputFormat::Navitia => {
+            return Err(Error::InvalidRecord {
+                details: String::from("navitia pois are read with navitia_poi_model"),
+            })
+        }
+    };
+
+    let nb_records = records.len();
+    let pois: Vec<InputPoi> = records
+        .into_iter()
+        .filter_map(|record| {
+            record
+                .and_then(|record| record.into_input_poi(&settings.mapping))
+                .map_err(|err| warn!("Invalid poi has been ignored: {}", err))
+                .ok()
+        })
+        .collect();
+    info!(
+        "{}/{} pois read from {}",
+        pois.len(),
+        nb_records,
+        path.display()
+    );
+
+    Ok(pois)
+}
+
+/// The fields of a record, and its coordinates (lon, lat) if it has a Point geometry.
+#[derive(Debug, Default)]
+struct Record {
+    fields: Map<String, Value>,
+    coord: Option<(f64, f64)>,
+}
+
+impl Record {
+    fn field(&self, key: &str) -> Option<String> {
+        self.fields.get(key).and_then(value_to_string)
+    }
+
+    fn required_field(&self, key: &str) -> Result<String, Error> {
+        self.field(key).ok_or_else(|| Error::InvalidRecord {
+            details: format!("missing field '{}'", key),
+        })
+    }
+
+    fn coordinate(&self, key: &str) -> Result<f64, Error> {
+        self.required_field(key)?
+            .parse()
+            .map_err(|_| Error::InvalidRecord {
+                details: format!("invalid coordinate '{}'", key),
+            })
+    }
+
+    fn into_input_poi(self, mapping: &FieldMapping) -> Result<InputPoi, Error> {
+        let id = self.required_field(&mapping.id)?;
+        let name = self.required_field(&mapping.name)?;
+        let poi_type_id = self.required_field(&mapping.poi_type_id)?;
+        let poi_type_name = mapping
+            .poi_type_name
+            .as_ref()
+            .and_then(|key| self.field(key))
+            .unwrap_or_else(|| poi_type_id.clone());
+        let (lon, lat) = match self.coord {
+            Some(coord) => coord,
+            None => (
+                self.coordinate(&mapping.lon)?,
+                self.coordinate(&mapping.lat)?,
+            ),
+        };
+        let coord = Coord::new(lon, lat).map_err(|err| Error::InvalidRecord {
+            details: format!("invalid coordinates for poi {}: {:?}", id, err),
+        })?;
+
+        let mapped = [
+            Some(&mapping.id),
+            Some(&mapping.name),
+            Some(&mapping.poi_type_id),
+            mapping.poi_type_name.as_ref(),
+            Some(&mapping.lat),
+            Some(&mapping.lon),
+        ];
+        let properties = self
+            .fields
+            .iter()
+            .filter(|&(key, _)| match &mapping.properties {
+                Some(properties) => properties.contains(key),
+                None => !mapped.contains(&Some(key)),
+            })
+            .filter_map(|(key, value)| value_to_string(value).map(|value| (key.clone(), value)))
+            .collect();
+
+        Ok(InputPoi {
+            id,
+            name,
+            coord,
+            poi_type: PoiType {
+                id: if poi_type_id.starts_with("poi_type:") {
+                    poi_type_id
+                } else {
+                    normalize_id("poi_type", &poi_type_id)
+                },
+                name: poi_type_name,
+            },
+            properties,
+        })
+    }
+}
+
+// Null values and empty strings are considered as missing.
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) if s.is_empty() => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+// The records of a file, each one being valid or not. Only the errors reading the file are
+// returned as such.
+type Records = Vec<Result<Record, Error>>;
+
+fn read_csv<R: Read>(reader: R, delimiter: char) -> Result<Records, Error> {
+    if !delimiter.is_ascii() {
+        return Err(Error::InvalidDelimiter { delimiter });
+    }
+    let mut records = Vec::new();
+    for fields in csv::ReaderBuilder::new()
+        .delimiter(delimiter as u8)
+        .from_reader(reader)
+        .deserialize::<BTreeMap<String, String>>()
+    {
+        match fields {
+            Ok(fields) => records.push(Ok(Record {
+                fields: fields
+                    .into_iter()
+                    .map(|(key, value)| (key, Value::String(value)))
+                    .collect(),
+                coord: None,
+            })),
+            Err(err) if err.is_io_error() => return Err(err).context(CsvSnafu),
+            Err(err) => records.push(Err(err).context(CsvSnafu)),
+        }
+    }
+    Ok(records)
+}
+
+fn read_geojson<R: Read>(reader: R) -> Result<Records, Error> {
+    let collection: Value = serde_json::from_reader(reader).context(JsonSnafu)?;
+    if collection["type"] != "FeatureCollection" {
+        return Err(Error::InvalidGeoJson {
+            details: String::from("expected a FeatureCollection"),
+        });
+    }
+    let features = match collection {
+        Value::Object(mut collection) => collection.remove("features"),
+        _ => None,
+    };
+    match features {
+        Some(Value::Array(features)) => Ok(features.into_iter().map(feature_to_record).collect()),
+        _ => Err(Error::InvalidGeoJson {
+            details: String::from("missing features"),
+        }),
+    }
+}
+
+fn read_ndjson<R: BufRead>(reader: R) -> Result<Records, Error> {
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = line.context(InvalidIOSnafu)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(ndjson_to_record(&line));
+    }
+    Ok(records)
+}
+
+fn ndjson_to_record(line: &str) -> Result<Record, Error> {
+    let value: Value = serde_json::from_str(line).context(JsonSnafu)?;
+    if value["type"] == "Feature" {
+        return feature_to_record(value);
+    }
+    match value {
+        Value::Object(fields) => Ok(Record {
+            fields,
+            coord: None,
+        }),
+        _ => Err(Error::InvalidRecord {
+            details: String::from("expected a JSON object"),
+        }),
+    }
+}
+
+fn feature_to_record(feature: Value) -> Result<Record, Error> {
+    let mut feature = match feature {
+        Value::Object(feature) => feature,
+        _ => {
+            return Err(Error::InvalidGeoJson {
+                details: String::from("expected a Feature"),
+            })
+        }
+    };
+    let fields = match feature.remove("properties") {
+        Some(Value::Object(properties)) => properties,
+        _ => Map::new(),
+    };
+    // Only Point geometries are supported, otherwise the coordinates are read in the fields.
+    let coord = feature.get("geometry").and_then(|geometry| {
+        if geometry["type"] != "Point" {
+            return None;
+        }
+        let coordinates = geometry["coordinates"].as_array()?;
+        Some((coordinates.get(0)?.as_f64()?, coordinates.get(1)?.as_f64()?))
+    });
+    Ok(Record { fields, coord })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> FieldMapping {
+        FieldMapping {
+            poi_type_id: String::from("category"),
+            poi_type_name: None,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn should_read_csv_with_custom_mapping() {
+        let data = "id;name;category;lat;lon;phone\n\
+                    1;Musée;museum;48.86;2.33;0102030405\n\
+                    2;Nowhere;museum;;2.33;\n";
+        let records = read_csv(data.as_bytes(), ';').unwrap();
+        let pois = records
+            .into_iter()
+            .map(|record| record.and_then(|record| record.into_input_poi(&mapping())))
+            .collect::<Vec<_>>();
+        assert_eq!(pois.len(), 2);
+        let poi = pois[0].as_ref().unwrap();
+        assert_eq!(poi.id, "1");
+        assert_eq!(poi.poi_type.id, "poi_type:museum");
+        assert_eq!(poi.poi_type.name, "museum");
+        assert_eq!(poi.coord.lat(), 48.86);
+        assert_eq!(poi.properties.len(), 1);
+        assert_eq!(poi.properties["phone"], "0102030405");
+        // The second poi has no latitude
+        assert!(pois[1].is_err());
+    }
+
+    #[test]
+    fn should_read_geojson_feature_collection() {
+        let data = r#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "geometry": { "type": "Point", "coordinates": [2.33, 48.86] },
+                "properties": { "id": "1", "name": "Musée", "category": "museum", "stars": 3 }
+            }]
+        }"#;
+        let records = read_geojson(data.as_bytes()).unwrap();
+        let poi = records
+            .into_iter()
+            .next()
+            .unwrap()
+            .unwrap()
+            .into_input_poi(&mapping())
+            .unwrap();
+        assert_eq!(poi.coord.lon(), 2.33);
+        assert_eq!(poi.properties["stars"], "3");
+    }
+
+    #[test]
+    fn should_read_ndjson_features_and_objects() {
+        let data = concat!(
+            r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [2.33, 48.86]}, "properties": {"id": "1", "name": "A", "category": "museum"}}"#,
+            "\n",
+            r#"{"id": "2", "name": "B", "category": "museum", "lat": 48.86, "lon": "2.33"}"#,
+            "\n"
+        );
+        let records = read_ndjson(data.as_bytes()).unwrap();
+        let pois = records
+            .into_iter()
+            .map(|record| record.unwrap().into_input_poi(&mapping()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pois.len(), 2);
+        assert_eq!(pois[1].coord.lat(), 48.86);
+        assert!(pois[1].properties.is_empty());
+    }
+
+    #[test]
+    fn should_skip_malformed_records() {
+        let data = "id,name,category,lat,lon\n\
+                    1,Musée,museum,48.86,2.33\n\
+                    2,Broken\n\
+                    3,Louvre,museum,48.86,2.34\n";
+        let records = read_csv(data.as_bytes(), ',').unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records[0].is_ok());
+        assert!(records[1].is_err());
+        assert!(records[2].is_ok());
+
+        let data = concat!(
+            r#"{"id": "1", "name": "A", "category": "museum", "lat": 48.86, "lon": 2.33}"#,
+            "\n",
+            r#"{"id": "2", "name": "#,
+            "\n",
+            r#"{"id": "3", "name": "C", "category": "museum", "lat": 48.86, "lon": 2.34}"#,
+            "\n"
+        );
+        let records = read_ndjson(data.as_bytes()).unwrap();
+        let ids = records
+            .into_iter()
+            .filter_map(|record| record.ok()?.field("id"))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec!["1", "3"]);
+    }
+}
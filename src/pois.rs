@@ -34,14 +34,24 @@ use futures::stream::{Stream, StreamExt};
 use mimir::domain::model::configuration::root_doctype;
 use navitia_poi_model::{Model as NavitiaModel, Poi as NavitiaPoi, PoiType as NavitiaPoiType};
 use snafu::{ResultExt, Snafu};
-use std::{collections::HashMap, ops::Deref, path::PathBuf, sync::Arc};
-use tracing::instrument;
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tracing::{instrument, warn};
 
 use crate::{
+    addr_geofinder::{self, AddrGeoFinder},
     admin,
     admin_geofinder::AdminGeoFinder,
     labels,
+    poi_reader::{self, InputFormat, InputPoi},
     settings::{self, admin_settings::AdminSettings},
+    utils::metrics,
+    weights::WeightConfig,
+    wikidata::{enrich_poi, Wikidata},
 };
 use common::document::ContainerDocument;
 use mimir::{
@@ -59,7 +69,7 @@ use places::{
     i18n_properties::I18nProperties,
     poi::{Poi, PoiType},
     street::Street,
-    Place,
+    Address, Members, Place,
 };
 
 #[derive(Debug, Snafu)]
@@ -104,25 +114,38 @@ pub enum Error {
 
     #[snafu(display("Admin Retrieval Error {}", details))]
     AdminRetrieval { details: admin::Error },
+
+    #[snafu(display("Poi Reader Error {}", source))]
+    PoiReader { source: poi_reader::Error },
+
+    #[snafu(display("Address GeoFinder Error {}", source))]
+    AddrGeoFinder { source: addr_geofinder::Error },
 }
 
 /// Stores the pois found in the 'input' file, in Elasticsearch, with the given configuration.
-/// We extract the list of pois from the input file, which is in the Navtia Model format.
-/// We then enrich this list, before importing it.
 #[instrument(skip_all)]
 pub async fn index_pois(
     input: PathBuf,
     client: &ElasticsearchStorage,
     settings: settings::poi2mimir::Settings,
+    wikidata: Option<&Wikidata>,
 ) -> Result<(), Error> {
-    let NavitiaModel { pois, poi_types } =
-        NavitiaModel::try_from_path(&input).map_err(|err| Error::NavitiaModelExtraction {
-            details: format!(
-                "Could not read navitia model from {}: {}",
-                input.display(),
-                err
-            ),
-        })?;
+    let pois = build_pois(input, client, &settings, wikidata).await?;
+    metrics::expect(pois.len());
+
+    import_pois(client, settings.container, futures::stream::iter(pois)).await
+}
+
+/// We extract the list of pois from the input file, which is either in the Navitia Model format,
+/// or a custom dataset (CSV, GeoJSON, NDJSON) depending on the input settings.
+/// We then enrich this list (admins, address, weight, wikidata) before returning it.
+pub async fn build_pois(
+    input: PathBuf,
+    client: &ElasticsearchStorage,
+    settings: &settings::poi2mimir::Settings,
+    wikidata: Option<&Wikidata>,
+) -> Result<Vec<Poi>, Error> {
+    let pois = read_input_pois(&input, settings)?;
 
     let admin_settings = AdminSettings::build(&settings.admins);
 
@@ -132,53 +155,137 @@ pub async fn index_pois(
 
     let admins_geofinder = Arc::new(admins_geofinder);
 
-    let poi_types = Arc::new(poi_types);
-
-    let pois: Vec<_> = futures::stream::iter(pois.into_iter())
-        .then(|(_id, poi)| {
-            let poi_types = poi_types.clone();
-            let admins_geofinder = admins_geofinder.clone();
-            into_poi(
-                poi,
-                poi_types,
-                client,
-                admins_geofinder,
-                settings.max_distance_reverse,
-            )
+    let addr_geofinder =
+        AddrGeoFinder::build(&settings.address_lookup, admins_geofinder.clone(), client)
+            .await
+            .context(AddrGeoFinderSnafu)?;
+
+    let max_distance_reverse = settings.max_distance_reverse;
+
+    let pois: Vec<Result<Poi, Error>> = match addr_geofinder {
+        // The address of each poi is the result of a reverse query to Elasticsearch.
+        None => {
+            futures::stream::iter(pois.into_iter())
+                .then(|poi| {
+                    let admins_geofinder = admins_geofinder.clone();
+                    async move {
+                        let address =
+                            reverse_address(client, &poi.coord, max_distance_reverse).await?;
+                        into_poi(poi, address, &admins_geofinder, &settings.weights)
+                    }
+                })
+                .collect()
+                .await
+        }
+        // The addresses are found in memory, in parallel.
+        Some(addr_geofinder) => {
+            let addr_geofinder = Arc::new(addr_geofinder);
+            let weights = settings.weights.clone();
+            addr_geofinder
+                .par_map(pois, move |addr_geofinder, poi| {
+                    let address = addr_geofinder.get(&poi.coord, max_distance_reverse as f64);
+                    into_poi(poi, address, &admins_geofinder, &weights)
+                })
+                .await
+        }
+    };
+
+    let pois = pois
+        .into_iter()
+        .filter_map(|poi| {
+            poi.map_err(|err| {
+                let reason = match err {
+                    Error::NoAdminFound { .. } => "no_admin",
+                    _ => "no_address",
+                };
+                metrics::rejected("poi", reason);
+            })
+            .ok()
         })
-        .filter_map(|poi_res| futures::future::ready(poi_res.ok()))
-        .collect()
-        .await;
+        .map(|poi| enrich_poi(wikidata, poi))
+        .collect();
 
-    import_pois(client, settings.container, futures::stream::iter(pois)).await
+    Ok(pois)
 }
 
-// FIXME Should not be ElasticsearchStorage, but rather a trait GenerateIndex
-pub async fn import_pois<S>(
-    client: &ElasticsearchStorage,
-    config: ContainerConfig,
-    pois: S,
-) -> Result<(), Error>
-where
-    S: Stream<Item = Poi> + Send + Sync + Unpin + 'static,
-{
-    client
-        .generate_index(&config, pois)
-        .await
-        .context(IndexGenerationSnafu)?;
+/// Computes the weights of the pois found in the 'input' file, without looking up their
+/// addresses, so without any query to Elasticsearch. The `admin` component of the weights is only
+/// available if an admin geofinder is given.
+pub fn weigh_pois(
+    input: &Path,
+    settings: &settings::poi2mimir::Settings,
+    admins_geofinder: Option<&AdminGeoFinder>,
+    wikidata: Option<&Wikidata>,
+) -> Result<Vec<Poi>, Error> {
+    let pois = read_input_pois(input, settings)?
+        .into_iter()
+        .map(|poi| {
+            let administrative_regions = admins_geofinder
+                .map(|admins_geofinder| admins_geofinder.get(&poi.coord))
+                .unwrap_or_default();
+            let poi = Poi {
+                id: places::utils::normalize_id("poi", &poi.id),
+                name: poi.name,
+                coord: poi.coord,
+                administrative_regions,
+                poi_type: poi.poi_type,
+                properties: poi.properties,
+                ..Poi::default()
+            };
+            enrich_poi(wikidata, settings.weights.apply_to_poi(poi))
+        })
+        .collect();
 
-    Ok(())
+    Ok(pois)
+}
+
+// Read the pois from the input file, in the format given by the settings.
+fn read_input_pois(
+    input: &Path,
+    settings: &settings::poi2mimir::Settings,
+) -> Result<Vec<InputPoi>, Error> {
+    match settings.input.format {
+        InputFormat::Navitia => read_navitia_pois(input),
+        _ => {
+            let pois = poi_reader::read_pois(input, &settings.input).context(PoiReaderSnafu)?;
+            metrics::read("poi", pois.len());
+            Ok(pois)
+        }
+    }
+}
+
+// Read the pois from a file in the Navitia Model format. Pois with an unknown type
+// or invalid coordinates are skipped.
+fn read_navitia_pois(input: &Path) -> Result<Vec<InputPoi>, Error> {
+    let NavitiaModel { pois, poi_types } =
+        NavitiaModel::try_from_path(input).map_err(|err| Error::NavitiaModelExtraction {
+            details: format!(
+                "Could not read navitia model from {}: {}",
+                input.display(),
+                err
+            ),
+        })?;
+
+    metrics::read("poi", pois.len());
+    let pois = pois
+        .into_iter()
+        .filter_map(|(_id, poi)| {
+            from_navitia_poi(poi, &poi_types)
+                .map_err(|err| {
+                    warn!("Invalid poi has been ignored: {}", err);
+                    metrics::rejected("poi", "invalid_poi");
+                })
+                .ok()
+        })
+        .collect();
+
+    Ok(pois)
 }
 
-// This function takes a Poi from the navitia model, ie from the CSV deserialization, and returns
-// a Poi from the mimir model, with all the contextual information added.
-async fn into_poi(
+fn from_navitia_poi(
     poi: NavitiaPoi,
-    poi_types: Arc<HashMap<String, NavitiaPoiType>>,
-    client: &ElasticsearchStorage,
-    admins_geofinder: Arc<AdminGeoFinder>,
-    max_distance_reverse: usize,
-) -> Result<Poi, Error> {
+    poi_types: &HashMap<String, NavitiaPoiType>,
+) -> Result<InputPoi, Error> {
     let NavitiaPoi {
         id,
         name,
@@ -196,6 +303,41 @@ async fn into_poi(
         })
         .map(PoiType::from)?;
 
+    let coord = places::coord::Coord::new(coord.lon(), coord.lat())
+        .map_err(|detail| Error::InvalidCoordinate { detail })?;
+
+    Ok(InputPoi {
+        id,
+        name,
+        coord,
+        poi_type,
+        properties,
+    })
+}
+
+// FIXME Should not be ElasticsearchStorage, but rather a trait GenerateIndex
+pub async fn import_pois<S>(
+    client: &ElasticsearchStorage,
+    config: ContainerConfig,
+    pois: S,
+) -> Result<(), Error>
+where
+    S: Stream<Item = Poi> + Send + Sync + Unpin + 'static,
+{
+    client
+        .generate_index(&config, pois)
+        .await
+        .context(IndexGenerationSnafu)?;
+
+    Ok(())
+}
+
+// Search Elasticsearch for the closest street or address of the given location.
+async fn reverse_address(
+    client: &ElasticsearchStorage,
+    coord: &places::coord::Coord,
+    max_distance_reverse: usize,
+) -> Result<Option<Address>, Error> {
     let distance = format!("{}m", max_distance_reverse);
     let dsl = dsl::build_reverse_query(&distance, coord.lat(), coord.lon());
 
@@ -217,16 +359,31 @@ async fn into_poi(
                 .map(Some),
         })?;
 
-    let addr = place.as_ref().and_then(|place| {
-        let place = place;
-        place.address()
-    });
+    Ok(place.and_then(|place| place.address()))
+}
 
-    let coord = places::coord::Coord::new(coord.lon(), coord.lat())
-        .map_err(|detail| Error::InvalidCoordinate { detail })?;
+// This function takes a Poi as read in the input (Navitia model or custom dataset) and its
+// address, and returns a Poi from the mimir model, with all the contextual information added.
+fn into_poi(
+    poi: InputPoi,
+    addr: Option<Address>,
+    admins_geofinder: &AdminGeoFinder,
+    weights: &WeightConfig,
+) -> Result<Poi, Error> {
+    let InputPoi {
+        id,
+        name,
+        coord,
+        poi_type,
+        properties,
+    } = poi;
 
     // We the the admins from the address, or, if we don't have any, from the geofinder.
-    let admins = place.map_or_else(|| admins_geofinder.get(&coord), |addr| addr.admins());
+    let admins = match &addr {
+        Some(Address::Street(street)) => street.admins(),
+        Some(Address::Addr(addr)) => addr.admins(),
+        None => admins_geofinder.get(&coord),
+    };
 
     if admins.is_empty() {
         return Err(Error::NoAdminFound {
@@ -234,14 +391,6 @@ async fn into_poi(
         });
     }
 
-    // The weight is that of the city, or 0.0 if there is no such admin.
-    let weight: f64 = admins
-        .iter()
-        .filter(|adm| adm.is_city())
-        .map(|adm| adm.weight)
-        .next()
-        .unwrap_or(0.0);
-
     let country_codes = places::admin::find_country_codes(admins.iter().map(|a| a.deref()));
 
     let label = labels::format_poi_label(&name, admins.iter().map(|a| a.deref()), &country_codes);
@@ -253,7 +402,7 @@ async fn into_poi(
         coord,
         approx_coord: Some(coord.into()),
         administrative_regions: admins,
-        weight,
+        weight: 0.0,
         zip_codes: vec![],
         poi_type,
         properties,
@@ -266,5 +415,5 @@ async fn into_poi(
         full_label_extra: vec![],
     };
 
-    Ok(poi)
+    Ok(weights.apply_to_poi(poi))
 }
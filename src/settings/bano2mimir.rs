@@ -5,6 +5,9 @@ use snafu::{ResultExt, Snafu};
 use std::{env, path::PathBuf};
 
 use mimir::adapters::secondary::elasticsearch::ElasticsearchStorageConfig;
+use mimir::utils::telemetry::TracingSettings;
+
+use crate::utils::metrics::MetricsSettings;
 
 use super::admin_settings::AdminFromCosmogonyFile;
 
@@ -36,12 +39,20 @@ pub struct Settings {
     #[cfg(feature = "db-storage")]
     pub database: Option<Database>,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     #[serde(default)]
     pub update_templates: bool,
 
     // will read admins from the file if Some(file)
     // will fetch admins from Elasticsearch if None
     pub admins: Option<AdminFromCosmogonyFile>,
+    // will override the weight of the addresses if Some(weights)
+    pub weights: Option<crate::weights::WeightConfig>,
 }
 
 pub fn default_langs() -> Vec<String> {
@@ -107,7 +118,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["bano2mimir", "elasticsearch"],
+            &["bano2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
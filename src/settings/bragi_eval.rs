@@ -0,0 +1,148 @@
+/// This module contains the definition for bragi-eval configuration and command line arguments.
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use snafu::{ResultExt, Snafu};
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
+
+use crate::tuning::TuningSettings;
+use mimir::{
+    adapters::{
+        primary::common::settings::QuerySettings,
+        secondary::elasticsearch::ElasticsearchStorageConfig,
+    },
+    utils::deserialize::deserialize_duration,
+};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Config Compilation Error: {}", source))]
+    ConfigCompilation { source: common::config::Error },
+    #[snafu(display("Config Merge Error: {} [{}]", msg, source))]
+    ConfigMerge {
+        msg: String,
+        source: config::ConfigError,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Settings {
+    pub mode: Option<String>,
+    pub elasticsearch: ElasticsearchStorageConfig,
+    pub query: QuerySettings,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Value>,
+    pub bragi_url: Option<String>,
+    pub profile: Option<String>,
+    pub lang: String,
+    pub limit: i64,
+    pub cutoffs: Vec<usize>,
+    pub concurrency: usize,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub timeout: Duration,
+    pub tuning: TuningSettings,
+}
+
+#[derive(Debug, clap::Parser)]
+#[clap(
+    name = "bragi-eval",
+    about = "Evaluate the relevance of the autocomplete against a golden set",
+    version = VERSION,
+    author = AUTHORS
+    )]
+pub struct Opts {
+    /// Defines the config directory
+    ///
+    /// This directory must contain 'elasticsearch', 'query' and 'bragi-eval' subdirectories.
+    #[clap(parse(from_os_str), short = 'c', long = "config-dir")]
+    pub config_dir: PathBuf,
+
+    /// Defines the run mode in {testing, dev, prod, ...}
+    ///
+    /// If no run mode is provided, a default behavior will be used.
+    #[clap(short = 'm', long = "run-mode")]
+    pub run_mode: Option<String>,
+
+    /// Override settings values using key=value
+    #[clap(
+        short = 's',
+        long = "setting",
+        multiple_values = false,
+        multiple_occurrences = true
+    )]
+    pub settings: Vec<String>,
+
+    /// Golden set, either a JSON file or a TSV file (query, lat, lon, expected ids, doc type)
+    #[clap(parse(from_os_str), short = 'g', long = "golden-set")]
+    pub golden_set: PathBuf,
+
+    /// Where to save the results of this run, as JSON (or the best profile, as TOML, when tuning)
+    #[clap(parse(from_os_str), short = 'o', long = "output")]
+    pub output: Option<PathBuf>,
+
+    /// Results of a previous run, to compare with
+    #[clap(parse(from_os_str), short = 'b', long = "baseline")]
+    pub baseline: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub cmd: Command,
+}
+
+#[derive(Debug, clap::Parser)]
+pub enum Command {
+    /// Execute bragi-eval with the given configuration
+    Run,
+    /// Search the query settings maximizing the tuning objective on the golden set
+    Tune,
+    /// Prints bragi-eval's configuration
+    Config,
+}
+
+impl Settings {
+    // Read the configuration from <config-dir>/bragi-eval, <config-dir>/query and
+    // <config-dir>/elasticsearch
+    pub fn new(opts: &Opts) -> Result<Self, Error> {
+        common::config::config_from(
+            opts.config_dir.as_ref(),
+            &["bragi-eval", "query", "elasticsearch"],
+            opts.run_mode.as_deref(),
+            "MIMIR",
+            opts.settings.clone(),
+        )
+        .context(ConfigCompilationSnafu)?
+        .try_into()
+        .context(ConfigMergeSnafu {
+            msg: "Cannot convert configuration into bragi-eval settings",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_return_ok_with_default_config_dir() {
+        let config_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("config");
+        let opts = Opts {
+            config_dir,
+            run_mode: None,
+            settings: vec![String::from("profile='transit'")],
+            golden_set: PathBuf::from("golden.tsv"),
+            output: None,
+            baseline: None,
+            cmd: Command::Run,
+        };
+        let settings = Settings::new(&opts);
+        assert!(
+            settings.is_ok(),
+            "Expected Ok, Got an Err: {}",
+            settings.unwrap_err()
+        );
+        let settings = settings.unwrap();
+        assert_eq!(settings.profile, Some(String::from("transit")));
+        assert!(settings.profiles.contains_key("transit"));
+    }
+}
@@ -5,6 +5,9 @@ use snafu::{ResultExt, Snafu};
 use std::{env, path::PathBuf};
 
 use mimir::adapters::secondary::elasticsearch::ElasticsearchStorageConfig;
+use mimir::utils::telemetry::TracingSettings;
+
+use crate::utils::metrics::MetricsSettings;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -26,9 +29,17 @@ pub struct Settings {
     pub elasticsearch: ElasticsearchStorageConfig,
     pub container: ContainerConfig,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     pub french_id_retrocompatibility: bool,
     #[serde(default)]
     pub update_templates: bool,
+    // will enrich the admins with the wikidata dump if Some(wikidata)
+    pub wikidata: Option<crate::wikidata::WikidataSettings>,
 }
 
 #[derive(Debug, clap::Parser)]
@@ -90,7 +101,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["cosmogony2mimir", "elasticsearch"],
+            &["cosmogony2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
@@ -68,6 +68,28 @@ pub enum Command {
     Run,
     /// Prints ctlmimir's configuration
     Config,
+    /// Manages the synonyms of each language, found in <config-dir>/elasticsearch/synonyms
+    Synonyms {
+        #[clap(subcommand)]
+        cmd: SynonymsCommand,
+    },
+}
+
+#[derive(Debug, clap::Parser)]
+pub enum SynonymsCommand {
+    /// Prints the synonym rules, of all the languages or of the given one
+    List { lang: Option<String> },
+    /// Adds a rule, such as 'bvd,bd,boulevard' or 'bvd => boulevard', to the synonyms of a language
+    Add { lang: String, rule: String },
+    /// Removes a rule from the synonyms of a language
+    Remove { lang: String, rule: String },
+    /// Applies the synonyms to Elasticsearch, without reindexing
+    Reload {
+        /// Also gives the synonym analyzers to the existing indices missing them, closing these
+        /// indices for a moment
+        #[clap(long)]
+        force: bool,
+    },
 }
 
 // TODO Parameterize the config directory
@@ -1,5 +1,6 @@
 pub mod admin_settings;
 pub mod bano2mimir;
+pub mod bragi_eval;
 pub mod cosmogony2mimir;
 pub mod ctlmimir;
 pub mod ntfs2mimir;
@@ -5,6 +5,9 @@ use snafu::{ResultExt, Snafu};
 use std::{env, path::PathBuf};
 
 use mimir::adapters::secondary::elasticsearch::ElasticsearchStorageConfig;
+use mimir::utils::telemetry::TracingSettings;
+
+use crate::utils::metrics::MetricsSettings;
 
 use super::admin_settings::AdminFromCosmogonyFile;
 
@@ -27,8 +30,16 @@ pub struct Settings {
     pub elasticsearch: ElasticsearchStorageConfig,
     pub container: ContainerConfig,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     pub physical_mode_weight: Option<Vec<PhysicalModeWeight>>,
     #[serde(default)]
+    pub weights: crate::weights::WeightConfig,
+    #[serde(default)]
     pub update_templates: bool,
 
     // will read admins from the file if Some(file)
@@ -83,6 +94,8 @@ pub enum Command {
     Run,
     /// Prints ntfs2mimir's configuration
     Config,
+    /// Computes the stops without indexing them, and prints the distribution of their weights
+    DryRun,
 }
 
 // TODO Parameterize the config directory
@@ -99,7 +112,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["ntfs2mimir", "elasticsearch"],
+            &["ntfs2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
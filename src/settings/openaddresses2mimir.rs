@@ -6,6 +6,9 @@ use std::{env, path::PathBuf};
 
 use super::admin_settings::AdminFromCosmogonyFile;
 use mimir::adapters::secondary::elasticsearch::ElasticsearchStorageConfig;
+use mimir::utils::telemetry::TracingSettings;
+
+use crate::utils::metrics::MetricsSettings;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -41,12 +44,20 @@ pub struct Settings {
     #[cfg(feature = "db-storage")]
     pub database: Option<Database>,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     #[serde(default)]
     pub update_templates: bool,
 
     // will read admins from the file if Some(file)
     // will fetch admins from Elasticsearch if None
     pub admins: Option<AdminFromCosmogonyFile>,
+    // will override the weight of the addresses if Some(weights)
+    pub weights: Option<crate::weights::WeightConfig>,
 }
 
 pub fn default_langs() -> Vec<String> {
@@ -112,7 +123,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["openaddresses2mimir", "elasticsearch"],
+            &["openaddresses2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
@@ -3,6 +3,9 @@ use super::admin_settings::AdminFromCosmogonyFile;
 use mimir::adapters::secondary::elasticsearch::ElasticsearchStorageConfig;
 use mimir::domain::model::configuration::ContainerConfig;
 use mimir::utils::deserialize::usize1000;
+use mimir::utils::telemetry::TracingSettings;
+
+use crate::utils::metrics::MetricsSettings;
 use serde::{Deserialize, Serialize};
 use snafu::{ResultExt, Snafu};
 use std::{env, path::PathBuf};
@@ -31,7 +34,12 @@ pub struct Poi {
     pub import: bool,
     #[serde(default = "usize1000")]
     pub max_distance_reverse: usize, // in meters
+    // where the address of each poi is looked up (Elasticsearch by default)
+    #[serde(default)]
+    pub address_lookup: crate::addr_geofinder::AddressLookup,
     pub config: Option<crate::osm_reader::poi::PoiConfig>,
+    #[serde(default)]
+    pub weights: crate::weights::WeightConfig,
 }
 
 #[cfg(feature = "db-storage")]
@@ -54,12 +62,20 @@ pub struct Settings {
     #[cfg(feature = "db-storage")]
     pub database: Option<Database>,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     #[serde(default)]
     pub update_templates: bool,
 
     // will read admins from the file if Some(file)
     // will fetch admins from Elasticsearch if None
     pub admins: Option<AdminFromCosmogonyFile>,
+    // will enrich the pois with the wikidata dump if Some(wikidata)
+    pub wikidata: Option<crate::wikidata::WikidataSettings>,
 }
 
 pub fn default_langs() -> Vec<String> {
@@ -109,6 +125,8 @@ pub enum Command {
     Run,
     /// Prints osm2mimir's configuration
     Config,
+    /// Computes the pois without indexing them, and prints the distribution of their weights
+    DryRun,
 }
 
 // TODO Parameterize the config directory
@@ -125,7 +143,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["osm2mimir", "elasticsearch"],
+            &["osm2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
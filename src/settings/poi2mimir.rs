@@ -5,10 +5,12 @@ use std::{env, path::PathBuf};
 
 use mimir::{
     adapters::secondary::elasticsearch::ElasticsearchStorageConfig,
-    domain::model::configuration::ContainerConfig, utils::deserialize::usize1000,
+    domain::model::configuration::ContainerConfig,
+    utils::{deserialize::usize1000, telemetry::TracingSettings},
 };
 
 use super::admin_settings::AdminFromCosmogonyFile;
+use crate::utils::metrics::MetricsSettings;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 const AUTHORS: &str = env!("CARGO_PKG_AUTHORS");
@@ -34,13 +36,28 @@ pub struct Settings {
     pub elasticsearch: ElasticsearchStorageConfig,
     pub container: ContainerConfig,
     pub nb_threads: Option<usize>,
+    /// Export of the spans, disabled by default.
+    #[serde(default)]
+    pub tracing: TracingSettings,
+    /// Metrics and progress of the import.
+    #[serde(default)]
+    pub metrics: MetricsSettings,
     #[serde(default)]
     pub update_templates: bool,
     // will read admins from the file if Some(file)
     // will fetch admins from Elasticsearch if None
     pub admins: Option<AdminFromCosmogonyFile>,
+    // will enrich the pois with the wikidata dump if Some(wikidata)
+    pub wikidata: Option<crate::wikidata::WikidataSettings>,
     #[serde(default = "usize1000")]
     pub max_distance_reverse: usize, // in meters
+    // where the address of each poi is looked up (Elasticsearch by default)
+    #[serde(default)]
+    pub address_lookup: crate::addr_geofinder::AddressLookup,
+    #[serde(default)]
+    pub weights: crate::weights::WeightConfig,
+    #[serde(default)]
+    pub input: crate::poi_reader::InputSettings,
 }
 
 pub fn default_langs() -> Vec<String> {
@@ -76,7 +93,7 @@ pub struct Opts {
     )]
     pub settings: Vec<String>,
 
-    /// POI file
+    /// POI file, in the format given by the 'input.format' setting
     #[clap(short = 'i', long = "input", parse(from_os_str))]
     pub input: PathBuf,
 
@@ -90,6 +107,12 @@ pub enum Command {
     Run,
     /// Prints poi2mimir's configuration
     Config,
+    /// Computes the weights of the pois without indexing them, and prints their distribution
+    DryRun {
+        /// Reads the admins from Elasticsearch, for the `admin` component of the weights
+        #[clap(long)]
+        with_admins: bool,
+    },
 }
 
 // TODO Parameterize the config directory
@@ -106,7 +129,7 @@ impl Settings {
 
         common::config::config_from(
             opts.config_dir.as_ref(),
-            &["poi2mimir", "elasticsearch"],
+            &["poi2mimir", "elasticsearch", "tracing", "metrics"],
             opts.run_mode.as_deref(),
             prefix,
             opts.settings.clone(),
@@ -41,6 +41,8 @@ use crate::{
     admin_geofinder::AdminGeoFinder,
     labels,
     settings::{admin_settings::AdminSettings, ntfs2mimir::Settings},
+    utils::metrics,
+    weights::{Components, WeightConfig},
 };
 use mimir::{
     adapters::secondary::elasticsearch::{self, ElasticsearchStorage},
@@ -135,27 +137,39 @@ pub fn build_stop_area_weight(
     result
 }
 
-pub fn make_weight(stop: &mut Stop, stop_areas_weights: &HashMap<String, f64>) {
-    // Admin weight
-    let admin_weight = stop
-        .administrative_regions
-        .iter()
-        .filter(|adm| adm.is_city())
-        .map(|adm| adm.weight)
-        .next()
-        .unwrap_or(0.0);
-    // FIXME: 1024, automagic!
-    // It's a factor used to bring the stop weight and the admin weight in the same order of
-    // magnitude...
-    // We then use a log to compress the distance between low admin weight and high ones.
-    // admin_weight = admin_weight * 1024.0 + 1.0;
-    // admin_weight = admin_weight.log10();
-
-    let weights = stop_areas_weights.get(&*stop.id);
-    if let Some(value) = weights {
-        stop.weight = (value + admin_weight) / (2_f64);
-    } else {
-        stop.weight = admin_weight
+/// Compute, for each stop area, the number of stop times relative to the busiest stop area.
+pub fn build_stop_area_frequency(navitia: &transit_model::Model) -> HashMap<String, f64> {
+    let mut nb_stop_times: HashMap<String, u32> = HashMap::new();
+    for vj in navitia.vehicle_journeys.values() {
+        for stop_time in &vj.stop_times {
+            let sp = &navitia.stop_points[stop_time.stop_point_idx];
+            let stop_area = places::utils::normalize_id("stop_area", &sp.stop_area_id);
+            *nb_stop_times.entry(stop_area).or_insert(0) += 1;
+        }
+    }
+    let max = *nb_stop_times.values().max().unwrap_or(&1) as f64;
+    nb_stop_times
+        .into_iter()
+        .map(|(id, nb)| (id, nb as f64 / max))
+        .collect()
+}
+
+/// Compute the weight of a stop, which must already be attached to its admins.
+/// If no component of the weight is available, the stop keeps its weight.
+pub fn make_weight(
+    stop: &mut Stop,
+    weights: &WeightConfig,
+    stop_areas_weights: &HashMap<String, f64>,
+    stop_areas_frequencies: &HashMap<String, f64>,
+) {
+    let weight = weights.compute(&Components {
+        admins: &stop.administrative_regions,
+        frequency: stop_areas_frequencies.get(&stop.id).copied(),
+        physical_modes: stop_areas_weights.get(&stop.id).copied(),
+        ..Default::default()
+    });
+    if let Some(weight) = weight {
+        stop.weight = weight;
     }
 }
 
@@ -254,48 +268,67 @@ where
 
 /// Stores the stops found in the 'input' directory, in Elasticsearch, with the given
 /// configuration.
-///
-/// The main part of this function is to actually create a list of stops
-/// from the information found in the NTFS directory.
 pub async fn index_ntfs(
     input: &Path,
     settings: &Settings,
     client: &ElasticsearchStorage,
 ) -> Result<(), Error> {
-    let mut stops = {
-        let navitia = transit_model::ntfs::read(&input).map_err(|err| Error::TransitModel {
-            details: format!(
-                "Could not read transit model from {}: {}",
-                input.display(),
-                err
-            ),
-        })?;
-
-        info!("Build stops weight by physical modes");
-        let stop_areas_weights = build_stop_area_weight(&navitia, &settings.physical_mode_weight);
-
-        info!("Make mimir stops from navitia stops");
-        let mut stops: Vec<Stop> = navitia
-            .stop_areas
-            .iter()
-            .map(|(idx, sa)| places::stop::to_mimir(idx, sa, &navitia))
-            .collect();
+    let stops = build_stops(input, settings, client).await?;
+    metrics::read("stop", stops.len());
+    metrics::expect(stops.len());
 
-        info!("Make weights for stops");
-        for stop in &mut stops {
-            stop.coverages.push(settings.container.dataset.clone());
-            make_weight(stop, &stop_areas_weights);
-        }
+    tracing::info!("Beginning to import stops into elasticsearch.");
+    import_stops(client, &settings.container, futures::stream::iter(stops)).await
+}
 
-        stops
-    };
+/// Create the list of stops from the information found in the NTFS directory,
+/// attached to their admins and weighted.
+pub async fn build_stops(
+    input: &Path,
+    settings: &Settings,
+    client: &ElasticsearchStorage,
+) -> Result<Vec<Stop>, Error> {
+    let navitia = transit_model::ntfs::read(&input).map_err(|err| Error::TransitModel {
+        details: format!(
+            "Could not read transit model from {}: {}",
+            input.display(),
+            err
+        ),
+    })?;
+
+    info!("Build stops weight by physical modes");
+    let stop_areas_weights = build_stop_area_weight(&navitia, &settings.physical_mode_weight);
+
+    info!("Build stops frequency");
+    let stop_areas_frequencies = build_stop_area_frequency(&navitia);
+
+    info!("Make mimir stops from navitia stops");
+    let mut stops: Vec<Stop> = navitia
+        .stop_areas
+        .iter()
+        .map(|(idx, sa)| places::stop::to_mimir(idx, sa, &navitia))
+        .collect();
+
+    for stop in &mut stops {
+        stop.coverages.push(settings.container.dataset.clone());
+    }
 
     info!("Attach stops to admins");
     let admin_settings = AdminSettings::build(&settings.admins);
     attach_stops_to_admin(stops.iter_mut(), &admin_settings, client).await?;
 
-    tracing::info!("Beginning to import stops into elasticsearch.");
-    import_stops(client, &settings.container, futures::stream::iter(stops)).await
+    // The weight depends on the admins, so it is computed once the stops are attached.
+    info!("Make weights for stops");
+    for stop in &mut stops {
+        make_weight(
+            stop,
+            &settings.weights,
+            &stop_areas_weights,
+            &stop_areas_frequencies,
+        );
+    }
+
+    Ok(stops)
 }
 
 // FIXME Should not be ElasticsearchStorage, but rather a trait GenerateIndex
@@ -317,7 +350,10 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::stops::make_weight;
+    use crate::{
+        stops::make_weight,
+        weights::{Coefficients, WeightConfig},
+    };
     use cosmogony::ZoneType;
     use places::{admin::Admin, stop::Stop};
     use serial_test::serial;
@@ -328,6 +364,19 @@ mod tests {
         (a - b).abs() < p
     }
 
+    // Same weights as the ones in ntfs2mimir's default configuration, which are the ones of the
+    // previous versions: the weight of the city was not taken into account.
+    fn stop_weights() -> WeightConfig {
+        WeightConfig {
+            coefficients: Coefficients {
+                admin: 0.0,
+                physical_modes: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
     #[tokio::test]
     #[serial]
     async fn test_make_weight_without_physical_mode_weight_and_without_admins() {
@@ -335,7 +384,7 @@ mod tests {
             id: "123".to_string(),
             ..Default::default()
         };
-        make_weight(&mut stop, &HashMap::new());
+        make_weight(&mut stop, &stop_weights(), &HashMap::new(), &HashMap::new());
         assert!(approx_equal(stop.weight, 0.0, 1));
     }
 
@@ -353,8 +402,8 @@ mod tests {
             administrative_regions: vec![Arc::new(admin)],
             ..Default::default()
         };
-        make_weight(&mut stop, &HashMap::new());
-        approx_equal(stop.weight, 2.0930, 4);
+        make_weight(&mut stop, &stop_weights(), &HashMap::new(), &HashMap::new());
+        assert!(approx_equal(stop.weight, 0.0, 4));
     }
 
     #[tokio::test]
@@ -374,7 +423,29 @@ mod tests {
             administrative_regions: vec![Arc::new(admin)],
             ..Default::default()
         };
-        make_weight(&mut stop, &physical_mode_weight);
-        approx_equal(stop.weight, 3.5465, 4);
+        make_weight(
+            &mut stop,
+            &stop_weights(),
+            &physical_mode_weight,
+            &HashMap::new(),
+        );
+        assert!(approx_equal(stop.weight, 2.5, 4));
+
+        // With a coefficient, the weight of the city changes the ranking of the stops.
+        let with_city = WeightConfig {
+            coefficients: Coefficients {
+                admin: 1.0,
+                physical_modes: 0.5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        make_weight(
+            &mut stop,
+            &with_city,
+            &physical_mode_weight,
+            &HashMap::new(),
+        );
+        assert!(approx_equal(stop.weight, 1.31, 4));
     }
 }
@@ -0,0 +1,403 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+//! Automatic tuning of the query settings against a golden set.
+//!
+//! The numeric parameters of the query settings are modified one at a time (coordinate descent)
+//! or all together (random search), and each candidate is evaluated on the golden set. The
+//! candidate is kept when it improves the objective metric.
+
+use futures::Future;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use snafu::{ResultExt, Snafu};
+use std::str::FromStr;
+use tracing::info;
+
+use crate::eval::{Report, ALL};
+use mimir::adapters::primary::common::settings::QuerySettings;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display(
+        "Unknown tuning objective '{}', expected mrr, ndcg or recall@<k>",
+        objective
+    ))]
+    UnknownObjective { objective: String },
+
+    #[snafu(display("Tuning parameter '{}' is not a number of the query settings", path))]
+    UnknownParameter { path: String },
+
+    #[snafu(display("Invalid query settings: {}", source))]
+    InvalidSettings { source: serde_json::Error },
+
+    #[snafu(display("Could not serialize the profile: {}", source))]
+    ProfileSerialization { source: toml::ser::Error },
+
+    #[snafu(display("Evaluation Error: {}", details))]
+    Evaluation { details: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// Each parameter in turn is increased and decreased by a step, which is halved when no
+    /// parameter can be improved anymore.
+    CoordinateDescent,
+    /// All the parameters are multiplied by random factors around the best candidate.
+    Random,
+}
+
+/// The metric, computed over all the queries, which the tuning maximizes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Objective {
+    Mrr,
+    Ndcg,
+    Recall(usize),
+}
+
+impl FromStr for Objective {
+    type Err = Error;
+
+    fn from_str(objective: &str) -> Result<Self, Self::Err> {
+        match objective {
+            "mrr" => Ok(Objective::Mrr),
+            "ndcg" => Ok(Objective::Ndcg),
+            _ => objective
+                .strip_prefix("recall@")
+                .and_then(|k| k.parse().ok())
+                .map(Objective::Recall)
+                .ok_or_else(|| Error::UnknownObjective {
+                    objective: objective.to_string(),
+                }),
+        }
+    }
+}
+
+impl Objective {
+    pub fn score(&self, report: &Report) -> f64 {
+        report
+            .metrics
+            .get(ALL)
+            .map(|metrics| match self {
+                Objective::Mrr => metrics.mrr,
+                Objective::Ndcg => metrics.ndcg,
+                Objective::Recall(k) => metrics.recall.get(k).copied().unwrap_or_default(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TuningSettings {
+    pub strategy: Strategy,
+    /// 'mrr', 'ndcg' or 'recall@<k>', where k is one of the cutoffs.
+    pub objective: String,
+    /// Maximum number of evaluations of the golden set.
+    pub iterations: usize,
+    /// Relative change applied to the parameters (eg. 0.2 for +/- 20%).
+    pub step: f64,
+    /// Seed of the random search, so that a tuning can be replayed.
+    pub seed: u64,
+    /// Name of the ranking profile emitted with the best parameters.
+    pub profile_name: String,
+    /// Dotted paths of the parameters to tune in the query settings.
+    pub parameters: Vec<String>,
+}
+
+/// The outcome of a tuning: the best query settings found, and their evaluation compared to
+/// the one of the initial settings.
+#[derive(Debug)]
+pub struct Tuning {
+    pub initial: QuerySettings,
+    pub best: QuerySettings,
+    pub initial_report: Report,
+    pub best_report: Report,
+    pub evaluations: usize,
+}
+
+/// Searches the query settings maximizing the objective. 'evaluate' runs the golden set with
+/// the given settings.
+pub async fn tune<F, Fut, E>(
+    initial: QuerySettings,
+    settings: &TuningSettings,
+    mut evaluate: F,
+) -> Result<Tuning, Error>
+where
+    F: FnMut(QuerySettings) -> Fut,
+    Fut: Future<Output = Result<Report, E>>,
+    E: std::fmt::Display,
+{
+    let objective: Objective = settings.objective.parse()?;
+    let base = serde_json::to_value(&initial).context(InvalidSettingsSnafu)?;
+    let mut values = settings
+        .parameters
+        .iter()
+        .map(|path| get(&base, path).ok_or_else(|| Error::UnknownParameter { path: path.clone() }))
+        .collect::<Result<Vec<f64>, Error>>()?;
+
+    let mut run = |values: &[f64]| {
+        let candidate = with_values(&base, &settings.parameters, values);
+        let fut = candidate.map(&mut evaluate);
+        async move {
+            fut?.await.map_err(|err| Error::Evaluation {
+                details: err.to_string(),
+            })
+        }
+    };
+
+    let initial_report = run(&values).await?;
+    let mut best_report = initial_report.clone();
+    let mut best_score = objective.score(&best_report);
+    let mut evaluations = 1;
+    info!("initial {}: {:.4}", settings.objective, best_score);
+
+    let mut rng = StdRng::seed_from_u64(settings.seed);
+    let mut step = settings.step;
+    let mut improved_in_pass = false;
+    let mut parameter = 0;
+
+    while evaluations < settings.iterations && step > 1e-3 && !values.is_empty() {
+        let candidates: Vec<Vec<f64>> = match settings.strategy {
+            Strategy::CoordinateDescent => [1.0 + step, 1.0 - step]
+                .iter()
+                .map(|factor| {
+                    let mut candidate = values.clone();
+                    candidate[parameter] *= factor;
+                    candidate
+                })
+                .collect(),
+            Strategy::Random => vec![values
+                .iter()
+                .map(|value| value * (rng.gen_range(-step..=step)).exp())
+                .collect()],
+        };
+
+        for candidate in candidates {
+            if evaluations >= settings.iterations {
+                break;
+            }
+            let report = run(&candidate).await?;
+            evaluations += 1;
+            let score = objective.score(&report);
+            if score > best_score {
+                info!(
+                    "evaluation {}: {} improved to {:.4}",
+                    evaluations, settings.objective, score
+                );
+                best_score = score;
+                best_report = report;
+                values = candidate;
+                improved_in_pass = true;
+                break;
+            }
+        }
+
+        if settings.strategy == Strategy::CoordinateDescent {
+            parameter += 1;
+            if parameter == values.len() {
+                parameter = 0;
+                if !improved_in_pass {
+                    step /= 2.0;
+                }
+                improved_in_pass = false;
+            }
+        }
+    }
+
+    let best = serde_json::from_value(with_values(&base, &settings.parameters, &values)?)
+        .context(InvalidSettingsSnafu)?;
+
+    Ok(Tuning {
+        initial,
+        best,
+        initial_report,
+        best_report,
+        evaluations,
+    })
+}
+
+impl Tuning {
+    /// The ranking profile holding the parameters which differ from the initial settings, as
+    /// it would be written in the query settings.
+    pub fn profile(&self, name: &str) -> Result<String, Error> {
+        let initial = serde_json::to_value(&self.initial).context(InvalidSettingsSnafu)?;
+        let best = serde_json::to_value(&self.best).context(InvalidSettingsSnafu)?;
+
+        let mut profile = Value::Object(Map::new());
+        diff(&initial, &best, &mut Vec::new(), &mut profile);
+
+        let mut profiles = Map::new();
+        profiles.insert(name.to_string(), profile);
+        let mut root = Map::new();
+        root.insert("profiles".to_string(), Value::Object(profiles));
+        // Going through toml's own values puts the numbers of each table before its subtables.
+        let root = toml::Value::try_from(Value::Object(root)).context(ProfileSerializationSnafu)?;
+        toml::to_string(&root).context(ProfileSerializationSnafu)
+    }
+}
+
+// Collects in 'out' the numbers of 'after' which differ from those of 'before'.
+fn diff(before: &Value, after: &Value, path: &mut Vec<String>, out: &mut Value) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in after {
+                if let Some(previous) = before.get(key) {
+                    path.push(key.clone());
+                    diff(previous, value, path, out);
+                    path.pop();
+                }
+            }
+        }
+        (Value::Number(before), Value::Number(after)) if before != after => {
+            set(out, &path.join("."), after.as_f64().unwrap_or_default());
+        }
+        _ => {}
+    }
+}
+
+fn get(settings: &Value, path: &str) -> Option<f64> {
+    path.split('.')
+        .try_fold(settings, |value, key| value.get(key))
+        .and_then(Value::as_f64)
+}
+
+fn set(settings: &mut Value, path: &str, number: f64) {
+    let mut value = settings;
+    for key in path.split('.') {
+        if !value.is_object() {
+            *value = Value::Object(Map::new());
+        }
+        value = value
+            .as_object_mut()
+            .expect("object")
+            .entry(key.to_string())
+            .or_insert(Value::Null);
+    }
+    *value = Value::from(number);
+}
+
+fn with_values(base: &Value, paths: &[String], values: &[f64]) -> Result<QuerySettings, Error> {
+    let mut settings = base.clone();
+    for (path, value) in paths.iter().zip(values) {
+        set(&mut settings, path, *value);
+    }
+    serde_json::from_value(settings).context(InvalidSettingsSnafu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::CaseResult;
+
+    fn tuning_settings(strategy: Strategy) -> TuningSettings {
+        TuningSettings {
+            strategy,
+            objective: "mrr".to_string(),
+            iterations: 60,
+            step: 0.5,
+            seed: 42,
+            profile_name: "tuned".to_string(),
+            parameters: vec![
+                "type_query.boosts.stop".to_string(),
+                "string_query.boosts.name".to_string(),
+            ],
+        }
+    }
+
+    // A fake golden set: the expected stop is found first once the stop boost is above 25.
+    fn evaluate(settings: QuerySettings) -> futures::future::Ready<Result<Report, Error>> {
+        let found = if settings.type_query.boosts.stop > 25.0 {
+            vec!["stop:1".to_string()]
+        } else {
+            vec!["poi:1".to_string(), "stop:1".to_string()]
+        };
+        let result = CaseResult {
+            query: "gare".to_string(),
+            doc_type: "stop".to_string(),
+            expected: vec!["stop:1".to_string()],
+            found,
+        };
+        futures::future::ready(Ok(Report::new(vec![result], &[1])))
+    }
+
+    #[test]
+    fn should_parse_objectives() {
+        assert_eq!("mrr".parse::<Objective>().unwrap(), Objective::Mrr);
+        assert_eq!(
+            "recall@5".parse::<Objective>().unwrap(),
+            Objective::Recall(5)
+        );
+        assert!("precision".parse::<Objective>().is_err());
+    }
+
+    #[tokio::test]
+    async fn should_improve_the_objective() {
+        for strategy in [Strategy::CoordinateDescent, Strategy::Random] {
+            let tuning = tune(
+                QuerySettings::default(),
+                &tuning_settings(strategy),
+                evaluate,
+            )
+            .await
+            .unwrap();
+
+            assert!(tuning.best.type_query.boosts.stop > 25.0);
+            assert_eq!(tuning.best_report.metrics[ALL].mrr, 1.0);
+            assert_eq!(tuning.initial_report.metrics[ALL].mrr, 0.5);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_emit_the_changed_parameters() {
+        let tuning = tune(
+            QuerySettings::default(),
+            &tuning_settings(Strategy::CoordinateDescent),
+            evaluate,
+        )
+        .await
+        .unwrap();
+
+        let profile = tuning.profile("tuned").unwrap();
+        assert!(profile.contains("[profiles.tuned.type_query.boosts]"));
+        assert!(!profile.contains("string_query"));
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_parameters() {
+        let mut settings = tuning_settings(Strategy::Random);
+        settings.parameters = vec!["type_query.boosts.bus".to_string()];
+
+        assert!(tune(QuerySettings::default(), &settings, evaluate)
+            .await
+            .is_err());
+    }
+}
@@ -1,15 +1,45 @@
-use snafu::Snafu;
+use mimir::utils::telemetry::TracingSettings;
+use opentelemetry::{
+    global,
+    propagation::{Extractor, TextMapPropagator},
+    sdk::{propagation::TraceContextPropagator, trace, Resource},
+    trace::TraceError,
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use snafu::{ResultExt, Snafu};
 use tracing::metadata::LevelFilter;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{layer::SubscriberExt, EnvFilter};
+use url::Url;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
     #[snafu(display("Could not init log file: {}", source))]
     InitLog { source: std::io::Error },
+
+    #[snafu(display("Could not init the export of the traces: {}", source))]
+    InitTracing { source: TraceError },
+}
+
+/// Keeps the logs and the traces flowing until it is dropped, which flushes them.
+pub struct LoggerGuard {
+    _worker: tracing_appender::non_blocking::WorkerGuard,
+    tracing: bool,
+}
+
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        if self.tracing {
+            global::shutdown_tracer_provider();
+        }
+    }
 }
 
 // FIXME Remove all expects
-pub fn logger_init() -> Result<tracing_appender::non_blocking::WorkerGuard, Error> {
+/// When the export of the traces is enabled, this must be called from within a tokio runtime,
+/// and the guard must be dropped before the runtime.
+pub fn logger_init(tracing: &TracingSettings) -> Result<LoggerGuard, Error> {
     let default_level = LevelFilter::INFO;
     let rust_log =
         std::env::var(EnvFilter::DEFAULT_ENV).unwrap_or_else(|_| default_level.to_string());
@@ -30,16 +60,150 @@ pub fn logger_init() -> Result<tracing_appender::non_blocking::WorkerGuard, Erro
         .with_target(false) // Don't include event targets.
         .compact();
 
+    let otlp_layer = match &tracing.otlp_endpoint {
+        Some(endpoint) => {
+            global::set_text_map_propagator(TraceContextPropagator::new());
+            let tracer = otlp_tracer(tracing, endpoint)?;
+            Some(tracing_opentelemetry::layer().with_tracer(tracer))
+        }
+        None => None,
+    };
+
     let subscriber = tracing_subscriber::registry()
         .with(
             tracing_subscriber::fmt::layer()
                 .with_writer(non_blocking)
                 .event_format(event_format),
         )
+        .with(otlp_layer)
         .with(env_filter);
 
     tracing::subscriber::set_global_default(subscriber)
         .expect("Failed to set global tracing subscriber.");
 
-    Ok(guard)
+    Ok(LoggerGuard {
+        _worker: guard,
+        tracing: tracing.is_enabled(),
+    })
+}
+
+// Builds the tracer sending the spans by batches to the collector.
+fn otlp_tracer(settings: &TracingSettings, endpoint: &Url) -> Result<trace::Tracer, Error> {
+    let service_name = settings
+        .service_name
+        .clone()
+        .unwrap_or_else(super::executable_name);
+
+    // The sampling decision of the caller is kept.
+    let sampler = trace::Sampler::ParentBased(Box::new(trace::Sampler::TraceIdRatioBased(
+        settings.sampling_ratio,
+    )));
+
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .http()
+                .with_endpoint(endpoint.as_str())
+                .with_timeout(settings.timeout),
+        )
+        .with_trace_config(
+            trace::config()
+                .with_sampler(sampler)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    service_name,
+                )])),
+        )
+        .install_batch(opentelemetry::runtime::Tokio)
+        .context(InitTracingSnafu)
+}
+
+/// Makes the span a child of the remote span given by the W3C 'traceparent' header, if any.
+/// This has no effect when the export of the traces is disabled.
+pub fn set_remote_parent(span: &tracing::Span, headers: &http::HeaderMap) {
+    let parent =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(parent);
+}
+
+struct HeaderExtractor<'a>(&'a http::HeaderMap);
+
+impl<'a> Extractor for HeaderExtractor<'a> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use tracing_subscriber::Registry;
+    use warp::Filter;
+
+    #[test]
+    fn should_extract_traceparent() {
+        let propagator = TraceContextPropagator::new();
+        let mut headers = http::HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01"
+                .parse()
+                .unwrap(),
+        );
+
+        let context = propagator.extract(&HeaderExtractor(&headers));
+        let span_context = context.span().span_context().clone();
+
+        assert!(span_context.is_remote());
+        assert_eq!(
+            span_context.trace_id().to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+    }
+
+    // A stand-in for the collector records the batches of spans it receives.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_export_spans_to_collector() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let collector = warp::post()
+            .and(warp::path!("v1" / "traces"))
+            .and(warp::body::bytes())
+            .map(move |body: warp::hyper::body::Bytes| {
+                sender.send(body).unwrap();
+                warp::reply()
+            });
+        let (addr, server) = warp::serve(collector).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        let endpoint: Url = format!("http://{}/v1/traces", addr)
+            .parse()
+            .expect("collector url");
+        let settings = TracingSettings {
+            otlp_endpoint: Some(endpoint.clone()),
+            service_name: Some(String::from("logger-test")),
+            ..TracingSettings::default()
+        };
+        let tracer = otlp_tracer(&settings, &endpoint).unwrap();
+        let subscriber =
+            Registry::default().with(tracing_opentelemetry::layer().with_tracer(tracer));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("bulk_block", index = "munin_addr").in_scope(|| {});
+        });
+
+        // Shutting down the provider flushes the spans.
+        tokio::task::spawn_blocking(global::shutdown_tracer_provider)
+            .await
+            .unwrap();
+
+        let body = receiver.recv().await.expect("exported spans");
+        let contains = |text: &str| body.windows(text.len()).any(|w| w == text.as_bytes());
+        assert!(contains("bulk_block"));
+        assert!(contains("logger-test"));
+    }
 }
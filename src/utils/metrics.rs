@@ -0,0 +1,315 @@
+//! Metrics of the importers: documents read and rejected from the input, and duration of the
+//! phases of the import. The documents indexed and the bulk retries are counted by the
+//! Elasticsearch adapter. The metrics are exposed on an HTTP port, and / or pushed to a
+//! Pushgateway, and the progress of the indexing is logged periodically.
+
+use futures::future::Future;
+use lazy_static::lazy_static;
+use mimir::{
+    adapters::secondary::elasticsearch::metrics::indexed_documents,
+    utils::deserialize::deserialize_duration,
+};
+use prometheus::{core::Collector, Encoder, TextEncoder};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use url::Url;
+use warp::Filter;
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not serve the metrics on port {}: {}", port, source))]
+    Bind { port: u16, source: warp::Error },
+}
+
+lazy_static! {
+    static ref DOCUMENTS_READ: prometheus::IntCounterVec = prometheus::register_int_counter_vec!(
+        "mimirsbrunn_documents_read_total",
+        "Number of documents read from the input.",
+        &["doc_type"]
+    )
+    .unwrap();
+    static ref DOCUMENTS_REJECTED: prometheus::IntCounterVec =
+        prometheus::register_int_counter_vec!(
+            "mimirsbrunn_documents_rejected_total",
+            "Number of documents of the input which are not indexed, by reason.",
+            &["doc_type", "reason"]
+        )
+        .unwrap();
+    static ref DOCUMENTS_EXPECTED: prometheus::IntGauge = prometheus::register_int_gauge!(
+        "mimirsbrunn_documents_expected",
+        "Number of documents to index, when it is known before indexing them."
+    )
+    .unwrap();
+    static ref PHASE_DURATION: prometheus::GaugeVec = prometheus::register_gauge_vec!(
+        "mimirsbrunn_phase_duration_seconds",
+        "Duration of the phases of the import.",
+        &["phase"]
+    )
+    .unwrap();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsSettings {
+    /// Port of the HTTP server exposing the metrics on '/metrics'.
+    pub port: Option<u16>,
+    /// Pushgateway receiving the metrics, with the name of the executable as job.
+    pub pushgateway_url: Option<Url>,
+    /// Interval between two pushes to the Pushgateway.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub push_interval: Duration,
+    /// Interval between two logs of the progress of the indexing, which are disabled if zero.
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub progress_interval: Duration,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            port: None,
+            pushgateway_url: None,
+            push_interval: Duration::from_secs(15),
+            progress_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Counts documents read from the input.
+pub fn read(doc_type: &str, count: usize) {
+    DOCUMENTS_READ
+        .with_label_values(&[doc_type])
+        .inc_by(count as u64);
+}
+
+/// Counts a document of the input which is not indexed.
+pub fn rejected(doc_type: &str, reason: &str) {
+    DOCUMENTS_REJECTED
+        .with_label_values(&[doc_type, reason])
+        .inc();
+}
+
+/// Adds documents to the number of documents to index, which gives the ETA of the indexing.
+pub fn expect(count: usize) {
+    DOCUMENTS_EXPECTED.add(count as i64);
+}
+
+/// Measures the duration of a phase of the import, until it is dropped.
+#[must_use = "The phase ends when it is dropped."]
+pub struct Phase {
+    name: &'static str,
+    start: Instant,
+}
+
+pub fn phase(name: &'static str) -> Phase {
+    info!("Starting phase '{}'", name);
+    Phase {
+        name,
+        start: Instant::now(),
+    }
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        PHASE_DURATION
+            .with_label_values(&[self.name])
+            .set(elapsed.as_secs_f64());
+        info!("Phase '{}' took {:.1?}", self.name, elapsed);
+    }
+}
+
+fn encode() -> Vec<u8> {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .expect("metrics encoding");
+    buffer
+}
+
+/// The tasks exposing the metrics and logging the progress, which run until `stop` is called.
+pub struct Metrics {
+    tasks: Vec<JoinHandle<()>>,
+    pushgateway_url: Option<Url>,
+}
+
+impl Metrics {
+    /// Starts exposing the metrics, which must be done from within a tokio runtime.
+    pub fn start(settings: &MetricsSettings) -> Result<Self, Error> {
+        let mut tasks = Vec::new();
+
+        if let Some(port) = settings.port {
+            let route = warp::get()
+                .and(warp::path("metrics"))
+                .and(warp::path::end())
+                .map(|| {
+                    warp::reply::with_header(
+                        encode(),
+                        "content-type",
+                        TextEncoder::new().format_type(),
+                    )
+                });
+            let (addr, server) = warp::serve(route)
+                .try_bind_ephemeral(SocketAddr::from(([0, 0, 0, 0], port)))
+                .context(BindSnafu { port })?;
+            info!("Serving the metrics on {}", addr);
+            tasks.push(tokio::spawn(server));
+        }
+
+        if let Some(url) = settings.pushgateway_url.clone() {
+            let interval = settings.push_interval;
+            tasks.push(tokio::spawn(async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    push(&url).await;
+                }
+            }));
+        }
+
+        if !settings.progress_interval.is_zero() {
+            tasks.push(tokio::spawn(log_progress(settings.progress_interval)));
+        }
+
+        Ok(Metrics {
+            tasks,
+            pushgateway_url: settings.pushgateway_url.clone(),
+        })
+    }
+
+    /// Stops the tasks, after a last push of the metrics.
+    pub async fn stop(self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+        if let Some(url) = &self.pushgateway_url {
+            push(url).await;
+        }
+    }
+}
+
+/// Runs the import while exposing its metrics.
+pub async fn with_metrics<F>(
+    settings: &MetricsSettings,
+    run: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: Future<Output = Result<(), Box<dyn std::error::Error>>>,
+{
+    let metrics = Metrics::start(settings).map_err(Box::new)?;
+    let res = run.await;
+    metrics.stop().await;
+    res
+}
+
+// A failed push is not an error of the import: the metrics are pushed again later.
+async fn push(url: &Url) {
+    let url = format!(
+        "{}/metrics/job/{}",
+        url.as_str().trim_end_matches('/'),
+        super::executable_name()
+    );
+    let res = reqwest::Client::new()
+        .put(&url)
+        .header("content-type", TextEncoder::new().format_type())
+        .body(encode())
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status);
+    if let Err(err) = res {
+        warn!("Could not push the metrics to {}: {}", url, err);
+    }
+}
+
+async fn log_progress(interval: Duration) {
+    let mut previous = indexed_documents();
+    loop {
+        tokio::time::sleep(interval).await;
+        let indexed = indexed_documents();
+        let read: u64 = DOCUMENTS_READ
+            .collect()
+            .iter()
+            .flat_map(|family| family.get_metric().iter())
+            .map(|metric| metric.get_counter().get_value() as u64)
+            .sum();
+        let expected = DOCUMENTS_EXPECTED.get().max(0) as u64;
+        info!(
+            "{}",
+            progress(read, indexed, indexed - previous, interval, expected)
+        );
+        previous = indexed;
+    }
+}
+
+// The ETA is only known when the number of documents to index is known.
+fn progress(read: u64, indexed: u64, delta: u64, interval: Duration, expected: u64) -> String {
+    let throughput = delta as f64 / interval.as_secs_f64();
+    let mut progress = format!(
+        "Progress: {} documents read, {} indexed, {:.0} documents/s",
+        read, indexed, throughput
+    );
+    if expected > indexed && throughput > 0.0 {
+        let eta = Duration::from_secs_f64((expected - indexed) as f64 / throughput);
+        progress.push_str(&format!(
+            ", {}/{} ({:.0}%), ETA {}s",
+            indexed,
+            expected,
+            100.0 * indexed as f64 / expected as f64,
+            eta.as_secs()
+        ));
+    }
+    progress
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_format_progress_with_eta() {
+        let interval = Duration::from_secs(10);
+
+        assert_eq!(
+            progress(5000, 1000, 500, interval, 0),
+            "Progress: 5000 documents read, 1000 indexed, 50 documents/s"
+        );
+        assert_eq!(
+            progress(5000, 1000, 500, interval, 2000),
+            "Progress: 5000 documents read, 1000 indexed, 50 documents/s, 1000/2000 (50%), ETA 20s"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn should_push_metrics_to_pushgateway() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let pushgateway = warp::put()
+            .and(warp::path!("metrics" / "job" / String))
+            .and(warp::body::bytes())
+            .map(move |job: String, body: warp::hyper::body::Bytes| {
+                sender.send((job, body)).unwrap();
+                warp::reply()
+            });
+        let (addr, server) = warp::serve(pushgateway).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        read("addr", 3);
+        rejected("addr", "no_street_name");
+        let settings = MetricsSettings {
+            pushgateway_url: Some(format!("http://{}", addr).parse().unwrap()),
+            progress_interval: Duration::ZERO,
+            ..MetricsSettings::default()
+        };
+        Metrics::start(&settings).unwrap().stop().await;
+
+        let (job, body) = receiver.recv().await.expect("pushed metrics");
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(job, crate::utils::executable_name());
+        assert!(body.contains(
+            r#"mimirsbrunn_documents_rejected_total{doc_type="addr",reason="no_street_name"} 1"#
+        ));
+    }
+}
@@ -1,5 +1,17 @@
 pub mod fs;
 pub mod launch;
 pub mod logger;
+pub mod metrics;
 pub mod slice;
 pub mod template;
+
+/// Name of the running executable, which identifies the process in the traces and the metrics.
+pub fn executable_name() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| String::from("mimirsbrunn"))
+}
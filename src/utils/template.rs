@@ -1,4 +1,7 @@
-use mimir::adapters::{primary::templates, secondary::elasticsearch::ElasticsearchStorage};
+use mimir::adapters::{
+    primary::{synonyms, templates},
+    secondary::elasticsearch::ElasticsearchStorage,
+};
 use std::path::PathBuf;
 
 pub async fn update_templates(
@@ -15,6 +18,17 @@ pub async fn update_templates(
         .await
         .map_err(Box::new)?;
 
+    // The index templates are composed of the synonyms component template, so the indices created
+    // afterwards get the synonym analyzers. The existing indices are left as they are: giving them
+    // the analyzers means closing them (see 'ctlmimir synonyms reload --force').
+    let path: PathBuf = db_file.join("elasticsearch").join("synonyms");
+
+    tracing::info!("Beginning synonyms imports from {:?}", &path);
+    let sets = synonyms::read_dir(&path).await.map_err(Box::new)?;
+    synonyms::import(client, &sets, client.config.synonyms_path.as_deref())
+        .await
+        .map_err(Box::new)?;
+
     let path: PathBuf = db_file
         .join("elasticsearch")
         .join("templates")
@@ -0,0 +1,398 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+/// In this module we put the computation of the weight of places (pois, stops, addresses).
+///
+/// The weight is described declaratively in the importer's settings. It is made of
+/// components, each one multiplied by its coefficient:
+///
+/// * `admin`: the weight of the city (or of the admin at `admin_level`), which is derived
+///   from its population,
+/// * `poi_type`: a base weight looked up by poi type id in `poi_types`,
+/// * `frequency`: the number of stop times at a stop area, relative to the busiest one,
+/// * `physical_modes`: the sum of the physical mode weights of a stop area.
+///
+/// The terms that are available for a place are combined (`sum`, `mean`, `max` or `product`),
+/// the result is multiplied by the multiplier of each matching tag in `tags`, and finally
+/// clamped between `min` and `max`. For example:
+///
+/// ```toml
+/// [weights]
+///   combine = "mean"
+///   [weights.coefficients]
+///     admin = 1.0
+///     poi_type = 1.0
+///   [weights.poi_types]
+///     "poi_type:amenity:hospital" = 0.8
+///   [weights.tags]
+///     wikidata = 1.5
+///     "tourism=museum" = 2.0
+/// ```
+use places::{addr::Addr, admin::Admin, poi::Poi};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, iter::FromIterator, sync::Arc};
+
+/// How the terms of the weight are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Combination {
+    Sum,
+    Mean,
+    Max,
+    Product,
+}
+
+impl Default for Combination {
+    fn default() -> Self {
+        Combination::Mean
+    }
+}
+
+impl Combination {
+    /// Combine the terms, returns None if there is nothing to combine.
+    pub fn apply(self, terms: &[f64]) -> Option<f64> {
+        if terms.is_empty() {
+            return None;
+        }
+        let res = match self {
+            Combination::Sum => terms.iter().sum(),
+            Combination::Mean => terms.iter().sum::<f64>() / terms.len() as f64,
+            Combination::Max => terms.iter().copied().fold(f64::MIN, f64::max),
+            Combination::Product => terms.iter().product(),
+        };
+        Some(res)
+    }
+}
+
+/// Coefficient applied to each component of the weight. A component with a
+/// coefficient of 0 is not taken into account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Coefficients {
+    #[serde(default)]
+    pub admin: f64,
+    #[serde(default)]
+    pub poi_type: f64,
+    #[serde(default)]
+    pub frequency: f64,
+    #[serde(default)]
+    pub physical_modes: f64,
+}
+
+impl Default for Coefficients {
+    // By default, the weight of a place is that of its city.
+    fn default() -> Self {
+        Coefficients {
+            admin: 1.0,
+            poi_type: 0.0,
+            frequency: 0.0,
+            physical_modes: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WeightConfig {
+    #[serde(default)]
+    pub combine: Combination,
+    #[serde(default)]
+    pub coefficients: Coefficients,
+    /// Level of the admin used for the `admin` component. If None, the city is used.
+    #[serde(default)]
+    pub admin_level: Option<u32>,
+    /// Base weight by poi type id.
+    #[serde(default)]
+    pub poi_types: BTreeMap<String, f64>,
+    /// Base weight of the poi types missing from `poi_types`.
+    #[serde(default)]
+    pub default_poi_type: Option<f64>,
+    /// Multipliers applied when a place has the given tag. The key is either
+    /// a tag key (eg 'wikidata'), or a 'key=value' pair (eg 'tourism=museum').
+    #[serde(default)]
+    pub tags: BTreeMap<String, f64>,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+/// The raw values a weight is computed from. Components which are not relevant
+/// for a kind of place are left to None.
+#[derive(Debug, Clone, Default)]
+pub struct Components<'a> {
+    pub admins: &'a [Arc<Admin>],
+    pub poi_type: Option<&'a str>,
+    pub tags: Option<&'a BTreeMap<String, String>>,
+    pub frequency: Option<f64>,
+    pub physical_modes: Option<f64>,
+}
+
+impl WeightConfig {
+    /// Compute the weight from the given components. Returns None if none of
+    /// the configured components is available, in which case the place keeps its weight.
+    pub fn compute(&self, components: &Components) -> Option<f64> {
+        let coefficients = &self.coefficients;
+        let admin = self
+            .admin_weight(components.admins)
+            .map(|w| w * coefficients.admin);
+        let poi_type = components
+            .poi_type
+            .and_then(|id| self.poi_types.get(id).copied().or(self.default_poi_type))
+            .map(|w| w * coefficients.poi_type);
+        let frequency = components.frequency.map(|f| f * coefficients.frequency);
+        let physical_modes = components
+            .physical_modes
+            .map(|w| w * coefficients.physical_modes);
+
+        let terms = [
+            (coefficients.admin, admin),
+            (coefficients.poi_type, poi_type),
+            (coefficients.frequency, frequency),
+            (coefficients.physical_modes, physical_modes),
+        ]
+        .iter()
+        .filter(|(coefficient, _)| *coefficient != 0.0)
+        .filter_map(|(_, term)| *term)
+        .collect::<Vec<_>>();
+
+        let weight = self.combine.apply(&terms)? * self.tags_multiplier(components.tags);
+        let weight = self.min.map_or(weight, |min| weight.max(min));
+        let weight = self.max.map_or(weight, |max| weight.min(max));
+        Some(weight)
+    }
+
+    pub fn apply_to_poi(&self, poi: Poi) -> Poi {
+        let weight = self.compute(&Components {
+            admins: &poi.administrative_regions,
+            poi_type: Some(&poi.poi_type.id),
+            tags: Some(&poi.properties),
+            ..Default::default()
+        });
+        match weight {
+            Some(weight) => Poi { weight, ..poi },
+            None => poi,
+        }
+    }
+
+    /// The weight of an address is also applied to its street.
+    pub fn apply_to_addr(&self, mut addr: Addr) -> Addr {
+        let weight = self.compute(&Components {
+            admins: &addr.street.administrative_regions,
+            ..Default::default()
+        });
+        if let Some(weight) = weight {
+            addr.weight = weight;
+            addr.street.weight = weight;
+        }
+        addr
+    }
+
+    fn admin_weight(&self, admins: &[Arc<Admin>]) -> Option<f64> {
+        admins
+            .iter()
+            .find(|admin| match self.admin_level {
+                Some(level) => admin.level == level,
+                None => admin.is_city(),
+            })
+            .map(|admin| admin.weight)
+    }
+
+    fn tags_multiplier(&self, tags: Option<&BTreeMap<String, String>>) -> f64 {
+        let tags = match tags {
+            Some(tags) => tags,
+            None => return 1.0,
+        };
+        self.tags
+            .iter()
+            .filter(|(tag, _)| match tag.split_once('=') {
+                Some((key, value)) => tags.get(key).map_or(false, |v| v == value),
+                None => tags.contains_key(tag.as_str()),
+            })
+            .map(|(_, multiplier)| multiplier)
+            .product()
+    }
+}
+
+/// Number of buckets in the histogram of a weight distribution.
+const NB_BUCKETS: usize = 10;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Quantile {
+    pub quantile: f64,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+/// Summary of a set of weights, as printed by the importers' dry-run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WeightDistribution {
+    pub count: usize,
+    pub zeros: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub quantiles: Vec<Quantile>,
+    pub histogram: Vec<Bucket>,
+}
+
+impl FromIterator<f64> for WeightDistribution {
+    fn from_iter<I: IntoIterator<Item = f64>>(weights: I) -> Self {
+        let mut weights: Vec<f64> = weights.into_iter().filter(|w| w.is_finite()).collect();
+        if weights.is_empty() {
+            return WeightDistribution::default();
+        }
+        weights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let count = weights.len();
+        let min = weights[0];
+        let max = weights[count - 1];
+
+        let quantiles = [0.1, 0.25, 0.5, 0.75, 0.9, 0.99]
+            .iter()
+            .map(|&quantile| Quantile {
+                quantile,
+                value: weights[((count - 1) as f64 * quantile).round() as usize],
+            })
+            .collect();
+
+        let width = (max - min) / NB_BUCKETS as f64;
+        let mut histogram: Vec<Bucket> = (0..NB_BUCKETS)
+            .map(|i| Bucket {
+                from: min + i as f64 * width,
+                to: min + (i + 1) as f64 * width,
+                count: 0,
+            })
+            .collect();
+        for w in &weights {
+            let idx = if width > 0.0 {
+                (((w - min) / width) as usize).min(NB_BUCKETS - 1)
+            } else {
+                0
+            };
+            histogram[idx].count += 1;
+        }
+
+        WeightDistribution {
+            count,
+            zeros: weights.iter().filter(|&&w| w == 0.0).count(),
+            min,
+            max,
+            mean: weights.iter().sum::<f64>() / count as f64,
+            quantiles,
+            histogram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmogony::ZoneType;
+    use places::poi::PoiType;
+
+    fn city(weight: f64) -> Arc<Admin> {
+        Arc::new(Admin {
+            id: "admin:city".to_string(),
+            level: 8,
+            weight,
+            zone_type: Some(ZoneType::City),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn default_config_should_use_city_weight() {
+        let config = WeightConfig::default();
+        let admins = vec![city(0.4)];
+        let components = Components {
+            admins: &admins,
+            ..Default::default()
+        };
+        assert_eq!(config.compute(&components), Some(0.4));
+        assert_eq!(config.compute(&Components::default()), None);
+    }
+
+    #[test]
+    fn should_combine_components_and_apply_tag_multipliers() {
+        let config: WeightConfig = toml::from_str(
+            r#"
+            combine = "sum"
+            max = 1.0
+            [coefficients]
+              admin = 0.5
+              poi_type = 1.0
+            [poi_types]
+              "poi_type:amenity:hospital" = 0.2
+            [tags]
+              wikidata = 2.0
+              "tourism=museum" = 10.0
+            "#,
+        )
+        .unwrap();
+
+        let poi = Poi {
+            administrative_regions: vec![city(0.2)],
+            poi_type: PoiType {
+                id: "poi_type:amenity:hospital".to_string(),
+                name: "Hôpital".to_string(),
+            },
+            properties: vec![("wikidata".to_string(), "Q42".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let poi = config.apply_to_poi(poi);
+        assert!((poi.weight - 0.6).abs() < 1e-9);
+
+        let mut poi = poi;
+        poi.properties
+            .insert("tourism".to_string(), "museum".to_string());
+        let poi = config.apply_to_poi(poi);
+        assert!((poi.weight - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn should_summarize_weight_distribution() {
+        let distribution: WeightDistribution = vec![0.0, 0.0, 0.5, 1.0].into_iter().collect();
+        assert_eq!(distribution.count, 4);
+        assert_eq!(distribution.zeros, 2);
+        assert_eq!(distribution.histogram.len(), NB_BUCKETS);
+        assert_eq!(distribution.histogram[0].count, 2);
+        assert_eq!(distribution.histogram[NB_BUCKETS - 1].count, 1);
+        assert!((distribution.mean - 0.375).abs() < 1e-9);
+    }
+}
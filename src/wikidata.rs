@@ -0,0 +1,388 @@
+// Copyright © 2016, Hove and/or its affiliates. All rights reserved.
+//
+// This file is part of Navitia,
+//     the software to build cool stuff with public transport.
+//
+// Hope you'll enjoy and contribute to this project,
+//     powered by Hove (www.kisio.com).
+// Help us simplify mobility and open public transport:
+//     a non ending quest to the responsive locomotion way of traveling!
+//
+// LICENCE: This program is free software; you can redistribute it
+// and/or modify it under the terms of the GNU Affero General Public
+// License as published by the Free Software Foundation, either
+// version 3 of the License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful, but
+// WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the GNU
+// Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public
+// License along with this program. If not, see
+// <http://www.gnu.org/licenses/>.
+//
+// Stay tuned using
+// twitter @navitia
+// IRC #navitia on freenode
+// https://groups.google.com/d/forum/navitia
+// www.navitia.io
+
+/// In this module we put the offline enrichment of admins and pois with Wikidata.
+///
+/// Admins (through their 'wikidata' code) and pois (through their 'wikidata' property)
+/// are looked up in a local subset of the Wikidata JSON dump, and we use the matching
+/// entity to add:
+/// * names (and labels) in the configured languages,
+/// * an importance, based on the number of sitelinks, which is blended into the weight,
+/// * an image (pois only, since admins have no properties).
+///
+/// The dump is read line by line, with one entity per line, as in the dumps published by
+/// Wikidata (the surrounding '[', ']' and trailing commas are ignored). It can be gzipped.
+use async_compression::tokio::bufread::GzipDecoder;
+use places::{admin::Admin, i18n_properties::I18nProperties, poi::Poi, Property};
+use serde::{Deserialize, Serialize};
+use snafu::{ResultExt, Snafu};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    ops::Deref,
+    path::PathBuf,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+};
+use tracing::{info, warn};
+
+use crate::labels;
+
+/// Base URL to get a file hosted on Wikimedia Commons
+const COMMONS_FILE_PATH: &str = "https://commons.wikimedia.org/wiki/Special:FilePath/";
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Could not open Wikidata dump {}: {}", path.display(), source))]
+    InvalidPath {
+        path: PathBuf,
+        source: tokio::io::Error,
+    },
+
+    #[snafu(display("IO Error: {}", source))]
+    InvalidIO { source: tokio::io::Error },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WikidataSettings {
+    /// Subset of the Wikidata JSON dump, with one entity per line.
+    pub file: PathBuf,
+    /// Languages of the names taken from Wikidata.
+    #[serde(default = "default_langs")]
+    pub langs: Vec<String>,
+    /// Part of the weight given to the importance computed from sitelinks, in [0, 1].
+    /// 0 leaves the weight untouched, 1 replaces it with the importance.
+    #[serde(default)]
+    pub sitelinks_weight: f64,
+    /// Add the Wikidata image to the properties of pois.
+    #[serde(default)]
+    pub images: bool,
+}
+
+pub fn default_langs() -> Vec<String> {
+    vec!["fr".to_string()]
+}
+
+/// The parts of a Wikidata entity we are interested in.
+#[derive(Debug, Deserialize)]
+struct Entity {
+    id: String,
+    #[serde(default)]
+    labels: BTreeMap<String, MonolingualText>,
+    #[serde(default)]
+    sitelinks: BTreeMap<String, serde::de::IgnoredAny>,
+    #[serde(default)]
+    claims: Claims,
+}
+
+#[derive(Debug, Deserialize)]
+struct MonolingualText {
+    value: String,
+}
+
+// We only keep the images (P18)
+#[derive(Debug, Default, Deserialize)]
+struct Claims {
+    #[serde(rename = "P18", default)]
+    images: Vec<Claim>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claim {
+    mainsnak: Snak,
+}
+
+#[derive(Debug, Deserialize)]
+struct Snak {
+    datavalue: Option<DataValue>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DataValue {
+    value: serde_json::Value,
+}
+
+/// What we keep of a Wikidata entity.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WikidataItem {
+    pub names: BTreeMap<String, String>,
+    pub sitelinks: usize,
+    pub image: Option<String>,
+}
+
+impl WikidataItem {
+    fn from_entity(entity: Entity, langs: &[String]) -> Self {
+        let image = entity
+            .claims
+            .images
+            .into_iter()
+            .filter_map(|claim| claim.mainsnak.datavalue)
+            .find_map(|datavalue| datavalue.value.as_str().map(commons_url));
+        WikidataItem {
+            names: entity
+                .labels
+                .into_iter()
+                .filter(|(lang, _)| langs.contains(lang))
+                .map(|(lang, text)| (lang, text.value))
+                .collect(),
+            sitelinks: entity.sitelinks.len(),
+            image,
+        }
+    }
+}
+
+fn commons_url(file: &str) -> String {
+    format!("{}{}", COMMONS_FILE_PATH, file.replace(' ', "_"))
+}
+
+pub struct Wikidata {
+    items: HashMap<String, WikidataItem>,
+    max_sitelinks: usize,
+    settings: WikidataSettings,
+}
+
+impl Wikidata {
+    /// Read the Wikidata dump given in the settings.
+    pub async fn load(settings: &WikidataSettings) -> Result<Self, Error> {
+        let path = &settings.file;
+        info!("reading wikidata entities from {}", path.display());
+        let file = File::open(path)
+            .await
+            .context(InvalidPathSnafu { path: path.clone() })?;
+        let file = BufReader::new(file);
+        let reader = if path.extension().and_then(OsStr::to_str) == Some("gz") {
+            Box::new(GzipDecoder::new(file)) as Box<dyn AsyncRead + Send + Unpin>
+        } else {
+            Box::new(file) as _
+        };
+
+        let mut items = HashMap::new();
+        let mut nb_invalid = 0;
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next_line().await.context(InvalidIOSnafu)? {
+            let line = line.trim().trim_end_matches(',');
+            if line.is_empty() || line == "[" || line == "]" {
+                continue;
+            }
+            match serde_json::from_str::<Entity>(line) {
+                Ok(entity) => {
+                    let id = entity.id.clone();
+                    items.insert(id, WikidataItem::from_entity(entity, &settings.langs));
+                }
+                Err(err) => {
+                    nb_invalid += 1;
+                    warn!("invalid wikidata entity: {}", err);
+                }
+            }
+        }
+        info!(
+            "{} wikidata entities read, {} invalid",
+            items.len(),
+            nb_invalid
+        );
+        Ok(Wikidata::new(items, settings.clone()))
+    }
+
+    pub fn new(items: HashMap<String, WikidataItem>, settings: WikidataSettings) -> Self {
+        let max_sitelinks = items.values().map(|item| item.sitelinks).max().unwrap_or(0);
+        Wikidata {
+            items,
+            max_sitelinks,
+            settings,
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<&WikidataItem> {
+        self.items.get(id)
+    }
+
+    /// Importance of an item, in [0, 1], based on its number of sitelinks relative to
+    /// the item with the most sitelinks. We use a log to compress the distance between
+    /// the few very famous items and the others.
+    pub fn importance(&self, item: &WikidataItem) -> f64 {
+        if self.max_sitelinks == 0 {
+            return 0.0;
+        }
+        (item.sitelinks as f64).ln_1p() / (self.max_sitelinks as f64).ln_1p()
+    }
+
+    fn blend_weight(&self, weight: f64, item: &WikidataItem) -> f64 {
+        let ratio = self.settings.sitelinks_weight;
+        (1.0 - ratio) * weight + ratio * self.importance(item)
+    }
+
+    pub fn enrich_admin(&self, mut admin: Admin) -> Admin {
+        let item = match admin.codes.get("wikidata").and_then(|id| self.get(id)) {
+            Some(item) => item,
+            None => return admin,
+        };
+
+        // The label of an admin starts with its name, so we build the missing labels
+        // by substituting the name with the one found in Wikidata.
+        let labels = item
+            .names
+            .iter()
+            .filter(|(lang, _)| admin.labels.get(lang).is_none())
+            .filter(|_| admin.label.starts_with(&admin.name))
+            .map(|(lang, name)| (lang.clone(), admin.label.replacen(&admin.name, name, 1)))
+            .collect::<Vec<_>>();
+        add_missing(&mut admin.labels, labels);
+        add_missing(&mut admin.names, item.names.clone());
+
+        admin.weight = self.blend_weight(admin.weight, item);
+        admin
+    }
+
+    pub fn enrich_poi(&self, mut poi: Poi) -> Poi {
+        let item = match poi.properties.get("wikidata").and_then(|id| self.get(id)) {
+            Some(item) => item,
+            None => return poi,
+        };
+
+        add_missing(&mut poi.names, item.names.clone());
+        let langs = item.names.keys().cloned().collect::<Vec<_>>();
+        let labels = labels::format_international_poi_label(
+            &poi.names,
+            &poi.name,
+            &poi.label,
+            poi.administrative_regions.iter().map(|a| a.deref()),
+            &poi.country_codes,
+            &langs,
+        );
+        add_missing(
+            &mut poi.labels,
+            labels.0.into_iter().map(|p| (p.key, p.value)),
+        );
+
+        if self.settings.images {
+            if let Some(image) = &item.image {
+                poi.properties
+                    .entry("image".to_string())
+                    .or_insert_with(|| image.clone());
+            }
+        }
+
+        poi.weight = self.blend_weight(poi.weight, item);
+        poi
+    }
+}
+
+// Add the properties whose key is not already present.
+fn add_missing(
+    properties: &mut I18nProperties,
+    new_properties: impl IntoIterator<Item = (String, String)>,
+) {
+    for (key, value) in new_properties {
+        if properties.get(&key).is_none() {
+            properties.0.push(Property { key, value });
+        }
+    }
+}
+
+/// Read the Wikidata dump if it is configured.
+pub async fn load_if_configured(
+    settings: &Option<WikidataSettings>,
+) -> Result<Option<Wikidata>, Error> {
+    match settings {
+        Some(settings) => Wikidata::load(settings).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Enrich the poi if a Wikidata dump has been loaded.
+pub fn enrich_poi(wikidata: Option<&Wikidata>, poi: Poi) -> Poi {
+    match wikidata {
+        Some(wikidata) => wikidata.enrich_poi(poi),
+        None => poi,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> WikidataSettings {
+        WikidataSettings {
+            file: PathBuf::from("unused"),
+            langs: vec!["fr".to_string(), "de".to_string()],
+            sitelinks_weight: 0.5,
+            images: true,
+        }
+    }
+
+    #[test]
+    fn should_read_entity_from_dump_line() {
+        let line = r#"{"type":"item","id":"Q90","labels":{"fr":{"language":"fr","value":"Paris"},"de":{"language":"de","value":"Paris"},"ru":{"language":"ru","value":"Париж"}},"sitelinks":{"frwiki":{"site":"frwiki","title":"Paris"},"dewiki":{"site":"dewiki","title":"Paris"}},"claims":{"P18":[{"mainsnak":{"snaktype":"value","property":"P18","datavalue":{"value":"Paris Night.jpg","type":"string"}}}]}},"#;
+        let entity: Entity = serde_json::from_str(line.trim_end_matches(',')).unwrap();
+        let item = WikidataItem::from_entity(entity, &settings().langs);
+        assert_eq!(item.names.len(), 2);
+        assert_eq!(item.sitelinks, 2);
+        assert_eq!(
+            item.image.as_deref(),
+            Some("https://commons.wikimedia.org/wiki/Special:FilePath/Paris_Night.jpg")
+        );
+    }
+
+    #[test]
+    fn should_enrich_poi_with_wikidata_item() {
+        let mut items = HashMap::new();
+        items.insert(
+            "Q1".to_string(),
+            WikidataItem {
+                names: vec![("de".to_string(), "Eiffelturm".to_string())]
+                    .into_iter()
+                    .collect(),
+                sitelinks: 10,
+                image: Some("image.jpg".to_string()),
+            },
+        );
+        let wikidata = Wikidata::new(items, settings());
+        let poi = Poi {
+            name: "Tour Eiffel".to_string(),
+            label: "Tour Eiffel".to_string(),
+            weight: 0.2,
+            properties: vec![("wikidata".to_string(), "Q1".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let poi = wikidata.enrich_poi(poi);
+        assert_eq!(poi.names.get("de"), Some("Eiffelturm"));
+        assert_eq!(poi.labels.get("de"), Some("Eiffelturm"));
+        assert_eq!(
+            poi.properties.get("image").map(String::as_str),
+            Some("image.jpg")
+        );
+        // The importance of the item with the most sitelinks is 1.
+        assert!((poi.weight - 0.6).abs() < 1e-9);
+    }
+}
@@ -11,7 +11,10 @@ use crate::{
 };
 use mimir::{
     adapters::{
-        primary::{bragi::handlers::build_es_indices_to_search, common::dsl::build_features_query},
+        primary::{
+            bragi::handlers::build_es_indices_to_search,
+            common::{dsl::build_features_query, fields::FieldSelection},
+        },
         secondary::elasticsearch::ElasticsearchStorageConfig,
     },
     domain::{model::query::Query, ports::primary::get_documents::GetDocuments},
@@ -69,7 +72,11 @@ impl Step for Get {
             .expect("Could not establish connection to Elasticsearch");
 
         // Build ES query
-        let dsl = build_features_query(&self.indexes, &self.id);
+        let dsl = build_features_query(
+            &self.indexes,
+            &self.id,
+            &FieldSelection::default().source(false),
+        );
 
         // Fetch documents
         self.results = {